@@ -6,17 +6,45 @@ use serde::{Deserialize, Serialize};
 pub struct Config {
     pub server: ServerConfig,
     pub database: DatabaseConfig,
+    pub fx: FxConfig,
+    pub network: NetworkConfig,
+    pub idempotency: IdempotencyConfig,
+    pub security: SecurityConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerConfig {
     pub host: String,
     pub port: u16,
+    pub iso8583_port: u16, // ISO 8583 TCP listener for acquirer links
+    pub iso8583_bcd: bool, // Numerics and length prefixes packed as BCD rather than ASCII
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
-    pub url: String,
+    pub url: String, // `memory:` for in-process repositories, else `sqlite::memory:` or `sqlite://<path>`
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FxConfig {
+    pub ecb_rates_file: Option<String>, // ECB reference rates (XML or CSV)
+    pub dcc_markup_bps: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    pub authorization_timeout_ms: u64, // Unanswered authorizations are reversed after this
+    pub reversal_retry_ms: u64,        // Base delay between reversal attempts
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyConfig {
+    pub window_secs: u64, // How long a key's response is kept for replay
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityConfig {
+    pub data_key: Option<String>, // 64 hex digits; encrypts card data at rest, random per run if unset
 }
 
 impl Default for Config {
@@ -25,10 +53,69 @@ impl Default for Config {
             server: ServerConfig {
                 host: "127.0.0.1".to_string(),
                 port: 3000,
+                iso8583_port: 8583,
+                iso8583_bcd: false,
             },
             database: DatabaseConfig {
-                url: "sqlite::memory:".to_string(),
+                url: "memory:".to_string(),
+            },
+            fx: FxConfig {
+                ecb_rates_file: None,
+                dcc_markup_bps: 300,
+            },
+            network: NetworkConfig {
+                authorization_timeout_ms: 30_000,
+                reversal_retry_ms: 10_000,
+            },
+            idempotency: IdempotencyConfig {
+                window_secs: 86_400,
             },
+            security: SecurityConfig {
+                data_key: None,
+            },
+        }
+    }
+}
+
+impl Config {
+    /// The defaults, with `database.url` and `security.data_key` taken from the
+    /// `EUROPAY_DATABASE_URL` and `EUROPAY_DATA_KEY` environment variables when set.
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = Self::default();
+        if let Ok(url) = std::env::var("EUROPAY_DATABASE_URL") {
+            config.database.url = url;
+        }
+        if let Ok(key) = std::env::var("EUROPAY_DATA_KEY") {
+            config.security.data_key = Some(key);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects settings the node cannot run with.
+    pub fn validate(&self) -> Result<(), String> {
+        let on_disk = !matches!(self.database.url.as_str(), "memory:" | "sqlite::memory:");
+        if on_disk && self.security.data_key.is_none() {
+            return Err(format!(
+                "database.url {} stores card data on disk, so security.data_key (EUROPAY_DATA_KEY) must be set to 64 hex digits",
+                self.database.url
+            ));
         }
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_database_requires_a_data_key() {
+        let mut config = Config::default();
+        config.validate().unwrap();
+        config.database.url = "sqlite://europay.db".to_string();
+        assert!(config.validate().unwrap_err().contains("EUROPAY_DATA_KEY"));
+        config.security.data_key = Some("07".repeat(32));
+        config.validate().unwrap();
+    }
+}
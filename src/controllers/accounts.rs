@@ -0,0 +1,180 @@
+// Account controllers
+
+use axum::{extract::{rejection::{JsonRejection, QueryRejection}, Path, Query, State}, http::StatusCode, response::Json as JsonResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::core::currency::Currency;
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
+use crate::models::accounts::{Account, AccountStatus};
+use crate::models::transactions::PaymentProcessor;
+use crate::utils::{paginate, validate_name, Page};
+
+#[derive(Serialize)]
+pub struct AccountResponse {
+    pub id: Uuid,
+    pub holder_name: String,
+    pub currency: Currency,
+    pub ledger_balance: Money,
+    pub available_balance: Money, // Ledger balance less funds held for authorizations
+    pub status: AccountStatus,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AccountResponse {
+    fn from_account(account: &Account) -> Result<Self, EuropayError> {
+        Ok(Self {
+            id: account.id,
+            holder_name: account.holder_name.clone(),
+            currency: account.currency,
+            ledger_balance: account.ledger_balance,
+            available_balance: account.available_balance()?,
+            status: account.status.clone(),
+            created_at: account.created_at,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateAccountRequest {
+    pub holder_name: String,
+    pub currency: Currency,
+}
+
+#[derive(Deserialize)]
+pub struct AccountStatusRequest {
+    pub status: AccountStatus,
+}
+
+#[derive(Deserialize)]
+pub struct DepositRequest {
+    #[serde(flatten)]
+    pub amount: Money,
+}
+
+#[derive(Deserialize)]
+pub struct AccountFilter {
+    pub status: Option<AccountStatus>,
+    pub currency: Option<Currency>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn create_account(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    payload: Result<Json<CreateAccountRequest>, JsonRejection>,
+) -> Result<(StatusCode, JsonResponse<AccountResponse>), EuropayError> {
+    let Json(payload) = payload?;
+    let holder_name = validate_name(&payload.holder_name, 140)?;
+    if !payload.currency.is_active_on(Utc::now().date_naive()) {
+        return Err(EuropayError::CurrencyUnavailable("Currency withdrawn".to_string()));
+    }
+    let account = Account::new(holder_name, payload.currency);
+    let response = AccountResponse::from_account(&account)?;
+    processor.lock().await.add_account(account)?;
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn update_account_status(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(account_id): Path<Uuid>,
+    payload: Result<Json<AccountStatusRequest>, JsonRejection>,
+) -> Result<JsonResponse<AccountResponse>, EuropayError> {
+    let Json(payload) = payload?;
+    let mut proc = processor.lock().await;
+    proc.update_account_status(account_id, payload.status)?;
+    let account = proc.get_account(account_id).ok_or(EuropayError::AccountNotFound)?;
+    Ok(Json(AccountResponse::from_account(account)?))
+}
+
+/// Funds the account from the issuer's settlement account.
+pub async fn deposit(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(account_id): Path<Uuid>,
+    payload: Result<Json<DepositRequest>, JsonRejection>,
+) -> Result<JsonResponse<AccountResponse>, EuropayError> {
+    let Json(payload) = payload?;
+    let mut proc = processor.lock().await;
+    proc.deposit(account_id, payload.amount)?;
+    let account = proc.get_account(account_id).ok_or(EuropayError::AccountNotFound)?;
+    Ok(Json(AccountResponse::from_account(account)?))
+}
+
+pub async fn get_account(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(account_id): Path<Uuid>,
+) -> Result<JsonResponse<AccountResponse>, EuropayError> {
+    let proc = processor.lock().await;
+    let account = proc.get_account(account_id).ok_or(EuropayError::AccountNotFound)?;
+    Ok(Json(AccountResponse::from_account(account)?))
+}
+
+pub async fn list_accounts(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    filter: Result<Query<AccountFilter>, QueryRejection>,
+) -> Result<JsonResponse<Page<AccountResponse>>, EuropayError> {
+    let Query(filter) = filter?;
+    let proc = processor.lock().await;
+    let accounts = proc.accounts()
+        .filter(|a| filter.status.as_ref().is_none_or(|status| &a.status == status))
+        .filter(|a| filter.currency.is_none_or(|currency| a.currency == currency))
+        .collect();
+    let page = paginate(accounts, |a| (a.created_at, a.id), filter.cursor.as_deref(), filter.limit)?;
+    let items = page.items.into_iter().map(AccountResponse::from_account).collect::<Result<_, _>>()?;
+    Ok(Json(Page { items, next_cursor: page.next_cursor }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+    use crate::controllers::cards::{issue_card, IssueCardRequest};
+    use crate::controllers::merchants::{create_merchant, CreateMerchantRequest};
+    use crate::models::transactions::fixtures;
+
+    #[tokio::test]
+    async fn test_onboarded_account_authorizes_until_frozen() {
+        let processor = Arc::new(Mutex::new(fixtures::processor()));
+        let eur = |amount: &str| Money::parse(amount, Currency::EUR).unwrap();
+
+        let request = CreateAccountRequest { holder_name: " Lena Vogel ".to_string(), currency: Currency::EUR };
+        let (status, Json(account)) = create_account(State(processor.clone()), Ok(Json(request))).await.unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(account.holder_name, "Lena Vogel");
+        let Json(funded) = deposit(State(processor.clone()), Path(account.id), Ok(Json(DepositRequest { amount: eur("50.00") }))).await.unwrap();
+        assert_eq!(funded.available_balance, eur("50.00"));
+
+        let request = |pan: &str| IssueCardRequest {
+            account_id: account.id,
+            pan: pan.to_string(),
+            expiry_month: 12,
+            expiry_year: Utc::now().year() as u16 + 3,
+            cvv: "123".to_string(),
+            cardholder_name: "Lena Vogel".to_string(),
+        };
+        let invalid = issue_card(State(processor.clone()), Ok(Json(request("4000000000000003")))).await.err().unwrap();
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+        let (_, Json(card)) = issue_card(State(processor.clone()), Ok(Json(request("4000000000000002")))).await.unwrap();
+        assert_eq!(card.masked_pan, "400000******0002");
+        assert_eq!(card.cardholder_name, "LENA VOGEL");
+
+        let request = CreateMerchantRequest { name: "Buchhandlung".to_string(), category: "5942".to_string(), acquirer_id: Uuid::new_v4() };
+        let (_, Json(merchant)) = create_merchant(State(processor.clone()), Ok(Json(request))).await.unwrap();
+
+        assert!(processor.lock().await.authorize_transaction(card.id, merchant.id, eur("20.00")).is_ok());
+
+        let frozen = AccountStatusRequest { status: AccountStatus::Frozen };
+        let Json(account) = update_account_status(State(processor.clone()), Path(account.id), Ok(Json(frozen))).await.unwrap();
+        assert_eq!(account.status, AccountStatus::Frozen);
+        let declined = processor.lock().await.authorize_transaction(card.id, merchant.id, eur("20.00")).err();
+        assert_eq!(declined, Some(EuropayError::AccountNotActive));
+
+        let closed = AccountStatusRequest { status: AccountStatus::Closed };
+        let refused = update_account_status(State(processor), Path(account.id), Ok(Json(closed))).await.err().unwrap();
+        assert_eq!(refused.status(), StatusCode::CONFLICT);
+    }
+}
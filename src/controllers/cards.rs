@@ -0,0 +1,115 @@
+// Card controllers
+
+use axum::{extract::{rejection::{JsonRejection, QueryRejection}, Path, Query, State}, http::StatusCode, response::Json as JsonResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::core::error::EuropayError;
+use crate::models::cards::{CardStatus, PaymentCard};
+use crate::models::transactions::PaymentProcessor;
+use crate::utils::{paginate, validate_cardholder_name, validate_cvv, validate_expiry, validate_pan, Page};
+
+/// A card as returned by the API. The PAN is masked and the CVV never leaves the processor.
+#[derive(Serialize)]
+pub struct CardResponse {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub masked_pan: String,
+    pub expiry_month: u8,
+    pub expiry_year: u16,
+    pub cardholder_name: String,
+    pub status: CardStatus,
+    pub issued_at: DateTime<Utc>,
+}
+
+impl From<&PaymentCard> for CardResponse {
+    fn from(card: &PaymentCard) -> Self {
+        Self {
+            id: card.id,
+            account_id: card.account_id,
+            masked_pan: card.masked_pan(),
+            expiry_month: card.expiry_month,
+            expiry_year: card.expiry_year,
+            cardholder_name: card.cardholder_name.clone(),
+            status: card.status.clone(),
+            issued_at: card.issued_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct IssueCardRequest {
+    pub account_id: Uuid,
+    pub pan: String,
+    pub expiry_month: u8,
+    pub expiry_year: u16,
+    pub cvv: String,
+    pub cardholder_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct CardStatusRequest {
+    pub status: CardStatus,
+}
+
+#[derive(Deserialize)]
+pub struct CardFilter {
+    pub account_id: Option<Uuid>,
+    pub status: Option<CardStatus>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn issue_card(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    payload: Result<Json<IssueCardRequest>, JsonRejection>,
+) -> Result<(StatusCode, JsonResponse<CardResponse>), EuropayError> {
+    let Json(payload) = payload?;
+    validate_pan(&payload.pan)?;
+    validate_cvv(&payload.cvv)?;
+    validate_expiry(payload.expiry_month, payload.expiry_year, Utc::now())?;
+    let cardholder_name = validate_cardholder_name(&payload.cardholder_name)?;
+    let card = PaymentCard::new(payload.account_id, payload.pan, payload.expiry_month, payload.expiry_year, payload.cvv, cardholder_name);
+    let response = CardResponse::from(&card);
+    processor.lock().await.issue_card(card)?;
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+/// Blocks or unblocks a card.
+pub async fn update_card_status(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(card_id): Path<Uuid>,
+    payload: Result<Json<CardStatusRequest>, JsonRejection>,
+) -> Result<JsonResponse<CardResponse>, EuropayError> {
+    let Json(payload) = payload?;
+    let mut proc = processor.lock().await;
+    proc.update_card_status(card_id, payload.status)?;
+    let card = proc.get_card(card_id).ok_or(EuropayError::CardNotFound)?;
+    Ok(Json(CardResponse::from(card)))
+}
+
+pub async fn get_card(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(card_id): Path<Uuid>,
+) -> Result<JsonResponse<CardResponse>, EuropayError> {
+    let proc = processor.lock().await;
+    let card = proc.get_card(card_id).ok_or(EuropayError::CardNotFound)?;
+    Ok(Json(CardResponse::from(card)))
+}
+
+pub async fn list_cards(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    filter: Result<Query<CardFilter>, QueryRejection>,
+) -> Result<JsonResponse<Page<CardResponse>>, EuropayError> {
+    let Query(filter) = filter?;
+    let proc = processor.lock().await;
+    let cards = proc.cards()
+        .filter(|c| filter.account_id.is_none_or(|id| c.account_id == id))
+        .filter(|c| filter.status.as_ref().is_none_or(|status| &c.status == status))
+        .collect();
+    let page = paginate(cards, |c| (c.issued_at, c.id), filter.cursor.as_deref(), filter.limit)?;
+    Ok(Json(page.map(CardResponse::from)))
+}
@@ -0,0 +1,187 @@
+// Dispute controllers
+
+use axum::{extract::{rejection::{JsonRejection, QueryRejection}, Json, Path, Query, State}, http::StatusCode, response::Json as JsonResponse};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::core::error::EuropayError;
+use crate::models::transactions::PaymentProcessor;
+use crate::services::disputes::{ChargebackRequest, Dispute, DisputeParty, DisputeService, DisputeStage};
+use crate::services::settlement::SettlementService;
+use crate::utils::{paginate, Page};
+
+#[derive(Clone)]
+pub struct DisputeState {
+    pub processor: Arc<Mutex<PaymentProcessor>>,
+    pub disputes: Arc<Mutex<DisputeService>>,
+    pub settlement: Arc<Mutex<SettlementService>>,
+}
+
+#[derive(Serialize)]
+pub struct ChargebackResponse {
+    pub dispute_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct EvidenceRequest {
+    pub dispute_id: Uuid,
+    pub evidence: String,
+}
+
+#[derive(Deserialize)]
+pub struct AcceptRequest {
+    pub dispute_id: Uuid,
+    pub party: DisputeParty, // The party conceding
+}
+
+#[derive(Deserialize)]
+pub struct RulingRequest {
+    pub dispute_id: Uuid,
+    pub winner: DisputeParty,
+}
+
+#[derive(Deserialize)]
+pub struct DisputeFilter {
+    pub transaction_id: Option<Uuid>,
+    pub stage: Option<DisputeStage>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn raise_chargeback(
+    State(state): State<DisputeState>,
+    payload: Result<Json<ChargebackRequest>, JsonRejection>,
+) -> Result<JsonResponse<ChargebackResponse>, EuropayError> {
+    let Json(payload) = payload?;
+    let mut processor = state.processor.lock().await;
+    let mut disputes = state.disputes.lock().await;
+    let mut settlement = state.settlement.lock().await;
+    let dispute_id = disputes.open_chargeback(&mut processor, payload, &mut settlement, Utc::now())?;
+    Ok(Json(ChargebackResponse { dispute_id }))
+}
+
+pub async fn submit_representment(
+    State(state): State<DisputeState>,
+    payload: Result<Json<EvidenceRequest>, JsonRejection>,
+) -> Result<StatusCode, EuropayError> {
+    let Json(payload) = payload?;
+    let mut processor = state.processor.lock().await;
+    let mut disputes = state.disputes.lock().await;
+    let mut settlement = state.settlement.lock().await;
+    disputes.represent(&mut processor, payload.dispute_id, payload.evidence, &mut settlement, Utc::now())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn submit_pre_arbitration(
+    State(state): State<DisputeState>,
+    payload: Result<Json<EvidenceRequest>, JsonRejection>,
+) -> Result<StatusCode, EuropayError> {
+    let Json(payload) = payload?;
+    let mut disputes = state.disputes.lock().await;
+    disputes.pre_arbitrate(payload.dispute_id, payload.evidence, Utc::now())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn request_arbitration(
+    State(state): State<DisputeState>,
+    payload: Result<Json<EvidenceRequest>, JsonRejection>,
+) -> Result<StatusCode, EuropayError> {
+    let Json(payload) = payload?;
+    let mut disputes = state.disputes.lock().await;
+    disputes.escalate_to_arbitration(payload.dispute_id, payload.evidence, Utc::now())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn accept_dispute(
+    State(state): State<DisputeState>,
+    payload: Result<Json<AcceptRequest>, JsonRejection>,
+) -> Result<StatusCode, EuropayError> {
+    let Json(payload) = payload?;
+    let mut processor = state.processor.lock().await;
+    let mut disputes = state.disputes.lock().await;
+    let mut settlement = state.settlement.lock().await;
+    disputes.accept(&mut processor, payload.dispute_id, payload.party, &mut settlement, Utc::now())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn record_ruling(
+    State(state): State<DisputeState>,
+    payload: Result<Json<RulingRequest>, JsonRejection>,
+) -> Result<StatusCode, EuropayError> {
+    let Json(payload) = payload?;
+    let mut processor = state.processor.lock().await;
+    let mut disputes = state.disputes.lock().await;
+    let mut settlement = state.settlement.lock().await;
+    disputes.rule(&mut processor, payload.dispute_id, payload.winner, &mut settlement, Utc::now())?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn get_dispute(
+    State(state): State<DisputeState>,
+    Path(dispute_id): Path<Uuid>,
+) -> Result<JsonResponse<Dispute>, EuropayError> {
+    let disputes = state.disputes.lock().await;
+    let dispute = disputes.get_dispute(dispute_id).ok_or(EuropayError::DisputeNotFound)?;
+    Ok(Json(dispute.clone()))
+}
+
+pub async fn list_disputes(
+    State(state): State<DisputeState>,
+    filter: Result<Query<DisputeFilter>, QueryRejection>,
+) -> Result<JsonResponse<Page<Dispute>>, EuropayError> {
+    let Query(filter) = filter?;
+    let disputes = state.disputes.lock().await;
+    let disputes = disputes.disputes()
+        .filter(|d| filter.transaction_id.is_none_or(|id| d.transaction_id == id))
+        .filter(|d| filter.stage.is_none_or(|stage| d.stage == stage))
+        .cloned()
+        .collect();
+    Ok(Json(paginate(disputes, |d| (d.opened_at, d.id), filter.cursor.as_deref(), filter.limit)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::Currency;
+    use crate::core::money::Money;
+    use crate::models::transactions::fixtures;
+    use crate::services::disputes::DisputeDeadlines;
+
+    #[tokio::test]
+    async fn test_list_disputes_filters_and_pages() {
+        let mut processor = fixtures::processor();
+        let fixtures::Cardholder { card_id, merchant_id, .. } = fixtures::add_cardholder(&mut processor, Currency::EUR, "100.00");
+        let eur = |amount: &str| Money::parse(amount, Currency::EUR).unwrap();
+        let mut disputes = DisputeService::new(DisputeDeadlines::default());
+        let mut settlement = SettlementService::new();
+        let mut ids = Vec::new();
+        let now = Utc::now();
+        for opened_after in 0..2 {
+            let tx_id = processor.authorize_transaction(card_id, merchant_id, eur("10.00")).unwrap();
+            processor.capture_transaction(tx_id).unwrap();
+            processor.settle_transaction(tx_id).unwrap();
+            let request = ChargebackRequest { transaction_id: tx_id, issuer_id: Uuid::new_v4(), reason_code: "4837".to_string(), amount: eur("10.00") };
+            ids.push((tx_id, disputes.open_chargeback(&mut processor, request, &mut settlement, now + chrono::Duration::seconds(opened_after)).unwrap()));
+        }
+        let state = DisputeState {
+            processor: Arc::new(Mutex::new(processor)),
+            disputes: Arc::new(Mutex::new(disputes)),
+            settlement: Arc::new(Mutex::new(settlement)),
+        };
+        let filter = |transaction_id: Option<Uuid>, cursor: Option<String>| Ok(Query(DisputeFilter { transaction_id, stage: None, cursor, limit: Some(1) }));
+
+        let Json(first) = list_disputes(State(state.clone()), filter(None, None)).await.unwrap();
+        assert_eq!(first.items.iter().map(|d| d.id).collect::<Vec<_>>(), vec![ids[0].1]);
+        let Json(second) = list_disputes(State(state.clone()), filter(None, first.next_cursor)).await.unwrap();
+        assert_eq!(second.items.iter().map(|d| d.id).collect::<Vec<_>>(), vec![ids[1].1]);
+        assert!(second.next_cursor.is_none());
+
+        let Json(filtered) = list_disputes(State(state.clone()), filter(Some(ids[1].0), None)).await.unwrap();
+        assert_eq!(filtered.items.iter().map(|d| d.id).collect::<Vec<_>>(), vec![ids[1].1]);
+        let Json(dispute) = get_dispute(State(state), Path(ids[0].1)).await.unwrap();
+        assert_eq!(dispute.transaction_id, ids[0].0);
+    }
+}
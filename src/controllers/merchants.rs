@@ -0,0 +1,81 @@
+// Merchant controllers
+
+use axum::{extract::{rejection::{JsonRejection, QueryRejection}, Path, Query, State}, http::StatusCode, response::Json as JsonResponse, Json};
+use serde::Deserialize;
+use uuid::Uuid;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::core::error::EuropayError;
+use crate::models::merchants::{Merchant, MerchantStatus};
+use crate::models::transactions::PaymentProcessor;
+use crate::utils::{paginate, validate_mcc, validate_name, Page};
+
+#[derive(Deserialize)]
+pub struct CreateMerchantRequest {
+    pub name: String,
+    pub category: String, // Merchant category code
+    pub acquirer_id: Uuid,
+}
+
+#[derive(Deserialize)]
+pub struct MerchantStatusRequest {
+    pub status: MerchantStatus,
+}
+
+#[derive(Deserialize)]
+pub struct MerchantFilter {
+    pub acquirer_id: Option<Uuid>,
+    pub category: Option<String>,
+    pub status: Option<MerchantStatus>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn create_merchant(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    payload: Result<Json<CreateMerchantRequest>, JsonRejection>,
+) -> Result<(StatusCode, JsonResponse<Merchant>), EuropayError> {
+    let Json(payload) = payload?;
+    let name = validate_name(&payload.name, 140)?;
+    validate_mcc(&payload.category)?;
+    let merchant = Merchant::new(name, payload.category, payload.acquirer_id);
+    processor.lock().await.add_merchant(merchant.clone())?;
+    Ok((StatusCode::CREATED, Json(merchant)))
+}
+
+pub async fn update_merchant_status(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(merchant_id): Path<Uuid>,
+    payload: Result<Json<MerchantStatusRequest>, JsonRejection>,
+) -> Result<JsonResponse<Merchant>, EuropayError> {
+    let Json(payload) = payload?;
+    let mut proc = processor.lock().await;
+    proc.update_merchant_status(merchant_id, payload.status)?;
+    let merchant = proc.get_merchant(merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+    Ok(Json(merchant.clone()))
+}
+
+pub async fn get_merchant(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(merchant_id): Path<Uuid>,
+) -> Result<JsonResponse<Merchant>, EuropayError> {
+    let proc = processor.lock().await;
+    let merchant = proc.get_merchant(merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+    Ok(Json(merchant.clone()))
+}
+
+pub async fn list_merchants(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    filter: Result<Query<MerchantFilter>, QueryRejection>,
+) -> Result<JsonResponse<Page<Merchant>>, EuropayError> {
+    let Query(filter) = filter?;
+    let proc = processor.lock().await;
+    let merchants = proc.merchants()
+        .filter(|m| filter.acquirer_id.is_none_or(|id| m.acquirer_id == id))
+        .filter(|m| filter.category.as_ref().is_none_or(|category| &m.category == category))
+        .filter(|m| filter.status.as_ref().is_none_or(|status| &m.status == status))
+        .cloned()
+        .collect();
+    Ok(Json(paginate(merchants, |m| (m.registered_at, m.id), filter.cursor.as_deref(), filter.limit)?))
+}
@@ -1,9 +1,13 @@
 // Network controllers
 
-use axum::{extract::Json, http::StatusCode, response::Json as JsonResponse};
+use axum::{extract::{rejection::JsonRejection, Json, State}, http::StatusCode, response::Json as JsonResponse};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
 
-use crate::core::network::NetworkMessage;
+use crate::core::error::EuropayError;
+use crate::core::network::{NetworkMessage, NetworkNode, NodeRole, TransactionRequest, TransactionResponse};
+use crate::services::network::HttpNetworkService;
 
 #[derive(Deserialize)]
 pub struct NetworkMessageRequest {
@@ -15,9 +19,28 @@ pub struct NetworkMessageResponse {
     pub response: NetworkMessage,
 }
 
+#[derive(Deserialize)]
+pub struct RegisterNodeRequest {
+    pub address: String, // Base URL of the node's HTTP API
+    pub role: NodeRole,
+}
+
+#[derive(Serialize)]
+pub struct RegisterNodeResponse {
+    pub node_id: Uuid,
+}
+
+/// An authorization for a card issued on another node.
+#[derive(Deserialize)]
+pub struct ForwardAuthorizationRequest {
+    pub issuer_node_id: Uuid,
+    pub request: TransactionRequest,
+}
+
 pub async fn handle_network_message(
-    Json(payload): Json<NetworkMessageRequest>,
-) -> Result<JsonResponse<NetworkMessageResponse>, StatusCode> {
+    payload: Result<Json<NetworkMessageRequest>, JsonRejection>,
+) -> Result<JsonResponse<NetworkMessageResponse>, EuropayError> {
+    let Json(payload) = payload?;
     // For now, just echo the message back
     // In real implementation, process the message
     let response = match payload.message {
@@ -28,8 +51,101 @@ pub async fn handle_network_message(
                 .unwrap()
                 .as_secs(),
         }),
+        NetworkMessage::ReversalRequest(reversal) => NetworkMessage::ReversalResponse(crate::core::network::ReversalResponse {
+            transaction_id: reversal.transaction_id,
+            acknowledged: true,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+        }),
         _ => payload.message, // Echo for other messages
     };
 
     Ok(Json(NetworkMessageResponse { response }))
-}
\ No newline at end of file
+}
+
+pub async fn register_node(
+    State(network): State<Arc<HttpNetworkService>>,
+    payload: Result<Json<RegisterNodeRequest>, JsonRejection>,
+) -> Result<(StatusCode, JsonResponse<RegisterNodeResponse>), EuropayError> {
+    let Json(payload) = payload?;
+    if !(payload.address.starts_with("http://") || payload.address.starts_with("https://")) {
+        return Err(EuropayError::InvalidRequest("Node address must be an http(s) URL".to_string()));
+    }
+    let node = NetworkNode::new(payload.address.trim_end_matches('/').to_string(), payload.role);
+    let node_id = node.id;
+    network.register_node(node).await;
+    Ok((StatusCode::CREATED, Json(RegisterNodeResponse { node_id })))
+}
+
+/// Sends the authorization to the card's issuing node. An unanswered one is reversed later
+/// by the reversal worker, as the issuer may have approved it.
+pub async fn forward_authorization(
+    State(network): State<Arc<HttpNetworkService>>,
+    payload: Result<Json<ForwardAuthorizationRequest>, JsonRejection>,
+) -> Result<JsonResponse<TransactionResponse>, EuropayError> {
+    let Json(payload) = payload?;
+    let issuer = network.get_node(&payload.issuer_node_id).await
+        .ok_or_else(|| EuropayError::InvalidRequest("Unknown issuer node".to_string()))?;
+    if issuer.role != NodeRole::Issuer {
+        return Err(EuropayError::InvalidRequest("Node is not an issuer".to_string()));
+    }
+    let response = network.authorize(&issuer, payload.request).await.map_err(EuropayError::IssuerUnavailable)?;
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use crate::core::currency::Currency;
+    use crate::core::money::Money;
+    use crate::core::network::MessageClass;
+
+    #[tokio::test]
+    async fn test_unanswered_forwarded_authorization_is_queued_for_reversal() {
+        // An issuer that accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+        let network = Arc::new(HttpNetworkService::new(Duration::from_millis(50), Duration::from_secs(1)));
+        let register = |role: NodeRole| Ok(Json(RegisterNodeRequest { address: address.clone(), role }));
+        let (_, Json(issuer)) = register_node(State(network.clone()), register(NodeRole::Issuer)).await.unwrap();
+        let (_, Json(acquirer)) = register_node(State(network.clone()), register(NodeRole::Acquirer)).await.unwrap();
+
+        let request = TransactionRequest {
+            transaction_id: Uuid::new_v4(),
+            message_class: MessageClass::Authorization,
+            card_id: Uuid::new_v4(),
+            merchant_id: Uuid::new_v4(),
+            amount: Money::parse("25.00", Currency::EUR).unwrap(),
+            timestamp: Utc::now().timestamp() as u64,
+            pan: "4000000000000002".to_string(),
+            processing_code: "000000".to_string(),
+            stan: "000001".to_string(),
+            retrieval_reference: "000000000001".to_string(),
+            terminal_id: "TERM0001".to_string(),
+            card_acceptor_id: "MERCHANT0000001".to_string(),
+        };
+        let forward = |issuer_node_id: Uuid| Ok(Json(ForwardAuthorizationRequest { issuer_node_id, request: request.clone() }));
+
+        let error = forward_authorization(State(network.clone()), forward(acquirer.node_id)).await.unwrap_err();
+        assert_eq!(error, EuropayError::InvalidRequest("Node is not an issuer".to_string()));
+        assert_eq!(network.reversals().lock().await.pending().count(), 0);
+
+        let error = forward_authorization(State(network.clone()), forward(issuer.node_id)).await.unwrap_err();
+        assert_eq!((error.code(), error.response_code()), ("issuer_unavailable", "91"));
+        let reversals = network.reversals();
+        let queue = reversals.lock().await;
+        assert_eq!(queue.in_flight().count(), 0);
+        assert_eq!(queue.pending().next().map(|r| r.request.transaction_id), Some(request.transaction_id));
+    }
+}
@@ -1,18 +1,27 @@
 // Settlement controllers
 
-use axum::{extract::{Json, State}, http::StatusCode, response::Json as JsonResponse};
+use axum::{extract::{rejection::{JsonRejection, QueryRejection}, Json, Path, Query, State}, http::StatusCode, response::Json as JsonResponse};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::services::settlement::SettlementService;
+use crate::core::error::EuropayError;
+use crate::models::transactions::PaymentProcessor;
+use crate::services::settlement::{SettlementAdjustment, SettlementBatch, SettlementService, SettlementStatus};
+use crate::utils::{paginate, Page};
+
+#[derive(Clone)]
+pub struct SettlementState {
+    pub processor: Arc<Mutex<PaymentProcessor>>,
+    pub settlement: Arc<Mutex<SettlementService>>,
+}
 
 #[derive(Deserialize)]
 pub struct CreateBatchRequest {
     pub issuer_id: Uuid,
     pub acquirer_id: Uuid,
-    pub transaction_ids: Vec<Uuid>,
+    pub transaction_ids: Vec<Uuid>, // Captured purchases and refunds
 }
 
 #[derive(Serialize)]
@@ -25,24 +34,64 @@ pub struct ProcessSettlementRequest {
     pub batch_id: Uuid,
 }
 
-pub async fn create_settlement_batch(
-    State(settlement_service): State<Arc<Mutex<SettlementService>>>,
-    Json(payload): Json<CreateBatchRequest>,
-) -> Result<JsonResponse<CreateBatchResponse>, StatusCode> {
-    // For now, create empty batch (in real system, fetch transactions)
-    let mut service = settlement_service.lock().await;
-    let batch_id = service.create_batch(payload.issuer_id, payload.acquirer_id, vec![]);
+#[derive(Deserialize)]
+pub struct BatchFilter {
+    pub issuer_id: Option<Uuid>,
+    pub acquirer_id: Option<Uuid>,
+    pub status: Option<SettlementStatus>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
 
+pub async fn create_settlement_batch(
+    State(state): State<SettlementState>,
+    payload: Result<Json<CreateBatchRequest>, JsonRejection>,
+) -> Result<JsonResponse<CreateBatchResponse>, EuropayError> {
+    let Json(payload) = payload?;
+    let processor = state.processor.lock().await;
+    let mut service = state.settlement.lock().await;
+    let batch_id = service.create_batch(payload.issuer_id, payload.acquirer_id, &payload.transaction_ids, &processor)?;
     Ok(Json(CreateBatchResponse { batch_id }))
 }
 
 pub async fn process_settlement(
-    State(settlement_service): State<Arc<Mutex<SettlementService>>>,
-    Json(payload): Json<ProcessSettlementRequest>,
-) -> Result<StatusCode, StatusCode> {
-    let mut service = settlement_service.lock().await;
-    match service.process_settlement(payload.batch_id) {
-        Ok(_) => Ok(StatusCode::OK),
-        Err(_) => Err(StatusCode::BAD_REQUEST),
-    }
+    State(state): State<SettlementState>,
+    payload: Result<Json<ProcessSettlementRequest>, JsonRejection>,
+) -> Result<StatusCode, EuropayError> {
+    let Json(payload) = payload?;
+    let mut processor = state.processor.lock().await;
+    let mut service = state.settlement.lock().await;
+    service.process_settlement(payload.batch_id, &mut processor)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn get_batch(
+    State(state): State<SettlementState>,
+    Path(batch_id): Path<Uuid>,
+) -> Result<JsonResponse<SettlementBatch>, EuropayError> {
+    let service = state.settlement.lock().await;
+    let batch = service.get_batch(&batch_id).ok_or(EuropayError::BatchNotFound)?;
+    Ok(Json(batch.clone()))
+}
+
+pub async fn list_batches(
+    State(state): State<SettlementState>,
+    filter: Result<Query<BatchFilter>, QueryRejection>,
+) -> Result<JsonResponse<Page<SettlementBatch>>, EuropayError> {
+    let Query(filter) = filter?;
+    let service = state.settlement.lock().await;
+    let batches = service.batches()
+        .filter(|b| filter.issuer_id.is_none_or(|id| b.issuer_id == id))
+        .filter(|b| filter.acquirer_id.is_none_or(|id| b.acquirer_id == id))
+        .filter(|b| filter.status.as_ref().is_none_or(|status| &b.status == status))
+        .cloned()
+        .collect();
+    Ok(Json(paginate(batches, |b| (b.created_at, b.id), filter.cursor.as_deref(), filter.limit)?))
+}
+
+pub async fn list_pending_adjustments(
+    State(state): State<SettlementState>,
+) -> Result<JsonResponse<Vec<SettlementAdjustment>>, EuropayError> {
+    let service = state.settlement.lock().await;
+    Ok(Json(service.pending_adjustments().to_vec()))
 }
\ No newline at end of file
@@ -1,25 +1,118 @@
 // Transaction controllers
 
-use axum::{extract::{Json, State}, http::StatusCode, response::Json as JsonResponse};
+use axum::{extract::{rejection::{JsonRejection, QueryRejection}, Json, Path, Query, State}, http::{header, HeaderMap, StatusCode}, response::Json as JsonResponse};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::models::transactions::PaymentProcessor;
-use crate::core::currency::Currency;
+use crate::models::lifecycle::TransitionEvent;
+use crate::models::transactions::{AuthorizationDecision, Capture, PaymentProcessor, Transaction, TransactionStatus};
+use crate::services::messaging::RESPONSE_APPROVED;
+use crate::core::currency::{Currency, DccQuote};
+use crate::core::error::EuropayError;
+use crate::core::locale::Locale;
+use crate::core::money::Money;
+use crate::utils::{paginate, Page};
 
 #[derive(Deserialize)]
 pub struct AuthorizeRequest {
     pub card_id: Uuid,
     pub merchant_id: Uuid,
-    pub amount: f64,
-    pub currency: Currency,
+    #[serde(flatten)]
+    pub amount: Money,
 }
 
+/// The issuer's decision. Declines are answered with 200 and a reason; only invalid
+/// requests get a 4xx.
 #[derive(Serialize)]
 pub struct AuthorizeResponse {
     pub transaction_id: Uuid,
+    pub approved: bool,
+    pub response_code: String, // ISO 8583 field 39
+    pub approval_code: Option<String>,
+    pub decline_reason: Option<String>, // Stable error code, e.g. "insufficient_funds"
+    pub billing_amount: Option<Money>,
+    pub display_amount: Option<String>, // Billing amount for the receipt, in the Accept-Language locale
+    pub dcc: Option<DccQuote>,
+}
+
+impl AuthorizeResponse {
+    fn approved(transaction: &Transaction, locale: Option<Locale>) -> Self {
+        Self {
+            transaction_id: transaction.id,
+            approved: true,
+            response_code: RESPONSE_APPROVED.to_string(),
+            approval_code: transaction.approval_code.clone(),
+            decline_reason: None,
+            billing_amount: Some(transaction.billing_amount),
+            display_amount: locale.map(|locale| locale.format(&transaction.billing_amount)),
+            dcc: transaction.dcc.clone(),
+        }
+    }
+
+    fn declined(transaction_id: Uuid, error: &EuropayError) -> Self {
+        Self {
+            transaction_id,
+            approved: false,
+            response_code: error.response_code().to_string(),
+            approval_code: None,
+            decline_reason: Some(error.code().to_string()),
+            billing_amount: None,
+            display_amount: None,
+            dcc: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RefundRequest {
+    pub transaction_id: Uuid, // The purchase being refunded
+    #[serde(flatten)]
+    pub amount: Money,
+}
+
+#[derive(Serialize)]
+pub struct RefundResponse {
+    pub refund_id: Uuid,
+    pub original_transaction_id: Uuid,
+    pub billing_amount: Money,
+    pub refunded_amount: Money, // Total refunded on the original so far
+}
+
+#[derive(Deserialize)]
+pub struct IncrementRequest {
+    pub transaction_id: Uuid,
+    #[serde(flatten)]
+    pub amount: Money, // Added to the authorized amount
+}
+
+#[derive(Deserialize)]
+pub struct CaptureRequest {
+    pub transaction_id: Uuid,
+    pub amount: Option<String>, // Decimal string; omitted with `currency` to capture the remainder in full
+    pub currency: Option<Currency>,
+    #[serde(default)]
+    pub final_capture: bool,
+}
+
+impl CaptureRequest {
+    // A half-given or unparsable amount is rejected rather than taken as a full capture
+    fn amount(&self) -> Result<Option<Money>, EuropayError> {
+        match (&self.amount, self.currency) {
+            (Some(amount), Some(currency)) => Ok(Some(Money::parse(amount, currency)?)),
+            (None, None) => Ok(None),
+            _ => Err(EuropayError::InvalidRequest("amount and currency must be given together".to_string())),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CaptureResponse {
+    pub capture_id: Uuid,
+    pub captured_amount: Money, // Total captured on the authorization so far
+    pub remaining_amount: Money,
 }
 
 #[derive(Deserialize)]
@@ -27,35 +120,246 @@ pub struct TransactionActionRequest {
     pub transaction_id: Uuid,
 }
 
+#[derive(Deserialize)]
+pub struct TransactionFilter {
+    pub merchant_id: Option<Uuid>,
+    pub status: Option<TransactionStatus>,
+    pub from: Option<DateTime<Utc>>, // Created at or after
+    pub to: Option<DateTime<Utc>>,   // Created before
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// The locale a terminal asked receipts to be rendered in, if it is one we support.
+fn receipt_locale(headers: &HeaderMap) -> Option<Locale> {
+    headers.get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(Locale::from_accept_language)
+}
+
 pub async fn authorize_transaction(
     State(processor): State<Arc<Mutex<PaymentProcessor>>>,
-    Json(payload): Json<AuthorizeRequest>,
-) -> Result<JsonResponse<AuthorizeResponse>, StatusCode> {
+    headers: HeaderMap,
+    payload: Result<Json<AuthorizeRequest>, JsonRejection>,
+) -> Result<JsonResponse<AuthorizeResponse>, EuropayError> {
+    let Json(payload) = payload?;
     let mut proc = processor.lock().await;
-    match proc.authorize_transaction(payload.card_id, payload.merchant_id, payload.amount, &payload.currency) {
-        Ok(tx_id) => Ok(Json(AuthorizeResponse { transaction_id: tx_id })),
-        Err(_) => Err(StatusCode::BAD_REQUEST),
+    match proc.authorize(payload.card_id, payload.merchant_id, payload.amount)? {
+        AuthorizationDecision::Approved(tx_id) => {
+            let transaction = proc.get_transaction(tx_id).ok_or(EuropayError::TransactionNotFound)?;
+            Ok(Json(AuthorizeResponse::approved(transaction, receipt_locale(&headers))))
+        }
+        AuthorizationDecision::Declined(tx_id, reason) => Ok(Json(AuthorizeResponse::declined(tx_id, &reason))),
     }
 }
 
-pub async fn capture_transaction(
+pub async fn increment_authorization(
     State(processor): State<Arc<Mutex<PaymentProcessor>>>,
-    Json(payload): Json<TransactionActionRequest>,
-) -> Result<StatusCode, StatusCode> {
+    headers: HeaderMap,
+    payload: Result<Json<IncrementRequest>, JsonRejection>,
+) -> Result<JsonResponse<AuthorizeResponse>, EuropayError> {
+    let Json(payload) = payload?;
     let mut proc = processor.lock().await;
-    match proc.capture_transaction(payload.transaction_id) {
-        Ok(_) => Ok(StatusCode::OK),
-        Err(_) => Err(StatusCode::BAD_REQUEST),
+    match proc.increment_authorization(payload.transaction_id, payload.amount) {
+        Ok(()) => {
+            let transaction = proc.get_transaction(payload.transaction_id).ok_or(EuropayError::TransactionNotFound)?;
+            Ok(Json(AuthorizeResponse::approved(transaction, receipt_locale(&headers))))
+        }
+        Err(e) if e.is_decline() => Ok(Json(AuthorizeResponse::declined(payload.transaction_id, &e))),
+        Err(e) => Err(e),
     }
 }
 
+pub async fn capture_transaction(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    payload: Result<Json<CaptureRequest>, JsonRejection>,
+) -> Result<JsonResponse<CaptureResponse>, EuropayError> {
+    let Json(payload) = payload?;
+    let amount = payload.amount()?;
+    let mut proc = processor.lock().await;
+    let capture_id = match amount {
+        Some(amount) => proc.capture_partial(payload.transaction_id, amount, payload.final_capture)?,
+        None => proc.capture_transaction(payload.transaction_id)?,
+    };
+    let transaction = proc.get_transaction(payload.transaction_id).ok_or(EuropayError::TransactionNotFound)?;
+    let remaining_amount = transaction.amount.checked_sub(&transaction.captured_amount)?;
+    Ok(Json(CaptureResponse {
+        capture_id,
+        captured_amount: transaction.captured_amount,
+        remaining_amount,
+    }))
+}
+
 pub async fn settle_transaction(
     State(processor): State<Arc<Mutex<PaymentProcessor>>>,
-    Json(payload): Json<TransactionActionRequest>,
-) -> Result<StatusCode, StatusCode> {
+    payload: Result<Json<TransactionActionRequest>, JsonRejection>,
+) -> Result<StatusCode, EuropayError> {
+    let Json(payload) = payload?;
+    let mut proc = processor.lock().await;
+    proc.settle_transaction(payload.transaction_id)?;
+    Ok(StatusCode::OK)
+}
+
+pub async fn refund_transaction(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    payload: Result<Json<RefundRequest>, JsonRejection>,
+) -> Result<JsonResponse<RefundResponse>, EuropayError> {
+    let Json(payload) = payload?;
     let mut proc = processor.lock().await;
-    match proc.settle_transaction(payload.transaction_id) {
-        Ok(_) => Ok(StatusCode::OK),
-        Err(_) => Err(StatusCode::BAD_REQUEST),
+    let refund_id = proc.refund_transaction(payload.transaction_id, payload.amount)?;
+    let refund = proc.get_transaction(refund_id).ok_or(EuropayError::TransactionNotFound)?;
+    let original = proc.get_transaction(payload.transaction_id).ok_or(EuropayError::TransactionNotFound)?;
+    Ok(Json(RefundResponse {
+        refund_id,
+        original_transaction_id: payload.transaction_id,
+        billing_amount: refund.billing_amount,
+        refunded_amount: original.refunded_amount,
+    }))
+}
+
+pub async fn get_transaction(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(transaction_id): Path<Uuid>,
+) -> Result<JsonResponse<Transaction>, EuropayError> {
+    let proc = processor.lock().await;
+    let transaction = proc.get_transaction(transaction_id).ok_or(EuropayError::TransactionNotFound)?;
+    Ok(Json(transaction.clone()))
+}
+
+pub async fn list_transactions(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    filter: Result<Query<TransactionFilter>, QueryRejection>,
+) -> Result<JsonResponse<Page<Transaction>>, EuropayError> {
+    let Query(filter) = filter?;
+    let proc = processor.lock().await;
+    let transactions = proc.transactions()
+        .filter(|tx| filter.merchant_id.is_none_or(|id| tx.merchant_id == id))
+        .filter(|tx| filter.status.is_none_or(|status| tx.status == status))
+        .filter(|tx| filter.from.is_none_or(|from| tx.created_at >= from))
+        .filter(|tx| filter.to.is_none_or(|to| tx.created_at < to))
+        .cloned()
+        .collect();
+    Ok(Json(paginate(transactions, |tx| (tx.created_at, tx.id), filter.cursor.as_deref(), filter.limit)?))
+}
+
+pub async fn get_transitions(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(transaction_id): Path<Uuid>,
+) -> Result<JsonResponse<Vec<TransitionEvent>>, EuropayError> {
+    let proc = processor.lock().await;
+    proc.get_transaction(transaction_id).ok_or(EuropayError::TransactionNotFound)?;
+    Ok(Json(proc.transitions_for(transaction_id).to_vec()))
+}
+
+pub async fn get_captures(
+    State(processor): State<Arc<Mutex<PaymentProcessor>>>,
+    Path(transaction_id): Path<Uuid>,
+) -> Result<JsonResponse<Vec<Capture>>, EuropayError> {
+    let proc = processor.lock().await;
+    proc.get_transaction(transaction_id).ok_or(EuropayError::TransactionNotFound)?;
+    Ok(Json(proc.captures_for(transaction_id).into_iter().cloned().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::transactions::fixtures;
+
+    #[tokio::test]
+    async fn test_decline_is_a_response_not_an_error() {
+        let mut processor = fixtures::processor();
+        let fixtures::Cardholder { card_id, merchant_id, .. } = fixtures::add_cardholder(&mut processor, Currency::EUR, "10.00");
+        let processor = Arc::new(Mutex::new(processor));
+        let request = |amount: &str| Ok(Json(AuthorizeRequest { card_id, merchant_id, amount: Money::parse(amount, Currency::EUR).unwrap() }));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT_LANGUAGE, "de-DE,de;q=0.9".parse().unwrap());
+        let Json(approved) = authorize_transaction(State(processor.clone()), headers, request("4.00")).await.unwrap();
+        assert!(approved.approved);
+        assert_eq!(approved.response_code, "00");
+        assert!(approved.approval_code.is_some());
+        assert_eq!(approved.display_amount.as_deref(), Some("4,00 €"));
+
+        let Json(declined) = authorize_transaction(State(processor.clone()), HeaderMap::new(), request("40.00")).await.unwrap();
+        assert!(!declined.approved);
+        assert_eq!(declined.response_code, "51");
+        assert_eq!(declined.decline_reason.as_deref(), Some("insufficient_funds"));
+        let recorded = processor.lock().await.get_transaction(declined.transaction_id).unwrap().clone();
+        assert_eq!(recorded.status, TransactionStatus::Declined);
+
+        let invalid = authorize_transaction(State(processor.clone()), HeaderMap::new(), request("-1.00")).await.err().unwrap();
+        assert_eq!(invalid.status(), StatusCode::BAD_REQUEST);
+
+        // An unknown card is an invalid request, not a decline
+        let unknown = Ok(Json(AuthorizeRequest { card_id: Uuid::new_v4(), merchant_id, amount: Money::parse("4.00", Currency::EUR).unwrap() }));
+        let invalid = authorize_transaction(State(processor.clone()), HeaderMap::new(), unknown).await.err().unwrap();
+        assert_eq!(invalid, EuropayError::CardNotFound);
+        assert_eq!(processor.lock().await.transactions().count(), 2);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_capture_rejects_partial_or_malformed_amount() {
+        let mut processor = fixtures::processor();
+        let fixtures::Cardholder { card_id, merchant_id, .. } = fixtures::add_cardholder(&mut processor, Currency::EUR, "50.00");
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, Money::parse("20.00", Currency::EUR).unwrap()).unwrap();
+        let processor = Arc::new(Mutex::new(processor));
+        let request = |body: serde_json::Value| Ok(Json(serde_json::from_value::<CaptureRequest>(body).unwrap()));
+
+        for body in [
+            serde_json::json!({ "transaction_id": tx_id, "amount": "12,50", "currency": "EUR" }),
+            serde_json::json!({ "transaction_id": tx_id, "amount": "12.50" }),
+            serde_json::json!({ "transaction_id": tx_id, "currency": "EUR" }),
+        ] {
+            let error = capture_transaction(State(processor.clone()), request(body)).await.err().unwrap();
+            assert_eq!(error.status(), StatusCode::BAD_REQUEST);
+        }
+        assert!(processor.lock().await.get_transaction(tx_id).unwrap().captured_amount.is_zero());
+
+        let body = serde_json::json!({ "transaction_id": tx_id, "amount": "12.50", "currency": "EUR" });
+        let Json(captured) = capture_transaction(State(processor.clone()), request(body)).await.unwrap();
+        assert_eq!(captured.captured_amount, Money::parse("12.50", Currency::EUR).unwrap());
+        assert_eq!(captured.remaining_amount, Money::parse("7.50", Currency::EUR).unwrap());
+
+        let Json(captures) = get_captures(State(processor), Path(tx_id)).await.unwrap();
+        assert_eq!(captures.len(), 1);
+        assert_eq!(captures[0].amount, Money::parse("12.50", Currency::EUR).unwrap());
+        assert!(!captures[0].is_final);
+    }
+
+    #[tokio::test]
+    async fn test_list_transactions_filters_and_pages() {
+        let mut processor = fixtures::processor();
+        let fixtures::Cardholder { card_id, merchant_id, .. } = fixtures::add_cardholder(&mut processor, Currency::EUR, "100.00");
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(processor.authorize_transaction(card_id, merchant_id, Money::parse("5.00", Currency::EUR).unwrap()).unwrap());
+        }
+        let captured_id = ids[1];
+        processor.capture_transaction(captured_id).unwrap();
+        let processor = Arc::new(Mutex::new(processor));
+        let filter = |status: Option<TransactionStatus>, cursor: Option<String>| Ok(Query(TransactionFilter {
+            merchant_id: Some(merchant_id),
+            status,
+            from: None,
+            to: None,
+            cursor,
+            limit: Some(2),
+        }));
+
+        let Json(first) = list_transactions(State(processor.clone()), filter(None, None)).await.unwrap();
+        assert_eq!(first.items.len(), 2);
+        let Json(second) = list_transactions(State(processor.clone()), filter(None, first.next_cursor)).await.unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert!(second.next_cursor.is_none());
+        let mut listed: Vec<Uuid> = first.items.iter().chain(&second.items).map(|tx| tx.id).collect();
+        listed.sort();
+        ids.sort();
+        assert_eq!(listed, ids);
+
+        let Json(captured) = list_transactions(State(processor.clone()), filter(Some(TransactionStatus::Captured), None)).await.unwrap();
+        assert_eq!(captured.items.iter().map(|tx| tx.id).collect::<Vec<_>>(), vec![captured_id]);
+
+        let missing = get_transaction(State(processor), Path(Uuid::new_v4())).await.err().unwrap();
+        assert_eq!(missing, EuropayError::TransactionNotFound);
+    }
+}
@@ -1,103 +1,267 @@
 // Currency support for European payments
 
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum Currency {
-    EUR, // Euro
-    GBP, // British Pound
-    CHF, // Swiss Franc
-    SEK, // Swedish Krona
-    NOK, // Norwegian Krone
-    DKK, // Danish Krone
-    PLN, // Polish Zloty
-    CZK, // Czech Koruna
-    HUF, // Hungarian Forint
-    RON, // Romanian Leu
-    BGN, // Bulgarian Lev
-    HRK, // Croatian Kuna
-}
+use crate::core::money::{Money, RoundingMode};
+use crate::core::rates::{Rate, RateProvider, RateSnapshot};
+
+/// An ISO 4217 currency, identified by its alphabetic code.
+///
+/// Values can only be obtained from the bundled registry, so every `Currency` has an entry
+/// in the ISO 4217 table (including withdrawn currencies kept for historical transactions).
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Currency([u8; 3]);
 
 impl Currency {
+    pub const EUR: Currency = Currency(*b"EUR"); // Euro
+    pub const GBP: Currency = Currency(*b"GBP"); // British Pound
+    pub const CHF: Currency = Currency(*b"CHF"); // Swiss Franc
+    pub const SEK: Currency = Currency(*b"SEK"); // Swedish Krona
+    pub const NOK: Currency = Currency(*b"NOK"); // Norwegian Krone
+    pub const DKK: Currency = Currency(*b"DKK"); // Danish Krone
+    pub const ISK: Currency = Currency(*b"ISK"); // Icelandic Krona
+    pub const PLN: Currency = Currency(*b"PLN"); // Polish Zloty
+    pub const CZK: Currency = Currency(*b"CZK"); // Czech Koruna
+    pub const HUF: Currency = Currency(*b"HUF"); // Hungarian Forint
+    pub const RON: Currency = Currency(*b"RON"); // Romanian Leu
+    pub const RSD: Currency = Currency(*b"RSD"); // Serbian Dinar
+    pub const MKD: Currency = Currency(*b"MKD"); // Macedonian Denar
+    pub const ALL: Currency = Currency(*b"ALL"); // Albanian Lek
+    pub const UAH: Currency = Currency(*b"UAH"); // Ukrainian Hryvnia
+    pub const TRY: Currency = Currency(*b"TRY"); // Turkish Lira
+    pub const USD: Currency = Currency(*b"USD"); // US Dollar
+    pub const BGN: Currency = Currency(*b"BGN"); // Bulgarian Lev (withdrawn 2026)
+    pub const HRK: Currency = Currency(*b"HRK"); // Croatian Kuna (withdrawn 2023)
+
+    pub fn from_code(code: &str) -> Option<Currency> {
+        CurrencyRegistry::bundled().get_by_code(code).map(|info| info.currency)
+    }
+
+    /// Looks up a currency by ISO 4217 numeric code, as carried in ISO 8583 field 49.
+    pub fn from_numeric(numeric: u16) -> Option<Currency> {
+        CurrencyRegistry::bundled().get_by_numeric(numeric).map(|info| info.currency)
+    }
+
+    pub fn code(&self) -> &str {
+        // Codes are validated against the registry, so they are always ASCII
+        std::str::from_utf8(&self.0).unwrap_or("XXX")
+    }
+
+    pub fn info(&self) -> &'static CurrencyInfo {
+        CurrencyRegistry::bundled()
+            .get(self)
+            .expect("every Currency value comes from the bundled ISO 4217 table")
+    }
+
+    pub fn numeric_code(&self) -> u16 {
+        self.info().numeric
+    }
+
     pub fn symbol(&self) -> &'static str {
-        match self {
-            Currency::EUR => "€",
-            Currency::GBP => "£",
-            Currency::CHF => "CHF",
-            Currency::SEK => "kr",
-            Currency::NOK => "kr",
-            Currency::DKK => "kr",
-            Currency::PLN => "zł",
-            Currency::CZK => "Kč",
-            Currency::HUF => "Ft",
-            Currency::RON => "lei",
-            Currency::BGN => "лв",
-            Currency::HRK => "kn",
-        }
+        &self.info().symbol
     }
 
     pub fn decimal_places(&self) -> u8 {
-        match self {
-            Currency::EUR | Currency::GBP | Currency::CHF => 2,
-            Currency::SEK | Currency::NOK | Currency::DKK => 2,
-            Currency::PLN => 2,
-            Currency::CZK => 2,
-            Currency::HUF => 0, // Hungarian Forint has no decimal places
-            Currency::RON => 2,
-            Currency::BGN => 2,
-            Currency::HRK => 2,
-        }
+        self.info().minor_units
     }
 
     pub fn is_eurozone(&self) -> bool {
-        matches!(self, Currency::EUR)
+        *self == Currency::EUR
+    }
+
+    /// Whether the currency's issuing area had joined the euro on `date`.
+    pub fn is_eurozone_on(&self, date: NaiveDate) -> bool {
+        self.info().euro_adopted.is_some_and(|adopted| date >= adopted)
+    }
+
+    /// Whether the currency was still legal tender (not withdrawn) on `date`.
+    pub fn is_active_on(&self, date: NaiveDate) -> bool {
+        self.info().withdrawn.is_none_or(|withdrawn| date < withdrawn)
     }
 }
 
-pub struct CurrencyConverter {
-    rates: HashMap<(Currency, Currency), f64>,
+impl fmt::Debug for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
 }
 
-impl CurrencyConverter {
-    pub fn new() -> Self {
-        let mut rates = HashMap::new();
-        // Initialize with some example rates (in real system, fetch from API)
-        rates.insert((Currency::EUR, Currency::GBP), 0.85);
-        rates.insert((Currency::EUR, Currency::CHF), 0.95);
-        rates.insert((Currency::EUR, Currency::SEK), 11.5);
-        rates.insert((Currency::EUR, Currency::NOK), 11.8);
-        rates.insert((Currency::EUR, Currency::DKK), 7.45);
-        rates.insert((Currency::EUR, Currency::PLN), 4.3);
-        rates.insert((Currency::EUR, Currency::CZK), 25.0);
-        rates.insert((Currency::EUR, Currency::HUF), 380.0);
-        rates.insert((Currency::EUR, Currency::RON), 4.95);
-        rates.insert((Currency::EUR, Currency::BGN), 1.955);
-        rates.insert((Currency::EUR, Currency::HRK), 7.5);
-
-        // Add reverse rates
-        let mut reverse_rates = HashMap::new();
-        for ((from, to), rate) in &rates {
-            reverse_rates.insert((*to, *from), 1.0 / rate);
-        }
-        rates.extend(reverse_rates);
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
 
-        Self { rates }
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        Currency::from_code(&code)
+            .ok_or_else(|| de::Error::custom(format!("unknown currency code: {}", code)))
     }
+}
+
+#[derive(Debug, Clone)]
+pub struct CurrencyInfo {
+    pub currency: Currency,
+    pub numeric: u16,
+    pub minor_units: u8,
+    pub symbol: String,
+    pub name: String,
+    pub withdrawn: Option<NaiveDate>,
+    pub euro_adopted: Option<NaiveDate>,
+}
+
+/// ISO 4217 table keyed by alphabetic and numeric code.
+pub struct CurrencyRegistry {
+    by_code: HashMap<Currency, CurrencyInfo>,
+    by_numeric: HashMap<u16, Currency>,
+}
 
-    pub fn convert(&self, amount: f64, from: &Currency, to: &Currency) -> f64 {
-        if from == to {
-            return amount;
+static BUNDLED_REGISTRY: LazyLock<CurrencyRegistry> = LazyLock::new(|| {
+    CurrencyRegistry::from_csv(include_str!("iso4217.csv")).expect("bundled ISO 4217 table is valid")
+});
+
+impl CurrencyRegistry {
+    pub fn bundled() -> &'static CurrencyRegistry {
+        &BUNDLED_REGISTRY
+    }
+
+    /// Loads a table of `alpha,numeric,minor_units,symbol,name,withdrawn,euro_adopted` rows.
+    /// Blank lines and lines starting with `#` are ignored; dates are `YYYY-MM-DD`.
+    pub fn from_csv(data: &str) -> Result<Self, String> {
+        let mut by_code = HashMap::new();
+        let mut by_numeric: HashMap<u16, Currency> = HashMap::new();
+
+        for (line_no, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let info = parse_row(line).map_err(|e| format!("line {}: {}", line_no + 1, e))?;
+            if by_code.contains_key(&info.currency) {
+                return Err(format!("line {}: duplicate currency {}", line_no + 1, info.currency));
+            }
+
+            // Numeric codes can be shared with a withdrawn predecessor; the active one wins
+            let replace = match by_numeric.get(&info.numeric) {
+                Some(existing) => info.withdrawn.is_none() && by_code.get(existing).is_some_and(|e: &CurrencyInfo| e.withdrawn.is_some()),
+                None => true,
+            };
+            if replace {
+                by_numeric.insert(info.numeric, info.currency);
+            }
+            by_code.insert(info.currency, info);
         }
 
-        if let Some(rate) = self.rates.get(&(*from, *to)) {
-            amount * rate
-        } else {
-            // Fallback: convert through EUR
-            let to_eur = self.rates.get(&(*from, Currency::EUR)).unwrap_or(&1.0);
-            let from_eur = self.rates.get(&(Currency::EUR, *to)).unwrap_or(&1.0);
-            amount * to_eur * from_eur
+        Ok(Self { by_code, by_numeric })
+    }
+
+    pub fn get(&self, currency: &Currency) -> Option<&CurrencyInfo> {
+        self.by_code.get(currency)
+    }
+
+    pub fn get_by_code(&self, code: &str) -> Option<&CurrencyInfo> {
+        let bytes: [u8; 3] = code.trim().to_ascii_uppercase().as_bytes().try_into().ok()?;
+        self.by_code.get(&Currency(bytes))
+    }
+
+    pub fn get_by_numeric(&self, numeric: u16) -> Option<&CurrencyInfo> {
+        self.by_numeric.get(&numeric).and_then(|c| self.by_code.get(c))
+    }
+}
+
+fn parse_row(line: &str) -> Result<CurrencyInfo, String> {
+    let cols: Vec<&str> = line.split(',').map(str::trim).collect();
+    if cols.len() != 7 {
+        return Err(format!("expected 7 columns, found {}", cols.len()));
+    }
+
+    let code: [u8; 3] = cols[0].as_bytes().try_into().map_err(|_| format!("invalid alpha code: {}", cols[0]))?;
+    if !code.iter().all(u8::is_ascii_uppercase) {
+        return Err(format!("invalid alpha code: {}", cols[0]));
+    }
+    let numeric = cols[1].parse::<u16>().map_err(|_| format!("invalid numeric code: {}", cols[1]))?;
+    let minor_units = cols[2].parse::<u8>().map_err(|_| format!("invalid minor units: {}", cols[2]))?;
+    let parse_date = |s: &str| -> Result<Option<NaiveDate>, String> {
+        if s.is_empty() {
+            return Ok(None);
         }
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").map(Some).map_err(|_| format!("invalid date: {}", s))
+    };
+
+    Ok(CurrencyInfo {
+        currency: Currency(code),
+        numeric,
+        minor_units,
+        symbol: cols[3].to_string(),
+        name: cols[4].to_string(),
+        withdrawn: parse_date(cols[5])?,
+        euro_adopted: parse_date(cols[6])?,
+    })
+}
+
+/// A Dynamic Currency Conversion offer: the merchant's amount expressed in the cardholder's currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DccQuote {
+    pub rate: Rate,
+    pub markup_bps: u32,
+    pub applied_rate: Rate,
+    pub transaction_amount: Money,
+    pub cardholder_amount: Money,
+    pub rate_effective_at: DateTime<Utc>,
+    pub quoted_at: DateTime<Utc>,
+}
+
+/// Converts amounts using the rates published by a [`RateProvider`].
+pub struct CurrencyConverter {
+    provider: Arc<dyn RateProvider>,
+}
+
+impl CurrencyConverter {
+    pub fn new(provider: Arc<dyn RateProvider>) -> Self {
+        Self { provider }
+    }
+
+    /// Rate from `from` to `to` in the latest snapshot, with the snapshot's effective time.
+    pub fn rate(&self, from: Currency, to: Currency) -> Result<(Rate, DateTime<Utc>), String> {
+        let snapshot = self.provider.latest()?;
+        Self::rate_in(&snapshot, from, to)
+    }
+
+    /// Quotes a Dynamic Currency Conversion of `amount` into the cardholder's `billing_currency`,
+    /// applying `markup_bps` basis points on top of the reference rate.
+    pub fn quote_dcc(&self, amount: &Money, billing_currency: Currency, markup_bps: u32) -> Result<DccQuote, String> {
+        let (rate, rate_effective_at) = self.rate(amount.currency(), billing_currency)?;
+        let applied_rate = rate.with_markup_bps(markup_bps)?;
+        let cardholder_amount = applied_rate
+            .apply(amount, billing_currency, RoundingMode::HalfUp)
+            .map_err(|e| e.to_string())?;
+
+        Ok(DccQuote {
+            rate,
+            markup_bps,
+            applied_rate,
+            transaction_amount: *amount,
+            cardholder_amount,
+            rate_effective_at,
+            quoted_at: Utc::now(),
+        })
+    }
+
+    fn rate_in(snapshot: &RateSnapshot, from: Currency, to: Currency) -> Result<(Rate, DateTime<Utc>), String> {
+        snapshot.rate(from, to)
+            .map(|rate| (rate, snapshot.effective_at))
+            .ok_or_else(|| format!("No exchange rate for {}/{}", from, to))
     }
 }
 
@@ -105,19 +269,81 @@ impl CurrencyConverter {
 mod tests {
     use super::*;
 
+    use crate::core::rates::InMemoryRateProvider;
+
+    fn test_converter() -> CurrencyConverter {
+        let snapshot = RateSnapshot::new(Currency::EUR, Utc::now())
+            .with_rate(Currency::GBP, Rate::parse("0.85").unwrap())
+            .with_rate(Currency::PLN, Rate::parse("4.3").unwrap());
+        CurrencyConverter::new(Arc::new(InMemoryRateProvider::with_snapshot(snapshot)))
+    }
+
     #[test]
     fn test_currency_conversion() {
-        let converter = CurrencyConverter::new();
-        let amount = 100.0;
-        let converted = converter.convert(amount, &Currency::EUR, &Currency::GBP);
-        assert!(converted > 80.0 && converted < 90.0);
+        let converter = test_converter();
+        let amount = Money::parse("100.00", Currency::EUR).unwrap();
+        let (rate, _) = converter.rate(Currency::EUR, Currency::GBP).unwrap();
+        let converted = rate.apply(&amount, Currency::GBP, RoundingMode::HalfUp).unwrap();
+        assert_eq!(converted, Money::parse("85.00", Currency::GBP).unwrap());
     }
 
     #[test]
     fn test_same_currency() {
-        let converter = CurrencyConverter::new();
-        let amount = 100.0;
-        let converted = converter.convert(amount, &Currency::EUR, &Currency::EUR);
-        assert_eq!(converted, 100.0);
+        let converter = test_converter();
+        let amount = Money::parse("100.00", Currency::EUR).unwrap();
+        let quote = converter.quote_dcc(&amount, Currency::EUR, 0).unwrap();
+        assert_eq!(quote.cardholder_amount, amount);
+    }
+
+    #[test]
+    fn test_dcc_quote_applies_markup() {
+        let converter = test_converter();
+        let amount = Money::parse("100.00", Currency::EUR).unwrap();
+        let quote = converter.quote_dcc(&amount, Currency::GBP, 300).unwrap();
+        assert_eq!(quote.rate, Rate::parse("0.85").unwrap());
+        assert_eq!(quote.applied_rate.to_string(), "0.8755000000");
+        assert_eq!(quote.cardholder_amount, Money::parse("87.55", Currency::GBP).unwrap());
+    }
+
+    #[test]
+    fn test_unknown_pair_is_an_error() {
+        let converter = test_converter();
+        let amount = Money::parse("100.00", Currency::EUR).unwrap();
+        assert!(converter.quote_dcc(&amount, Currency::CHF, 300).is_err());
+    }
+
+    #[test]
+    fn test_registry_lookup() {
+        assert_eq!(Currency::from_code("eur"), Some(Currency::EUR));
+        assert_eq!(Currency::from_numeric(978), Some(Currency::EUR));
+        assert_eq!(Currency::from_numeric(352), Some(Currency::ISK));
+        assert_eq!(Currency::ISK.decimal_places(), 0);
+        assert_eq!(Currency::PLN.symbol(), "zł");
+        assert_eq!(Currency::from_code("XYZ"), None);
+
+        for currency in [Currency::EUR, Currency::GBP, Currency::CHF, Currency::SEK, Currency::NOK, Currency::DKK,
+                         Currency::ISK, Currency::PLN, Currency::CZK, Currency::HUF, Currency::RON, Currency::RSD,
+                         Currency::MKD, Currency::ALL, Currency::UAH, Currency::TRY, Currency::USD, Currency::BGN,
+                         Currency::HRK] {
+            assert!(CurrencyRegistry::bundled().get(&currency).is_some(), "{} missing", currency);
+        }
+    }
+
+    #[test]
+    fn test_withdrawn_currencies_resolve() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        assert_eq!(Currency::from_numeric(191), Some(Currency::HRK));
+        assert!(Currency::HRK.is_active_on(date(2022, 12, 31)));
+        assert!(!Currency::HRK.is_active_on(date(2023, 1, 1)));
+        assert!(!Currency::HRK.is_eurozone_on(date(2022, 12, 31)));
+        assert!(Currency::HRK.is_eurozone_on(date(2023, 1, 1)));
+        assert!(!Currency::GBP.is_eurozone_on(date(2023, 1, 1)));
+    }
+
+    #[test]
+    fn test_serde_by_code() {
+        assert_eq!(serde_json::to_string(&Currency::HUF).unwrap(), r#""HUF""#);
+        assert_eq!(serde_json::from_str::<Currency>(r#""SEK""#).unwrap(), Currency::SEK);
+        assert!(serde_json::from_str::<Currency>(r#""ABC""#).is_err());
     }
 }
\ No newline at end of file
@@ -0,0 +1,250 @@
+// Processing errors with stable API codes and their ISO 8583 response codes
+
+use axum::{extract::rejection::{JsonRejection, QueryRejection}, http::{header, StatusCode}, response::{IntoResponse, Response}, Json};
+use serde::Serialize;
+use std::fmt;
+
+use crate::core::money::MoneyError;
+use crate::models::lifecycle::TransitionError;
+use crate::services::messaging::{
+    RESPONSE_DO_NOT_HONOR, RESPONSE_DUPLICATE_TRANSMISSION, RESPONSE_EXPIRED_CARD, RESPONSE_FORMAT_ERROR, RESPONSE_INSUFFICIENT_FUNDS,
+    RESPONSE_INVALID_AMOUNT, RESPONSE_INVALID_CARD, RESPONSE_INVALID_MERCHANT, RESPONSE_INVALID_TRANSACTION,
+    RESPONSE_ISSUER_UNAVAILABLE, RESPONSE_NO_ORIGINAL, RESPONSE_RESTRICTED_CARD, RESPONSE_SUSPECTED_FRAUD, RESPONSE_SYSTEM_MALFUNCTION,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EuropayError {
+    CardNotFound,
+    AccountNotFound,
+    MerchantNotFound,
+    TransactionNotFound,
+    DisputeNotFound,
+    BatchNotFound,
+    CardNotActive,
+    AccountNotActive,
+    MerchantNotActive,
+    CardExpired,
+    InsufficientFunds,
+    FraudSuspected,
+    InvalidAmount(String),
+    CurrencyMismatch,
+    CurrencyUnavailable(String), // Withdrawn, or no rate to convert it
+    InvalidState(String),        // The transaction or dispute does not allow the operation
+    InvalidRequest(String),      // Malformed input, e.g. a missing ISO 8583 field
+    IdempotencyConflict(String), // Key reused for a different request, or still in flight
+    IssuerUnavailable(String),   // The issuing node did not answer in time
+    Ledger(String),
+    Storage(String),             // The database failed or holds a record we cannot read
+    Internal(String),
+}
+
+impl EuropayError {
+    /// Machine-readable code for API clients. These never change once published.
+    pub fn code(&self) -> &'static str {
+        match self {
+            EuropayError::CardNotFound => "card_not_found",
+            EuropayError::AccountNotFound => "account_not_found",
+            EuropayError::MerchantNotFound => "merchant_not_found",
+            EuropayError::TransactionNotFound => "transaction_not_found",
+            EuropayError::DisputeNotFound => "dispute_not_found",
+            EuropayError::BatchNotFound => "batch_not_found",
+            EuropayError::CardNotActive => "card_not_active",
+            EuropayError::AccountNotActive => "account_not_active",
+            EuropayError::MerchantNotActive => "merchant_not_active",
+            EuropayError::CardExpired => "card_expired",
+            EuropayError::InsufficientFunds => "insufficient_funds",
+            EuropayError::FraudSuspected => "fraud_suspected",
+            EuropayError::InvalidAmount(_) => "invalid_amount",
+            EuropayError::CurrencyMismatch => "currency_mismatch",
+            EuropayError::CurrencyUnavailable(_) => "currency_unavailable",
+            EuropayError::InvalidState(_) => "invalid_state",
+            EuropayError::InvalidRequest(_) => "invalid_request",
+            EuropayError::IdempotencyConflict(_) => "idempotency_conflict",
+            EuropayError::IssuerUnavailable(_) => "issuer_unavailable",
+            EuropayError::Ledger(_) => "ledger_error",
+            EuropayError::Storage(_) => "storage_error",
+            EuropayError::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            EuropayError::CardNotFound
+            | EuropayError::AccountNotFound
+            | EuropayError::MerchantNotFound
+            | EuropayError::TransactionNotFound
+            | EuropayError::DisputeNotFound
+            | EuropayError::BatchNotFound => StatusCode::NOT_FOUND,
+            // Well-formed requests the card, account or risk checks decline
+            EuropayError::CardNotActive
+            | EuropayError::AccountNotActive
+            | EuropayError::MerchantNotActive
+            | EuropayError::CardExpired
+            | EuropayError::InsufficientFunds
+            | EuropayError::FraudSuspected
+            | EuropayError::CurrencyUnavailable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            EuropayError::InvalidState(_) | EuropayError::IdempotencyConflict(_) => StatusCode::CONFLICT,
+            EuropayError::InvalidAmount(_) | EuropayError::CurrencyMismatch | EuropayError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            EuropayError::IssuerUnavailable(_) => StatusCode::GATEWAY_TIMEOUT,
+            EuropayError::Ledger(_) | EuropayError::Storage(_) | EuropayError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Whether this is the issuer declining a valid request, as opposed to the request
+    /// itself being invalid (including naming an unknown card or merchant) or the
+    /// processing failing.
+    pub fn is_decline(&self) -> bool {
+        matches!(
+            self,
+            EuropayError::CardNotActive
+                | EuropayError::AccountNotActive
+                | EuropayError::MerchantNotActive
+                | EuropayError::CardExpired
+                | EuropayError::InsufficientFunds
+                | EuropayError::FraudSuspected
+                | EuropayError::CurrencyUnavailable(_)
+        )
+    }
+
+    /// The field 39 code reporting this error to an acquirer.
+    pub fn response_code(&self) -> &'static str {
+        match self {
+            EuropayError::CardNotFound | EuropayError::AccountNotFound => RESPONSE_INVALID_CARD,
+            EuropayError::MerchantNotFound | EuropayError::MerchantNotActive => RESPONSE_INVALID_MERCHANT,
+            EuropayError::TransactionNotFound => RESPONSE_NO_ORIGINAL,
+            EuropayError::CardNotActive | EuropayError::AccountNotActive => RESPONSE_RESTRICTED_CARD,
+            EuropayError::CardExpired => RESPONSE_EXPIRED_CARD,
+            EuropayError::InsufficientFunds => RESPONSE_INSUFFICIENT_FUNDS,
+            EuropayError::FraudSuspected => RESPONSE_SUSPECTED_FRAUD,
+            EuropayError::InvalidAmount(_) => RESPONSE_INVALID_AMOUNT,
+            EuropayError::CurrencyMismatch | EuropayError::CurrencyUnavailable(_) | EuropayError::InvalidState(_) => RESPONSE_INVALID_TRANSACTION,
+            EuropayError::InvalidRequest(_) => RESPONSE_FORMAT_ERROR,
+            EuropayError::IdempotencyConflict(_) => RESPONSE_DUPLICATE_TRANSMISSION,
+            EuropayError::IssuerUnavailable(_) => RESPONSE_ISSUER_UNAVAILABLE,
+            EuropayError::Ledger(_) | EuropayError::Storage(_) | EuropayError::Internal(_) => RESPONSE_SYSTEM_MALFUNCTION,
+            EuropayError::DisputeNotFound | EuropayError::BatchNotFound => RESPONSE_DO_NOT_HONOR,
+        }
+    }
+}
+
+impl fmt::Display for EuropayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EuropayError::CardNotFound => write!(f, "Card not found"),
+            EuropayError::AccountNotFound => write!(f, "Account not found"),
+            EuropayError::MerchantNotFound => write!(f, "Merchant not found"),
+            EuropayError::TransactionNotFound => write!(f, "Transaction not found"),
+            EuropayError::DisputeNotFound => write!(f, "Dispute not found"),
+            EuropayError::BatchNotFound => write!(f, "Batch not found"),
+            EuropayError::CardNotActive => write!(f, "Card not active"),
+            EuropayError::AccountNotActive => write!(f, "Account not active"),
+            EuropayError::MerchantNotActive => write!(f, "Merchant not active"),
+            EuropayError::CardExpired => write!(f, "Card expired"),
+            EuropayError::InsufficientFunds => write!(f, "Insufficient funds"),
+            EuropayError::FraudSuspected => write!(f, "Transaction flagged for fraud"),
+            EuropayError::CurrencyMismatch => write!(f, "Currency mismatch"),
+            EuropayError::InvalidAmount(detail)
+            | EuropayError::CurrencyUnavailable(detail)
+            | EuropayError::InvalidState(detail)
+            | EuropayError::InvalidRequest(detail)
+            | EuropayError::IdempotencyConflict(detail)
+            | EuropayError::IssuerUnavailable(detail)
+            | EuropayError::Ledger(detail)
+            | EuropayError::Storage(detail)
+            | EuropayError::Internal(detail) => write!(f, "{}", detail),
+        }
+    }
+}
+
+impl std::error::Error for EuropayError {}
+
+impl From<MoneyError> for EuropayError {
+    fn from(error: MoneyError) -> Self {
+        match error {
+            MoneyError::CurrencyMismatch(_, _) => EuropayError::CurrencyMismatch,
+            other => EuropayError::InvalidAmount(other.to_string()),
+        }
+    }
+}
+
+impl From<TransitionError> for EuropayError {
+    fn from(error: TransitionError) -> Self {
+        EuropayError::InvalidState(error.to_string())
+    }
+}
+
+impl From<JsonRejection> for EuropayError {
+    fn from(rejection: JsonRejection) -> Self {
+        EuropayError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for EuropayError {
+    fn from(rejection: QueryRejection) -> Self {
+        EuropayError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<rusqlite::Error> for EuropayError {
+    fn from(error: rusqlite::Error) -> Self {
+        EuropayError::Storage(error.to_string())
+    }
+}
+
+// Request bodies are rejected by the extractors, so this only comes from stored records
+impl From<serde_json::Error> for EuropayError {
+    fn from(error: serde_json::Error) -> Self {
+        EuropayError::Storage(error.to_string())
+    }
+}
+
+/// Problem details body (RFC 7807) returned for a failed API request.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub code: &'static str,
+    pub status: u16,
+    pub detail: String,
+    pub response_code: &'static str,
+}
+
+impl From<&EuropayError> for Problem {
+    fn from(error: &EuropayError) -> Self {
+        Self {
+            code: error.code(),
+            status: error.status().as_u16(),
+            detail: error.to_string(),
+            response_code: error.response_code(),
+        }
+    }
+}
+
+impl IntoResponse for EuropayError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let headers = [(header::CONTENT_TYPE, "application/problem+json")];
+        (status, headers, Json(Problem::from(&self))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::Currency;
+
+    #[test]
+    fn test_errors_map_to_status_code_and_response_code() {
+        let error = EuropayError::InsufficientFunds;
+        assert_eq!((error.code(), error.status(), error.response_code()), ("insufficient_funds", StatusCode::UNPROCESSABLE_ENTITY, "51"));
+
+        let error = EuropayError::from(MoneyError::CurrencyMismatch(Currency::EUR, Currency::GBP));
+        assert_eq!(error, EuropayError::CurrencyMismatch);
+
+        let problem = serde_json::to_value(Problem::from(&EuropayError::CardExpired)).unwrap();
+        assert_eq!(problem, serde_json::json!({
+            "code": "card_expired",
+            "status": 422,
+            "detail": "Card expired",
+            "response_code": "54",
+        }));
+    }
+}
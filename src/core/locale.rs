@@ -0,0 +1,139 @@
+// Locale-aware amount formatting
+
+use crate::core::money::Money;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolPosition {
+    Prefix,         // £1,234.56
+    PrefixSpaced,   // € 1.234,56
+    SuffixSpaced,   // 1.234,56 €
+}
+
+/// Number and currency conventions for one locale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    pub tag: &'static str,
+    pub decimal_separator: char,
+    pub grouping_separator: char,
+    pub symbol_position: SymbolPosition,
+}
+
+const fn locale(tag: &'static str, decimal_separator: char, grouping_separator: char, symbol_position: SymbolPosition) -> Locale {
+    Locale { tag, decimal_separator, grouping_separator, symbol_position }
+}
+
+const LOCALES: &[Locale] = &[
+    locale("en-GB", '.', ',', SymbolPosition::Prefix),
+    locale("en-IE", '.', ',', SymbolPosition::Prefix),
+    locale("en-US", '.', ',', SymbolPosition::Prefix),
+    locale("de-DE", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("de-AT", ',', ' ', SymbolPosition::PrefixSpaced),
+    locale("de-CH", '.', '’', SymbolPosition::PrefixSpaced),
+    locale("fr-FR", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("fr-BE", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("nl-NL", ',', '.', SymbolPosition::PrefixSpaced),
+    locale("nl-BE", ',', '.', SymbolPosition::PrefixSpaced),
+    locale("es-ES", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("it-IT", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("pt-PT", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("el-GR", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("fi-FI", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("sv-SE", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("da-DK", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("nb-NO", ',', ' ', SymbolPosition::PrefixSpaced),
+    locale("is-IS", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("pl-PL", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("cs-CZ", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("sk-SK", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("hu-HU", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("ro-RO", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("bg-BG", ',', ' ', SymbolPosition::SuffixSpaced),
+    locale("hr-HR", ',', '.', SymbolPosition::SuffixSpaced),
+    locale("sl-SI", ',', '.', SymbolPosition::SuffixSpaced),
+];
+
+impl Locale {
+    /// Looks up a locale by BCP 47 tag, e.g. `de-DE` (case-insensitive, `_` accepted).
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let tag = tag.replace('_', "-");
+        LOCALES.iter().find(|l| l.tag.eq_ignore_ascii_case(&tag)).copied()
+    }
+
+    /// The first supported locale in an `Accept-Language` header, e.g. `fr-BE, fr;q=0.8`.
+    /// A bare language such as `pl` picks the first locale for it.
+    pub fn from_accept_language(header: &str) -> Option<Locale> {
+        header.split(',')
+            .map(|range| range.split(';').next().unwrap_or("").trim())
+            .find_map(|tag| Self::from_tag(tag).or_else(|| {
+                LOCALES.iter().find(|l| l.tag.split('-').next().is_some_and(|lang| lang.eq_ignore_ascii_case(tag))).copied()
+            }))
+    }
+
+    /// Formats the amount with its currency symbol, e.g. `1.234,56 €` for de-DE.
+    pub fn format(&self, amount: &Money) -> String {
+        let number = self.format_number(amount);
+        let symbol = amount.currency().symbol();
+        let (sign, digits) = match number.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", number.as_str()),
+        };
+        match self.symbol_position {
+            SymbolPosition::Prefix => format!("{}{}{}", sign, symbol, digits),
+            SymbolPosition::PrefixSpaced => format!("{}{} {}", sign, symbol, digits),
+            SymbolPosition::SuffixSpaced => format!("{}{} {}", sign, digits, symbol),
+        }
+    }
+
+    /// Formats the amount without a currency symbol, e.g. `1.234,56` for de-DE.
+    pub fn format_number(&self, amount: &Money) -> String {
+        let plain = amount.to_decimal_string();
+        let (sign, unsigned) = match plain.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", plain.as_str()),
+        };
+        let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+
+        let mut grouped = String::new();
+        for (i, c) in int_part.chars().enumerate() {
+            if i > 0 && (int_part.len() - i) % 3 == 0 {
+                grouped.push(self.grouping_separator);
+            }
+            grouped.push(c);
+        }
+        if frac_part.is_empty() {
+            format!("{}{}", sign, grouped)
+        } else {
+            format!("{}{}{}{}", sign, grouped, self.decimal_separator, frac_part)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::Currency;
+
+    fn money(s: &str, currency: Currency) -> Money {
+        Money::parse(s, currency).unwrap()
+    }
+
+    #[test]
+    fn test_format_european_conventions() {
+        let de = Locale::from_tag("de-DE").unwrap();
+        let gb = Locale::from_tag("en_GB").unwrap();
+        let pl = Locale::from_tag("pl-PL").unwrap();
+
+        assert_eq!(de.format(&money("1234.56", Currency::EUR)), "1.234,56 €");
+        assert_eq!(gb.format(&money("1234.56", Currency::GBP)), "£1,234.56");
+        assert_eq!(pl.format(&money("1234.56", Currency::PLN)), "1 234,56 zł");
+        assert_eq!(gb.format(&money("-1234567.00", Currency::GBP)), "-£1,234,567.00");
+        assert_eq!(de.format_number(&money("12", Currency::ISK)), "12");
+    }
+
+    #[test]
+    fn test_from_accept_language() {
+        assert_eq!(Locale::from_accept_language("fr-BE, fr;q=0.8, en;q=0.5").unwrap().tag, "fr-BE");
+        assert_eq!(Locale::from_accept_language("xx-XX, pl;q=0.9").unwrap().tag, "pl-PL");
+        assert_eq!(Locale::from_accept_language("*"), None);
+    }
+}
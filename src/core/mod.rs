@@ -1,4 +1,8 @@
 // Core module
 
 pub mod currency;
-pub mod network;
\ No newline at end of file
+pub mod error;
+pub mod locale;
+pub mod money;
+pub mod network;
+pub mod rates;
\ No newline at end of file
@@ -0,0 +1,330 @@
+// Fixed-point monetary amounts
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use crate::core::currency::Currency;
+
+/// How to resolve a result that falls between two minor units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    /// Round half away from zero (commercial rounding)
+    HalfUp,
+    /// Round half to the nearest even unit (banker's rounding)
+    HalfEven,
+    /// Truncate toward zero
+    Down,
+    /// Round away from zero
+    Up,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MoneyError {
+    CurrencyMismatch(Currency, Currency),
+    Overflow,
+    InvalidAmount(String),
+    TooManyDecimals(Currency),
+    DivisionByZero,
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch(a, b) => write!(f, "Currency mismatch: {} vs {}", a, b),
+            MoneyError::Overflow => write!(f, "Amount overflow"),
+            MoneyError::InvalidAmount(s) => write!(f, "Invalid amount: {}", s),
+            MoneyError::TooManyDecimals(c) => write!(f, "Too many decimal places for {}", c),
+            MoneyError::DivisionByZero => write!(f, "Division by zero"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// An amount held as an integer number of minor units (e.g. cents) of a currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "MoneyRepr", into = "MoneyRepr")]
+pub struct Money {
+    minor_units: i64,
+    currency: Currency,
+}
+
+// Wire representation: the amount travels as a decimal string so clients never go through f64
+#[derive(Clone, Serialize, Deserialize)]
+struct MoneyRepr {
+    amount: String,
+    currency: Currency,
+}
+
+impl TryFrom<MoneyRepr> for Money {
+    type Error = MoneyError;
+
+    fn try_from(repr: MoneyRepr) -> Result<Self, Self::Error> {
+        Money::parse(&repr.amount, repr.currency)
+    }
+}
+
+impl From<Money> for MoneyRepr {
+    fn from(money: Money) -> Self {
+        Self {
+            amount: money.to_decimal_string(),
+            currency: money.currency,
+        }
+    }
+}
+
+impl Money {
+    pub fn from_minor(minor_units: i64, currency: Currency) -> Self {
+        Self { minor_units, currency }
+    }
+
+    pub fn zero(currency: Currency) -> Self {
+        Self::from_minor(0, currency)
+    }
+
+    pub fn from_major(major_units: i64, currency: Currency) -> Result<Self, MoneyError> {
+        let minor_units = major_units
+            .checked_mul(scale(currency)?)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Self::from_minor(minor_units, currency))
+    }
+
+    /// Parses a decimal string such as `"12.34"`, rejecting more decimals than the currency allows.
+    pub fn parse(s: &str, currency: Currency) -> Result<Self, MoneyError> {
+        let (negative, mantissa, decimals) = parse_decimal(s)?;
+        let places = currency.decimal_places() as u32;
+        if decimals > places {
+            return Err(MoneyError::TooManyDecimals(currency));
+        }
+        let factor = 10i128.checked_pow(places - decimals).ok_or(MoneyError::Overflow)?;
+        let minor = mantissa.checked_mul(factor).ok_or(MoneyError::Overflow)?;
+        Self::from_i128(if negative { -minor } else { minor }, currency)
+    }
+
+    /// Parses a decimal string, rounding any excess decimals with the given mode.
+    pub fn parse_rounded(s: &str, currency: Currency, mode: RoundingMode) -> Result<Self, MoneyError> {
+        let (negative, mantissa, decimals) = parse_decimal(s)?;
+        let places = currency.decimal_places() as u32;
+        let signed = if negative { -mantissa } else { mantissa };
+        let minor = if decimals > places {
+            let divisor = 10i128.checked_pow(decimals - places).ok_or(MoneyError::Overflow)?;
+            divide_rounded(signed, divisor, mode)?
+        } else {
+            let factor = 10i128.checked_pow(places - decimals).ok_or(MoneyError::Overflow)?;
+            signed.checked_mul(factor).ok_or(MoneyError::Overflow)?
+        };
+        Self::from_i128(minor, currency)
+    }
+
+    pub fn minor_units(&self) -> i64 {
+        self.minor_units
+    }
+
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor_units == 0
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.minor_units > 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.minor_units < 0
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.ensure_same_currency(other)?;
+        let minor_units = self.minor_units
+            .checked_add(other.minor_units)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Self::from_minor(minor_units, self.currency))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.ensure_same_currency(other)?;
+        let minor_units = self.minor_units
+            .checked_sub(other.minor_units)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Self::from_minor(minor_units, self.currency))
+    }
+
+    pub fn checked_mul(&self, factor: i64) -> Result<Money, MoneyError> {
+        let minor_units = self.minor_units
+            .checked_mul(factor)
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Self::from_minor(minor_units, self.currency))
+    }
+
+    /// Multiplies by `numerator / denominator`, rounding the result to whole minor units.
+    pub fn checked_mul_ratio(&self, numerator: i128, denominator: i128, mode: RoundingMode) -> Result<Money, MoneyError> {
+        let product = (self.minor_units as i128)
+            .checked_mul(numerator)
+            .ok_or(MoneyError::Overflow)?;
+        let minor = divide_rounded(product, denominator, mode)?;
+        Self::from_i128(minor, self.currency)
+    }
+
+    pub fn to_decimal_string(self) -> String {
+        let places = self.currency.decimal_places() as u32;
+        let sign = if self.minor_units < 0 { "-" } else { "" };
+        let abs = self.minor_units.unsigned_abs();
+        if places == 0 {
+            return format!("{}{}", sign, abs);
+        }
+        let scale = 10u64.pow(places);
+        format!("{}{}.{:0width$}", sign, abs / scale, abs % scale, width = places as usize)
+    }
+
+    fn ensure_same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch(self.currency, other.currency));
+        }
+        Ok(())
+    }
+
+    fn from_i128(minor: i128, currency: Currency) -> Result<Self, MoneyError> {
+        let minor_units = i64::try_from(minor).map_err(|_| MoneyError::Overflow)?;
+        Ok(Self::from_minor(minor_units, currency))
+    }
+}
+
+impl PartialOrd for Money {
+    /// Amounts in different currencies are not comparable.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.currency != other.currency {
+            return None;
+        }
+        Some(self.minor_units.cmp(&other.minor_units))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.to_decimal_string(), self.currency)
+    }
+}
+
+fn scale(currency: Currency) -> Result<i64, MoneyError> {
+    10i64
+        .checked_pow(currency.decimal_places() as u32)
+        .ok_or(MoneyError::Overflow)
+}
+
+// Splits a decimal string into (negative, digits without the point, number of decimals)
+fn parse_decimal(s: &str) -> Result<(bool, i128, u32), MoneyError> {
+    let invalid = || MoneyError::InvalidAmount(s.to_string());
+    let trimmed = s.trim();
+    let (negative, unsigned) = match trimmed.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+    };
+    let (int_part, frac_part) = match unsigned.split_once('.') {
+        Some((i, f)) => (i, f),
+        None => (unsigned, ""),
+    };
+    if int_part.is_empty() || (unsigned.contains('.') && frac_part.is_empty()) {
+        return Err(invalid());
+    }
+    if !int_part.bytes().chain(frac_part.bytes()).all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let mut mantissa: i128 = 0;
+    for b in int_part.bytes().chain(frac_part.bytes()) {
+        mantissa = mantissa
+            .checked_mul(10)
+            .and_then(|m| m.checked_add((b - b'0') as i128))
+            .ok_or(MoneyError::Overflow)?;
+    }
+    Ok((negative, mantissa, frac_part.len() as u32))
+}
+
+pub(crate) fn divide_rounded(value: i128, divisor: i128, mode: RoundingMode) -> Result<i128, MoneyError> {
+    if divisor == 0 {
+        return Err(MoneyError::DivisionByZero);
+    }
+    let quotient = value / divisor;
+    let remainder = value % divisor;
+    if remainder == 0 {
+        return Ok(quotient);
+    }
+
+    // Direction away from zero for the exact result
+    let away = if (value < 0) != (divisor < 0) { -1 } else { 1 };
+    let twice_rem = remainder.abs() * 2;
+    let round_away = match mode {
+        RoundingMode::Down => false,
+        RoundingMode::Up => true,
+        RoundingMode::HalfUp => twice_rem >= divisor.abs(),
+        RoundingMode::HalfEven => match twice_rem.cmp(&divisor.abs()) {
+            Ordering::Greater => true,
+            Ordering::Less => false,
+            Ordering::Equal => quotient % 2 != 0,
+        },
+    };
+    Ok(if round_away { quotient + away } else { quotient })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format() {
+        let money = Money::parse("1234.5", Currency::EUR).unwrap();
+        assert_eq!(money.minor_units(), 123450);
+        assert_eq!(money.to_decimal_string(), "1234.50");
+
+        let isk = Money::parse("1500", Currency::ISK).unwrap();
+        assert_eq!(isk.to_decimal_string(), "1500");
+        assert_eq!(Money::parse("-0.05", Currency::EUR).unwrap().to_decimal_string(), "-0.05");
+    }
+
+    #[test]
+    fn test_parse_rejects_excess_precision() {
+        assert_eq!(Money::parse("1.005", Currency::EUR), Err(MoneyError::TooManyDecimals(Currency::EUR)));
+        assert!(Money::parse("1.", Currency::EUR).is_err());
+        assert!(Money::parse("abc", Currency::EUR).is_err());
+
+        let rounded = Money::parse_rounded("1.005", Currency::EUR, RoundingMode::HalfEven).unwrap();
+        assert_eq!(rounded.minor_units(), 100);
+        let rounded = Money::parse_rounded("1.005", Currency::EUR, RoundingMode::HalfUp).unwrap();
+        assert_eq!(rounded.minor_units(), 101);
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let a = Money::parse("0.10", Currency::EUR).unwrap();
+        let b = Money::parse("0.20", Currency::EUR).unwrap();
+        assert_eq!(a.checked_add(&b).unwrap(), Money::parse("0.30", Currency::EUR).unwrap());
+        assert!(a.checked_sub(&b).unwrap().is_negative());
+
+        let gbp = Money::parse("0.10", Currency::GBP).unwrap();
+        assert!(matches!(a.checked_add(&gbp), Err(MoneyError::CurrencyMismatch(_, _))));
+        assert_eq!(Money::from_minor(i64::MAX, Currency::EUR).checked_mul(2), Err(MoneyError::Overflow));
+    }
+
+    #[test]
+    fn test_mul_ratio_rounding() {
+        let amount = Money::from_minor(25, Currency::EUR);
+        assert_eq!(amount.checked_mul_ratio(1, 10, RoundingMode::HalfUp).unwrap().minor_units(), 3);
+        assert_eq!(amount.checked_mul_ratio(1, 10, RoundingMode::HalfEven).unwrap().minor_units(), 2);
+        assert_eq!(amount.checked_mul_ratio(1, 10, RoundingMode::Down).unwrap().minor_units(), 2);
+        let negative = Money::from_minor(-25, Currency::EUR);
+        assert_eq!(negative.checked_mul_ratio(1, 10, RoundingMode::HalfUp).unwrap().minor_units(), -3);
+    }
+
+    #[test]
+    fn test_serde_uses_decimal_strings() {
+        let money = Money::parse("12.30", Currency::EUR).unwrap();
+        let json = serde_json::to_string(&money).unwrap();
+        assert_eq!(json, r#"{"amount":"12.30","currency":"EUR"}"#);
+        let back: Money = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, money);
+        assert!(serde_json::from_str::<Money>(r#"{"amount":12.3,"currency":"EUR"}"#).is_err());
+    }
+}
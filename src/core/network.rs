@@ -1,8 +1,9 @@
 // Network protocol for Europay nodes
 
 use serde::{Deserialize, Serialize};
+use std::future::Future;
 use uuid::Uuid;
-use crate::core::currency::Currency;
+use crate::core::money::Money;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum NetworkMessage {
@@ -10,33 +11,86 @@ pub enum NetworkMessage {
     TransactionResponse(TransactionResponse),
     SettlementRequest(SettlementRequest),
     SettlementResponse(SettlementResponse),
+    ReversalRequest(ReversalRequest),
+    ReversalResponse(ReversalResponse),
     Heartbeat(Heartbeat),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Dual-message authorizations (01xx) versus single-message financial transactions (02xx).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageClass {
+    Authorization,
+    Financial,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TransactionRequest {
     pub transaction_id: Uuid,
+    pub message_class: MessageClass,
     pub card_id: Uuid,
     pub merchant_id: Uuid,
-    pub amount: f64,
-    pub currency: Currency,
+    #[serde(flatten)]
+    pub amount: Money,
     pub timestamp: u64,
+    pub pan: String,
+    pub processing_code: String,
+    pub stan: String, // System trace audit number
+    pub retrieval_reference: String,
+    pub terminal_id: String,
+    pub card_acceptor_id: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TransactionResponse {
     pub transaction_id: Uuid,
+    pub message_class: MessageClass,
     pub approved: bool,
     pub response_code: String,
+    pub approval_code: Option<String>,
+    pub stan: String,
+    pub retrieval_reference: String,
+    pub timestamp: u64,
+}
+
+/// Cancels an authorization whose outcome the sender never learned, e.g. after a timeout.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReversalRequest {
+    pub transaction_id: Uuid, // Of the original request
+    pub message_class: MessageClass,
+    #[serde(flatten)]
+    pub amount: Money,
+    pub original_stan: String,
+    pub original_timestamp: u64,
+    pub retrieval_reference: String,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReversalResponse {
+    pub transaction_id: Uuid,
+    pub acknowledged: bool,
     pub timestamp: u64,
 }
 
+impl ReversalRequest {
+    pub fn for_request(request: &TransactionRequest, timestamp: u64) -> Self {
+        Self {
+            transaction_id: request.transaction_id,
+            message_class: request.message_class,
+            amount: request.amount,
+            original_stan: request.stan.clone(),
+            original_timestamp: request.timestamp,
+            retrieval_reference: request.retrieval_reference.clone(),
+            timestamp,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SettlementRequest {
     pub batch_id: Uuid,
     pub transactions: Vec<Uuid>,
-    pub total_amount: f64,
-    pub currency: Currency,
+    pub total_amount: Money,
     pub timestamp: u64,
 }
 
@@ -60,7 +114,7 @@ pub struct NetworkNode {
     pub role: NodeRole,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum NodeRole {
     Issuer,
     Acquirer,
@@ -78,6 +132,6 @@ impl NetworkNode {
 }
 
 pub trait NetworkProtocol {
-    async fn send_message(&self, to: &NetworkNode, message: NetworkMessage) -> Result<NetworkMessage, String>;
-    async fn broadcast(&self, message: NetworkMessage) -> Result<(), String>;
+    fn send_message(&self, to: &NetworkNode, message: NetworkMessage) -> impl Future<Output = Result<NetworkMessage, String>> + Send;
+    fn broadcast(&self, message: NetworkMessage) -> impl Future<Output = Result<(), String>> + Send;
 }
\ No newline at end of file
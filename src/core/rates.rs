@@ -0,0 +1,381 @@
+// FX rate providers and timestamped rate snapshots
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+use crate::core::currency::Currency;
+use crate::core::money::{divide_rounded, Money, MoneyError, RoundingMode};
+
+// Precision used for cross rates derived from two quotes against the base currency
+const CROSS_RATE_SCALE: u32 = 10;
+
+/// A decimal exchange rate, `mantissa / 10^scale` units of quote currency per unit of base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Rate {
+    mantissa: i64,
+    scale: u32,
+}
+
+impl Rate {
+    pub fn new(mantissa: i64, scale: u32) -> Result<Self, String> {
+        if mantissa <= 0 {
+            return Err("Rate must be positive".to_string());
+        }
+        if 10i128.checked_pow(scale).is_none_or(|f| f > i64::MAX as i128) {
+            return Err("Rate scale too large".to_string());
+        }
+        Ok(Self { mantissa, scale })
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let (int_part, frac_part) = s.split_once('.').unwrap_or((s, ""));
+        if int_part.is_empty() || !int_part.bytes().chain(frac_part.bytes()).all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid rate: {}", s));
+        }
+        let mantissa = format!("{}{}", int_part, frac_part)
+            .parse::<i64>()
+            .map_err(|_| format!("Invalid rate: {}", s))?;
+        Self::new(mantissa, frac_part.len() as u32)
+    }
+
+    /// Builds a rate from an exact fraction, rounded to `scale` decimal places.
+    pub fn from_ratio(numerator: i128, denominator: i128, scale: u32) -> Result<Self, String> {
+        let factor = 10i128.checked_pow(scale).ok_or("Rate scale too large")?;
+        let scaled = numerator.checked_mul(factor).ok_or("Rate overflow")?;
+        let mantissa = divide_rounded(scaled, denominator, RoundingMode::HalfEven).map_err(|e| e.to_string())?;
+        Self::new(i64::try_from(mantissa).map_err(|_| "Rate overflow")?, scale)
+    }
+
+    pub fn mantissa(&self) -> i64 {
+        self.mantissa
+    }
+
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// This rate increased by `bps` basis points.
+    pub fn with_markup_bps(&self, bps: u32) -> Result<Self, String> {
+        let numerator = (self.mantissa as i128) * (10_000 + bps as i128);
+        let denominator = 10i128.pow(self.scale) * 10_000;
+        Self::from_ratio(numerator, denominator, self.scale.max(CROSS_RATE_SCALE))
+    }
+
+    pub fn inverse(&self) -> Result<Self, String> {
+        Self::from_ratio(10i128.pow(self.scale), self.mantissa as i128, CROSS_RATE_SCALE)
+    }
+
+    /// Converts `amount` into `to` at this rate, adjusting for the currencies' minor units.
+    pub fn apply(&self, amount: &Money, to: Currency, mode: RoundingMode) -> Result<Money, MoneyError> {
+        let from_places = amount.currency().decimal_places() as u32;
+        let to_places = to.decimal_places() as u32;
+        let numerator = (self.mantissa as i128) * 10i128.pow(to_places);
+        let denominator = 10i128.pow(self.scale + from_places);
+        let converted = amount.checked_mul_ratio(numerator, denominator, mode)?;
+        Ok(Money::from_minor(converted.minor_units(), to))
+    }
+}
+
+impl TryFrom<String> for Rate {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Rate::parse(&s)
+    }
+}
+
+impl From<Rate> for String {
+    fn from(rate: Rate) -> Self {
+        rate.to_string()
+    }
+}
+
+impl fmt::Display for Rate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.scale == 0 {
+            return write!(f, "{}", self.mantissa);
+        }
+        let factor = 10i64.pow(self.scale);
+        write!(f, "{}.{:0width$}", self.mantissa / factor, self.mantissa % factor, width = self.scale as usize)
+    }
+}
+
+/// Rates quoted against a single base currency, valid from `effective_at`.
+#[derive(Debug, Clone)]
+pub struct RateSnapshot {
+    pub base: Currency,
+    pub effective_at: DateTime<Utc>,
+    rates: HashMap<Currency, Rate>,
+}
+
+impl RateSnapshot {
+    pub fn new(base: Currency, effective_at: DateTime<Utc>) -> Self {
+        Self {
+            base,
+            effective_at,
+            rates: HashMap::new(),
+        }
+    }
+
+    pub fn with_rate(mut self, quote: Currency, rate: Rate) -> Self {
+        self.insert(quote, rate);
+        self
+    }
+
+    pub fn insert(&mut self, quote: Currency, rate: Rate) {
+        self.rates.insert(quote, rate);
+    }
+
+    /// Units of `to` per unit of `from`, crossing through the base currency when needed.
+    pub fn rate(&self, from: Currency, to: Currency) -> Option<Rate> {
+        if from == to {
+            return Rate::new(1, 0).ok();
+        }
+        if from == self.base {
+            return self.rates.get(&to).copied();
+        }
+        let from_rate = self.rates.get(&from)?;
+        if to == self.base {
+            return from_rate.inverse().ok();
+        }
+        let to_rate = self.rates.get(&to)?;
+        // to/from = (to_m / 10^to_s) / (from_m / 10^from_s)
+        let numerator = (to_rate.mantissa as i128) * 10i128.pow(from_rate.scale);
+        let denominator = (from_rate.mantissa as i128) * 10i128.pow(to_rate.scale);
+        Rate::from_ratio(numerator, denominator, CROSS_RATE_SCALE).ok()
+    }
+}
+
+pub trait RateProvider: Send + Sync {
+    /// The most recent snapshot.
+    fn latest(&self) -> Result<RateSnapshot, String>;
+}
+
+fn latest_snapshot(snapshots: &[RateSnapshot]) -> Result<RateSnapshot, String> {
+    snapshots.iter()
+        .max_by_key(|s| s.effective_at)
+        .cloned()
+        .ok_or_else(|| "No rate snapshot available".to_string())
+}
+
+pub struct InMemoryRateProvider {
+    snapshots: RwLock<Vec<RateSnapshot>>,
+}
+
+impl InMemoryRateProvider {
+    pub fn new() -> Self {
+        Self {
+            snapshots: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn with_snapshot(snapshot: RateSnapshot) -> Self {
+        let provider = Self::new();
+        provider.publish(snapshot);
+        provider
+    }
+
+    pub fn publish(&self, snapshot: RateSnapshot) {
+        let mut snapshots = self.snapshots.write().unwrap_or_else(|e| e.into_inner());
+        snapshots.push(snapshot);
+    }
+}
+
+impl Default for InMemoryRateProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateProvider for InMemoryRateProvider {
+    fn latest(&self) -> Result<RateSnapshot, String> {
+        let snapshots = self.snapshots.read().unwrap_or_else(|e| e.into_inner());
+        latest_snapshot(&snapshots)
+    }
+}
+
+/// Reads ECB euro foreign exchange reference rates from a file in either the XML
+/// (`eurofxref-daily.xml`, `eurofxref-hist.xml`) or CSV (`eurofxref.csv`, `eurofxref-hist.csv`) format.
+pub struct EcbFileRateProvider {
+    path: PathBuf,
+    snapshots: RwLock<Vec<RateSnapshot>>,
+}
+
+impl EcbFileRateProvider {
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, String> {
+        let provider = Self {
+            path: path.into(),
+            snapshots: RwLock::new(Vec::new()),
+        };
+        provider.reload()?;
+        Ok(provider)
+    }
+
+    /// Re-reads the file, e.g. after the daily reference rates have been downloaded again.
+    pub fn reload(&self) -> Result<(), String> {
+        let data = std::fs::read_to_string(&self.path)
+            .map_err(|e| format!("Failed to read {}: {}", self.path.display(), e))?;
+        let parsed = parse_ecb(&data)?;
+        let mut snapshots = self.snapshots.write().unwrap_or_else(|e| e.into_inner());
+        *snapshots = parsed;
+        Ok(())
+    }
+}
+
+impl RateProvider for EcbFileRateProvider {
+    fn latest(&self) -> Result<RateSnapshot, String> {
+        let snapshots = self.snapshots.read().unwrap_or_else(|e| e.into_inner());
+        latest_snapshot(&snapshots)
+    }
+}
+
+pub fn parse_ecb(data: &str) -> Result<Vec<RateSnapshot>, String> {
+    let snapshots = if data.trim_start().starts_with('<') {
+        parse_ecb_xml(data)?
+    } else {
+        parse_ecb_csv(data)?
+    };
+    if snapshots.is_empty() {
+        return Err("No reference rates found".to_string());
+    }
+    Ok(snapshots)
+}
+
+// Reference rates apply from the start of their reference date
+fn reference_date(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+fn parse_ecb_xml(data: &str) -> Result<Vec<RateSnapshot>, String> {
+    let mut snapshots = Vec::new();
+    let mut current: Option<RateSnapshot> = None;
+
+    for tag in data.split('<').skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        if !tag.starts_with("Cube") {
+            continue;
+        }
+        if let Some(time) = xml_attr(tag, "time") {
+            let date = NaiveDate::parse_from_str(time, "%Y-%m-%d")
+                .map_err(|_| format!("Invalid reference date: {}", time))?;
+            snapshots.extend(current.take());
+            current = Some(RateSnapshot::new(Currency::EUR, reference_date(date)));
+        } else if let (Some(code), Some(rate)) = (xml_attr(tag, "currency"), xml_attr(tag, "rate")) {
+            let snapshot = current.as_mut().ok_or("Rate outside of a dated Cube")?;
+            // The ECB publishes currencies we may not carry; skip those
+            if let Some(currency) = Currency::from_code(code) {
+                snapshot.insert(currency, Rate::parse(rate)?);
+            }
+        }
+    }
+    snapshots.extend(current);
+    Ok(snapshots)
+}
+
+fn xml_attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    for quote in ['\'', '"'] {
+        let needle = format!("{}={}", name, quote);
+        if let Some(start) = tag.find(&needle) {
+            let rest = &tag[start + needle.len()..];
+            return rest.find(quote).map(|end| &rest[..end]);
+        }
+    }
+    None
+}
+
+fn parse_ecb_csv(data: &str) -> Result<Vec<RateSnapshot>, String> {
+    let mut lines = data.lines().filter(|l| !l.trim().is_empty());
+    let header: Vec<&str> = lines.next().ok_or("Empty CSV")?.split(',').map(str::trim).collect();
+    if !header.first().is_some_and(|h| h.eq_ignore_ascii_case("date")) {
+        return Err("CSV header must start with Date".to_string());
+    }
+    let columns: Vec<Option<Currency>> = header.iter().skip(1).map(|c| Currency::from_code(c)).collect();
+
+    let mut snapshots = Vec::new();
+    for line in lines {
+        let cells: Vec<&str> = line.split(',').map(str::trim).collect();
+        let date_str = cells.first().copied().unwrap_or_default();
+        // The daily file uses "17 October 2025", the historical file "2025-10-17"
+        let date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+            .or_else(|_| NaiveDate::parse_from_str(date_str, "%d %B %Y"))
+            .map_err(|_| format!("Invalid reference date: {}", date_str))?;
+
+        let mut snapshot = RateSnapshot::new(Currency::EUR, reference_date(date));
+        for (currency, cell) in columns.iter().zip(cells.iter().skip(1)) {
+            if let Some(currency) = currency {
+                if cell.is_empty() || *cell == "N/A" {
+                    continue;
+                }
+                snapshot.insert(*currency, Rate::parse(cell)?);
+            }
+        }
+        snapshots.push(snapshot);
+    }
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECB_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+    <gesmes:subject>Reference rates</gesmes:subject>
+    <Cube>
+        <Cube time='2025-10-17'>
+            <Cube currency='USD' rate='1.1681'/>
+            <Cube currency='GBP' rate='0.86930'/>
+            <Cube currency='IDR' rate='19379.68'/>
+        </Cube>
+        <Cube time='2025-10-16'>
+            <Cube currency='USD' rate='1.1654'/>
+            <Cube currency='GBP' rate='0.86885'/>
+        </Cube>
+    </Cube>
+</gesmes:Envelope>"#;
+
+    #[test]
+    fn test_parse_ecb_xml() {
+        let snapshots = parse_ecb(ECB_XML).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        let latest = latest_snapshot(&snapshots).unwrap();
+        assert_eq!(latest.effective_at.date_naive(), NaiveDate::from_ymd_opt(2025, 10, 17).unwrap());
+        assert_eq!(latest.rate(Currency::EUR, Currency::USD), Some(Rate::parse("1.1681").unwrap()));
+    }
+
+    #[test]
+    fn test_parse_ecb_csv() {
+        let daily = "Date, USD, JPY, GBP, \n17 October 2025, 1.1681, 175.64, 0.86930, \n";
+        let snapshots = parse_ecb(daily).unwrap();
+        assert_eq!(snapshots[0].rate(Currency::EUR, Currency::GBP), Some(Rate::parse("0.86930").unwrap()));
+
+        let hist = "Date,USD,HRK\n2025-10-17,1.1681,N/A\n2022-12-30,1.0666,7.5365\n";
+        let snapshots = parse_ecb(hist).unwrap();
+        let at = reference_date(NaiveDate::from_ymd_opt(2022, 12, 30).unwrap());
+        let old = snapshots.iter().find(|s| s.effective_at == at).unwrap();
+        assert_eq!(old.rate(Currency::EUR, Currency::HRK), Some(Rate::parse("7.5365").unwrap()));
+    }
+
+    #[test]
+    fn test_cross_rate_and_apply() {
+        let snapshot = RateSnapshot::new(Currency::EUR, Utc::now())
+            .with_rate(Currency::GBP, Rate::parse("0.85").unwrap())
+            .with_rate(Currency::ISK, Rate::parse("150").unwrap());
+
+        let gbp_to_isk = snapshot.rate(Currency::GBP, Currency::ISK).unwrap();
+        let amount = Money::parse("8.50", Currency::GBP).unwrap();
+        let converted = gbp_to_isk.apply(&amount, Currency::ISK, RoundingMode::HalfUp).unwrap();
+        assert_eq!(converted, Money::from_minor(1500, Currency::ISK));
+
+        let back = snapshot.rate(Currency::GBP, Currency::EUR).unwrap();
+        let eur = back.apply(&amount, Currency::EUR, RoundingMode::HalfUp).unwrap();
+        assert_eq!(eur.to_decimal_string(), "10.00");
+        assert!(snapshot.rate(Currency::GBP, Currency::USD).is_none());
+    }
+}
@@ -0,0 +1,12 @@
+pub mod config;
+pub mod middlewares;
+pub mod controllers;
+pub mod services;
+pub mod models;
+pub mod routes;
+pub mod utils;
+pub mod core;
+pub mod queries;
+pub mod repositories;
+mod tests;
+mod scripts;
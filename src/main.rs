@@ -1,55 +1,177 @@
-mod config;
-mod middlewares;
-mod controllers;
-mod services;
-mod models;
-mod routes;
-mod utils;
-mod core;
-mod queries;
-mod tests;
-mod scripts;
-
 use axum::Router;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
-use tracing;
+use tracing_subscriber::EnvFilter;
 
-use models::transactions::PaymentProcessor;
-use routes::transactions;
-use routes::settlement;
-use config::Config;
-use middlewares::logging_middleware;
-use services::settlement::SettlementService;
+use europay::models::transactions::PaymentProcessor;
+use europay::routes::{self, transactions, settlement};
+use europay::config::Config;
+use europay::middlewares::logging_middleware;
+use europay::services::idempotency::IdempotencyStore;
+use europay::services::settlement::SettlementService;
+use europay::services::disputes::{DisputeDeadlines, DisputeService};
+use europay::controllers::disputes::DisputeState;
+use europay::controllers::settlement::SettlementState;
+use europay::services::iso8583::{hex_decode, CodecConfig, FieldDictionary, Iso8583Codec};
+use europay::services::iso_server::Iso8583Server;
+use europay::services::network::HttpNetworkService;
+use europay::services::security::SecurityManager;
+use europay::repositories::sqlite::SqliteStore;
+use europay::core::currency::CurrencyConverter;
+use europay::core::error::EuropayError;
+use europay::core::rates::{EcbFileRateProvider, InMemoryRateProvider, RateProvider};
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+    // Initialize tracing; startup warnings are shown unless RUST_LOG says otherwise
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
 
     // Load configuration
-    let config = Config::default();
+    let config = Config::from_env().unwrap_or_else(|e| exit_with(e));
+
+    // Load FX rates; without a rates file cross-currency authorizations are declined
+    let rate_provider: Arc<dyn RateProvider> = match &config.fx.ecb_rates_file {
+        Some(path) => Arc::new(EcbFileRateProvider::load(path)
+            .unwrap_or_else(|e| exit_with(format!("Cannot load FX rates from {}: {}", path, e)))),
+        None => Arc::new(InMemoryRateProvider::new()),
+    };
+    if let Err(e) = rate_provider.latest() {
+        tracing::warn!("No FX rates loaded ({}); DCC quotes and cross-currency authorizations will be declined", e);
+    }
+    let converter = CurrencyConverter::new(rate_provider);
+
+    // Restore state from the configured backend
+    let (processor, settlement_service, dispute_service) = if config.database.url == "memory:" {
+        tracing::warn!("In-memory backend; state is lost when the node stops");
+        (
+            PaymentProcessor::new(converter, config.fx.dcc_markup_bps),
+            SettlementService::new(),
+            DisputeService::new(DisputeDeadlines::default()),
+        )
+    } else {
+        // Card data in the database is encrypted under the configured key; validation
+        // already required one unless the database lives only in memory
+        let security = match &config.security.data_key {
+            Some(key) => hex_decode(key)
+                .and_then(|key| SecurityManager::with_key(&key))
+                .unwrap_or_else(|e| exit_with(format!("Invalid security.data_key: {}", e))),
+            None => SecurityManager::new(),
+        };
+        let store = SqliteStore::open(&config.database.url, security)
+            .unwrap_or_else(|e| exit_with(format!("Cannot open {}: {}", config.database.url, e)));
+        let restore = || -> Result<_, EuropayError> {
+            Ok((
+                PaymentProcessor::load(converter, config.fx.dcc_markup_bps, store.repositories())?,
+                SettlementService::load(Box::new(store.batches()), Box::new(store.adjustments()))?,
+                DisputeService::load(DisputeDeadlines::default(), Box::new(store.disputes()))?,
+            ))
+        };
+        restore().unwrap_or_else(|e| exit_with(format!("Cannot restore state from {}: {}", config.database.url, e)))
+    };
 
     // Create shared state
-    let processor = Arc::new(Mutex::new(PaymentProcessor::new()));
-    let settlement_service = Arc::new(Mutex::new(SettlementService::new()));
+    let processor = Arc::new(Mutex::new(processor));
+    let settlement_service = Arc::new(Mutex::new(settlement_service));
+    let dispute_service = Arc::new(Mutex::new(dispute_service));
+    let dispute_state = DisputeState {
+        processor: processor.clone(),
+        disputes: dispute_service.clone(),
+        settlement: settlement_service.clone(),
+    };
+    let idempotency = Arc::new(Mutex::new(IdempotencyStore::new(chrono::Duration::seconds(config.idempotency.window_secs as i64))));
+    let settlement_state = SettlementState {
+        processor: processor.clone(),
+        settlement: settlement_service.clone(),
+    };
+
+    // Lapse authorizations left uncaptured past their window
+    {
+        let processor = processor.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(600));
+            loop {
+                ticker.tick().await;
+                let expired = processor.lock().await.expire_authorizations(chrono::Utc::now());
+                if !expired.is_empty() {
+                    tracing::info!("Expired {} authorizations", expired.len());
+                }
+            }
+        });
+    }
+
+    // Forget idempotency keys once their replay window has passed
+    {
+        let idempotency = idempotency.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(600));
+            loop {
+                ticker.tick().await;
+                idempotency.lock().await.purge_expired(chrono::Utc::now());
+            }
+        });
+    }
+
+    // Decide disputes whose deadline passed without a response
+    {
+        let (processor, disputes, settlement) = (processor.clone(), dispute_service.clone(), settlement_service.clone());
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(3600));
+            loop {
+                ticker.tick().await;
+                let mut processor = processor.lock().await;
+                let mut disputes = disputes.lock().await;
+                let mut settlement = settlement.lock().await;
+                if let Err(e) = disputes.expire_overdue(&mut processor, &mut settlement, chrono::Utc::now()) {
+                    tracing::warn!("Dispute expiry failed: {}", e);
+                }
+            }
+        });
+    }
+
+    // Reverse authorizations that issuers leave unanswered
+    let network_service = Arc::new(HttpNetworkService::new(
+        std::time::Duration::from_millis(config.network.authorization_timeout_ms),
+        std::time::Duration::from_millis(config.network.reversal_retry_ms),
+    ));
+    tokio::spawn(network_service.clone().run_reversal_worker(std::time::Duration::from_secs(1)));
 
     // Build the application
     let app = Router::new()
         .route("/health", axum::routing::get(|| async { "OK" }))
-        .nest("/transactions", transactions::create_routes(processor.clone()))
-        .nest("/network", routes::network::create_routes())
-        .nest("/settlement", settlement::create_routes(settlement_service.clone()))
+        .nest("/transactions", transactions::create_routes(processor.clone(), idempotency.clone()))
+        .nest("/accounts", routes::accounts::create_routes(processor.clone()))
+        .nest("/cards", routes::cards::create_routes(processor.clone()))
+        .nest("/merchants", routes::merchants::create_routes(processor.clone()))
+        .nest("/network", routes::network::create_routes(network_service.clone()))
+        .nest("/settlement", settlement::create_routes(settlement_state, idempotency.clone()))
+        .nest("/disputes", routes::disputes::create_routes(dispute_state))
         .layer(axum::middleware::from_fn(logging_middleware))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
+    // Run the ISO 8583 listener alongside the HTTP API
+    let iso_addr = SocketAddr::from((config.server.host.parse::<std::net::IpAddr>().unwrap(), config.server.iso8583_port));
+    let iso_listener = tokio::net::TcpListener::bind(iso_addr).await.unwrap();
+    tracing::info!("ISO 8583 listening on {}", iso_addr);
+    let codec_config = if config.server.iso8583_bcd { CodecConfig::bcd() } else { CodecConfig::ascii() };
+    let codec = Iso8583Codec::new(FieldDictionary::iso8583_1987().clone(), codec_config);
+    let iso_server = Arc::new(Iso8583Server::new(processor.clone(), codec));
+    tokio::spawn(iso_server.run(iso_listener));
+
     // Run the server
     let addr = SocketAddr::from((config.server.host.parse::<std::net::IpAddr>().unwrap(), config.server.port));
     tracing::info!("Listening on {}", addr);
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
     axum::serve(listener, app).await.unwrap();
 }
+
+/// Logs why the node cannot start and exits.
+fn exit_with(message: impl std::fmt::Display) -> ! {
+    tracing::error!("{}", message);
+    std::process::exit(1)
+}
\ No newline at end of file
@@ -1,13 +1,24 @@
 // Middleware module
 
 use axum::{
-    extract::Request,
-    http::StatusCode,
+    body::{to_bytes, Body},
+    extract::{OriginalUri, Request, State},
+    http::{header, HeaderValue, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
+use std::sync::Arc;
+use tokio::sync::Mutex;
 use tracing::info;
 
+use crate::core::error::EuropayError;
+use crate::services::idempotency::{IdempotencyStore, StoredResponse};
+
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024; // axum's default request body limit
+
 pub async fn logging_middleware(request: Request, next: Next) -> Response {
     let method = request.method().clone();
     let uri = request.uri().clone();
@@ -19,4 +30,104 @@ pub async fn logging_middleware(request: Request, next: Next) -> Response {
     info!("Response: {}", response.status());
 
     response
+}
+
+/// Honours the `Idempotency-Key` header on mutating requests: the first response for a key
+/// is stored and replayed for retries with the same body. Server errors are not stored, so
+/// the request can be retried under the same key.
+pub async fn idempotency_middleware(
+    State(store): State<Arc<Mutex<IdempotencyStore>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY) else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= 255 => key.to_string(),
+        _ => return EuropayError::InvalidRequest("Invalid Idempotency-Key header".to_string()).into_response(),
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(body) => body,
+        Err(_) => return EuropayError::InvalidRequest("Request body too large".to_string()).into_response(),
+    };
+    let path = parts.extensions.get::<OriginalUri>().map_or_else(|| parts.uri.path().to_string(), |uri| uri.path().to_string());
+    let fingerprint = IdempotencyStore::fingerprint(parts.method.as_str(), &path, &body);
+
+    match store.lock().await.begin(&key, &fingerprint, chrono::Utc::now()) {
+        Ok(Some(stored)) => return replay(stored),
+        Ok(None) => {}
+        Err(e) => return e.into_response(),
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status().is_server_error() {
+        store.lock().await.abandon(&key);
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            store.lock().await.abandon(&key);
+            return EuropayError::Internal(e.to_string()).into_response();
+        }
+    };
+    store.lock().await.complete(&key, StoredResponse {
+        status: parts.status.as_u16(),
+        content_type: parts.headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).map(str::to_string),
+        body: body.to_vec(),
+    });
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn replay(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    if let Some(content_type) = stored.content_type.and_then(|value| HeaderValue::from_str(&value).ok()) {
+        response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    response.headers_mut().insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware::from_fn_with_state, routing::post, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_idempotency_key_replays_response_and_rejects_other_body() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let store = Arc::new(Mutex::new(IdempotencyStore::new(chrono::Duration::hours(24))));
+        let counter = calls.clone();
+        let app = Router::new()
+            .route("/authorize", post(move |body: String| async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                (StatusCode::CREATED, body)
+            }))
+            .route_layer(from_fn_with_state(store, idempotency_middleware));
+        let request = |body: &'static str| {
+            Request::post("/authorize").header(IDEMPOTENCY_KEY, "retry-1").body(Body::from(body)).unwrap()
+        };
+
+        let first = app.clone().oneshot(request("4.00")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(first.headers().get(IDEMPOTENT_REPLAYED).is_none());
+
+        let replayed = app.clone().oneshot(request("4.00")).await.unwrap();
+        assert_eq!(replayed.status(), StatusCode::CREATED);
+        assert_eq!(replayed.headers().get(IDEMPOTENT_REPLAYED).unwrap(), "true");
+        assert_eq!(to_bytes(replayed.into_body(), usize::MAX).await.unwrap(), "4.00");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let conflict = app.oneshot(request("40.00")).await.unwrap();
+        assert_eq!(conflict.status(), StatusCode::CONFLICT);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
 }
\ No newline at end of file
@@ -3,13 +3,17 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 use crate::core::currency::Currency;
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Account {
     pub id: Uuid,
     pub holder_name: String,
-    pub balance: f64,
+    pub ledger_balance: Money, // Derived from the ledger; includes funds held for authorizations
+    pub holds: HashMap<Uuid, Money>, // Transaction id -> amount reserved for it
     pub currency: Currency,
     pub created_at: DateTime<Utc>,
     pub status: AccountStatus,
@@ -27,23 +31,75 @@ impl Account {
         Self {
             id: Uuid::new_v4(),
             holder_name,
-            balance: 0.0,
+            ledger_balance: Money::zero(currency),
+            holds: HashMap::new(),
             currency,
             created_at: Utc::now(),
             status: AccountStatus::Active,
         }
     }
 
-    pub fn debit(&mut self, amount: f64) -> Result<(), String> {
-        if self.balance >= amount {
-            self.balance -= amount;
-            Ok(())
+    /// Freezing stops new authorizations but keeps the funds; closing is final and needs
+    /// the account emptied first.
+    pub fn set_status(&mut self, status: AccountStatus) -> Result<(), EuropayError> {
+        if self.status == AccountStatus::Closed && status != AccountStatus::Closed {
+            return Err(EuropayError::InvalidState("Account is closed".to_string()));
+        }
+        if status == AccountStatus::Closed && (!self.holds.is_empty() || !self.ledger_balance.is_zero()) {
+            return Err(EuropayError::InvalidState("Account still holds funds".to_string()));
+        }
+        self.status = status;
+        Ok(())
+    }
+
+    pub fn held_amount(&self) -> Result<Money, EuropayError> {
+        self.holds.values()
+            .try_fold(Money::zero(self.currency), |total, hold| total.checked_add(hold))
+            .map_err(EuropayError::from)
+    }
+
+    /// Ledger balance less everything held for open authorizations.
+    pub fn available_balance(&self) -> Result<Money, EuropayError> {
+        Ok(self.ledger_balance.checked_sub(&self.held_amount()?)?)
+    }
+
+    /// Reserves `amount` for a transaction, replacing any hold it already has.
+    pub fn place_hold(&mut self, tx_id: Uuid, amount: &Money) -> Result<(), EuropayError> {
+        if !self.can_debit(Some(tx_id), amount)? {
+            return Err(EuropayError::InsufficientFunds);
+        }
+        self.holds.insert(tx_id, *amount);
+        Ok(())
+    }
+
+    /// Reserves `amount` even if it exceeds the available balance, e.g. for a stand-in approval.
+    pub fn force_hold(&mut self, tx_id: Uuid, amount: &Money) {
+        self.holds.insert(tx_id, *amount);
+    }
+
+    pub fn release_hold(&mut self, tx_id: Uuid) -> Option<Money> {
+        self.holds.remove(&tx_id)
+    }
+
+    /// Reduces the transaction's hold once `amount` of it has been captured.
+    pub fn consume_hold(&mut self, tx_id: Uuid, amount: &Money) -> Result<(), EuropayError> {
+        let Some(hold) = self.holds.get(&tx_id).copied() else {
+            return Ok(());
+        };
+        let remaining = hold.checked_sub(amount)?;
+        if remaining.is_positive() {
+            self.holds.insert(tx_id, remaining);
         } else {
-            Err("Insufficient funds".to_string())
+            self.holds.remove(&tx_id);
         }
+        Ok(())
     }
 
-    pub fn credit(&mut self, amount: f64) {
-        self.balance += amount;
+    /// Whether `amount` can be taken from the account, counting the transaction's own hold
+    /// as available to it.
+    pub fn can_debit(&self, tx_id: Option<Uuid>, amount: &Money) -> Result<bool, EuropayError> {
+        let own_hold = tx_id.and_then(|id| self.holds.get(&id)).copied().unwrap_or(Money::zero(self.currency));
+        let available = self.available_balance()?.checked_add(&own_hold)?;
+        Ok(!available.checked_sub(amount)?.is_negative())
     }
-}
\ No newline at end of file
+}
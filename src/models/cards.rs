@@ -4,14 +4,20 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 
+use crate::core::error::EuropayError;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaymentCard {
     pub id: Uuid,
     pub account_id: Uuid,
-    pub pan: String, // Primary Account Number (masked for security)
+    // Never serialized: stores keep the PAN encrypted beside the document, and the CVV is
+    // not kept once the card is issued
+    #[serde(skip_serializing, default)]
+    pub pan: String, // Primary Account Number
     pub expiry_month: u8,
     pub expiry_year: u16,
-    pub cvv: String, // In real system, this would be hashed/encrypted
+    #[serde(skip_serializing, default)]
+    pub cvv: String,
     pub cardholder_name: String,
     pub status: CardStatus,
     pub issued_at: DateTime<Utc>,
@@ -39,6 +45,27 @@ impl PaymentCard {
         }
     }
 
+    /// The PAN as it may be shown: first six and last four digits only.
+    pub fn masked_pan(&self) -> String {
+        let len = self.pan.len();
+        if len <= 10 {
+            return "*".repeat(len);
+        }
+        format!("{}{}{}", &self.pan[..6], "*".repeat(len - 10), &self.pan[len - 4..])
+    }
+
+    /// Blocks or unblocks the card. Expiry follows from the date and cannot be undone.
+    pub fn set_status(&mut self, status: CardStatus) -> Result<(), EuropayError> {
+        if self.status == CardStatus::Expired && status != CardStatus::Expired {
+            return Err(EuropayError::InvalidState("Card has expired".to_string()));
+        }
+        if status == CardStatus::Active && self.is_expired() {
+            return Err(EuropayError::CardExpired);
+        }
+        self.status = status;
+        Ok(())
+    }
+
     pub fn is_expired(&self) -> bool {
         let now = Utc::now();
         let expiry_date = chrono::NaiveDate::from_ymd_opt(self.expiry_year as i32, self.expiry_month as u32, 1)
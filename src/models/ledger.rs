@@ -0,0 +1,256 @@
+// Double-entry ledger behind account balances and settlement
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::core::currency::Currency;
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
+use crate::repositories::memory::InMemoryLedgerRepository;
+use crate::repositories::LedgerRepository;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LedgerAccountType {
+    Cardholder,         // Owned by a cardholder account
+    Merchant,           // Owed to a merchant by its acquirer
+    IssuerSettlement,   // Cardholder funds awaiting settlement with acquirers
+    AcquirerSettlement, // Merchant funds awaiting settlement from the issuer, per acquirer (the owner)
+    Fees,               // Revenue, e.g. DCC markup
+    Fx,                 // Currency position from cross-currency transactions
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerAccount {
+    pub id: Uuid,
+    pub account_type: LedgerAccountType,
+    pub owner_id: Option<Uuid>,
+    pub currency: Currency,
+    pub opened_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Debit,
+    Credit,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Posting {
+    pub ledger_account_id: Uuid,
+    pub side: Side,
+    pub amount: Money, // Never negative
+}
+
+impl Posting {
+    pub fn debit(ledger_account_id: Uuid, amount: Money) -> Self {
+        Self { ledger_account_id, side: Side::Debit, amount }
+    }
+
+    pub fn credit(ledger_account_id: Uuid, amount: Money) -> Self {
+        Self { ledger_account_id, side: Side::Credit, amount }
+    }
+
+    /// The same movement in the opposite direction.
+    pub fn reversed(&self) -> Self {
+        let side = match self.side {
+            Side::Debit => Side::Credit,
+            Side::Credit => Side::Debit,
+        };
+        Self { side, ..self.clone() }
+    }
+}
+
+/// A set of postings whose debits equal its credits in every currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: Uuid,
+    pub description: String,
+    pub reference: Option<Uuid>, // e.g. the transaction or settlement batch
+    pub postings: Vec<Posting>,
+    pub posted_at: DateTime<Utc>,
+}
+
+/// Running balances over the journal, which is written through to a repository as entries are posted.
+pub struct Ledger {
+    accounts: HashMap<Uuid, LedgerAccount>,
+    index: HashMap<(LedgerAccountType, Option<Uuid>, Currency), Uuid>,
+    balances: HashMap<Uuid, Money>,
+    repository: Box<dyn LedgerRepository>,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self {
+            accounts: HashMap::new(),
+            index: HashMap::new(),
+            balances: HashMap::new(),
+            repository: Box::new(InMemoryLedgerRepository::default()),
+        }
+    }
+
+    /// Restores the ledger from its repository, replaying the journal to rebuild the balances.
+    pub fn load(repository: Box<dyn LedgerRepository>) -> Result<Self, EuropayError> {
+        let mut ledger = Self {
+            repository,
+            ..Self::new()
+        };
+        for account in ledger.repository.accounts()? {
+            ledger.index.insert((account.account_type, account.owner_id, account.currency), account.id);
+            ledger.balances.insert(account.id, Money::zero(account.currency));
+            ledger.accounts.insert(account.id, account);
+        }
+        for entry in ledger.repository.entries()? {
+            for posting in &entry.postings {
+                let balance = ledger.balance(posting.ledger_account_id);
+                let balance = match posting.side {
+                    Side::Debit => balance.checked_sub(&posting.amount),
+                    Side::Credit => balance.checked_add(&posting.amount),
+                }?;
+                ledger.balances.insert(posting.ledger_account_id, balance);
+            }
+        }
+        Ok(ledger)
+    }
+
+    /// The ledger account of this type, owner and currency, opening it on first use.
+    pub fn account_id(&mut self, account_type: LedgerAccountType, owner_id: Option<Uuid>, currency: Currency) -> Uuid {
+        if let Some(id) = self.index.get(&(account_type, owner_id, currency)) {
+            return *id;
+        }
+        let account = LedgerAccount {
+            id: Uuid::new_v4(),
+            account_type,
+            owner_id,
+            currency,
+            opened_at: Utc::now(),
+        };
+        let id = account.id;
+        self.index.insert((account_type, owner_id, currency), id);
+        self.balances.insert(id, Money::zero(currency));
+        self.accounts.insert(id, account);
+        id
+    }
+
+    /// Records a journal entry, rejecting it unless it balances in every currency.
+    pub fn post(&mut self, description: &str, reference: Option<Uuid>, postings: Vec<Posting>) -> Result<Uuid, EuropayError> {
+        // Zero postings carry no information, e.g. an empty fee leg
+        let postings: Vec<Posting> = postings.into_iter().filter(|p| !p.amount.is_zero()).collect();
+        if postings.is_empty() {
+            return Err(EuropayError::Ledger("Journal entry has no postings".to_string()));
+        }
+
+        let mut totals: HashMap<Currency, i128> = HashMap::new();
+        for posting in &postings {
+            let account = self.accounts.get(&posting.ledger_account_id).ok_or_else(|| EuropayError::Ledger("Ledger account not found".to_string()))?;
+            if account.currency != posting.amount.currency() {
+                return Err(EuropayError::Ledger(format!("Posting in {} to a {} account", posting.amount.currency(), account.currency)));
+            }
+            if posting.amount.is_negative() {
+                return Err(EuropayError::Ledger("Posting amount must not be negative".to_string()));
+            }
+            let signed = match posting.side {
+                Side::Debit => posting.amount.minor_units() as i128,
+                Side::Credit => -(posting.amount.minor_units() as i128),
+            };
+            *totals.entry(posting.amount.currency()).or_insert(0) += signed;
+        }
+        if let Some((currency, _)) = totals.iter().find(|(_, total)| **total != 0) {
+            return Err(EuropayError::Ledger(format!("Journal entry does not balance in {}", currency)));
+        }
+
+        // Balances are credits less debits: what is owed to the account's owner
+        let mut updated: HashMap<Uuid, Money> = HashMap::new();
+        for posting in &postings {
+            let id = posting.ledger_account_id;
+            let balance = updated.get(&id).copied().unwrap_or_else(|| self.balance(id));
+            let balance = match posting.side {
+                Side::Debit => balance.checked_sub(&posting.amount),
+                Side::Credit => balance.checked_add(&posting.amount),
+            }?;
+            updated.insert(id, balance);
+        }
+
+        let entry = JournalEntry {
+            id: Uuid::new_v4(),
+            description: description.to_string(),
+            reference,
+            postings,
+            posted_at: Utc::now(),
+        };
+        // Stored before it takes effect, so a failed write leaves the ledger unchanged
+        let accounts: Vec<LedgerAccount> = updated.keys().filter_map(|id| self.accounts.get(id)).cloned().collect();
+        self.repository.append(&accounts, &entry)?;
+
+        self.balances.extend(updated);
+        Ok(entry.id)
+    }
+
+    pub fn balance(&self, ledger_account_id: Uuid) -> Money {
+        self.balances.get(&ledger_account_id).copied()
+            .or_else(|| self.accounts.get(&ledger_account_id).map(|a| Money::zero(a.currency)))
+            .unwrap_or(Money::zero(Currency::EUR))
+    }
+
+    pub fn balance_of(&self, account_type: LedgerAccountType, owner_id: Option<Uuid>, currency: Currency) -> Money {
+        self.index.get(&(account_type, owner_id, currency))
+            .map_or(Money::zero(currency), |id| self.balance(*id))
+    }
+
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, EuropayError> {
+        self.repository.entries()
+    }
+
+    pub fn entries_for(&self, reference: Uuid) -> Result<Vec<JournalEntry>, EuropayError> {
+        Ok(self.entries()?.into_iter().filter(|entry| entry.reference == Some(reference)).collect())
+    }
+
+    /// Recomputes every balance from the journal and checks it matches the running balance.
+    pub fn verify(&self) -> Result<(), EuropayError> {
+        let mut derived: HashMap<Uuid, i128> = HashMap::new();
+        for posting in self.entries()?.iter().flat_map(|entry| &entry.postings) {
+            let signed = match posting.side {
+                Side::Debit => -(posting.amount.minor_units() as i128),
+                Side::Credit => posting.amount.minor_units() as i128,
+            };
+            *derived.entry(posting.ledger_account_id).or_insert(0) += signed;
+        }
+        for (id, balance) in &self.balances {
+            if derived.get(id).copied().unwrap_or(0) != balance.minor_units() as i128 {
+                return Err(EuropayError::Ledger(format!("Balance of ledger account {} does not match its postings", id)));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Ledger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unbalanced_entries_are_rejected() {
+        let mut ledger = Ledger::new();
+        let owner = Some(Uuid::new_v4());
+        let cardholder = ledger.account_id(LedgerAccountType::Cardholder, owner, Currency::EUR);
+        let issuer = ledger.account_id(LedgerAccountType::IssuerSettlement, None, Currency::EUR);
+        let fx = ledger.account_id(LedgerAccountType::Fx, None, Currency::GBP);
+        let eur = |s: &str| Money::parse(s, Currency::EUR).unwrap();
+
+        ledger.post("Deposit", None, vec![Posting::debit(issuer, eur("100.00")), Posting::credit(cardholder, eur("100.00"))]).unwrap();
+        assert!(ledger.post("Bad", None, vec![Posting::debit(issuer, eur("5.00")), Posting::credit(cardholder, eur("4.99"))]).is_err());
+        assert!(ledger.post("Wrong currency", None, vec![Posting::debit(fx, eur("1.00")), Posting::credit(cardholder, eur("1.00"))]).is_err());
+
+        assert_eq!(ledger.balance(cardholder), eur("100.00"));
+        assert_eq!(ledger.balance_of(LedgerAccountType::IssuerSettlement, None, Currency::EUR), eur("-100.00"));
+        assert_eq!(ledger.entries().unwrap().len(), 1);
+        ledger.verify().unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+// Transaction lifecycle: the allowed status transitions and their audit trail
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use uuid::Uuid;
+
+use crate::core::error::EuropayError;
+use crate::models::transactions::{Transaction, TransactionStatus, TransactionType};
+use crate::repositories::memory::InMemoryTransitionRepository;
+use crate::repositories::TransitionRepository;
+
+/// Who caused a transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Actor {
+    Issuer,   // Authorization decisions
+    Acquirer, // Captures, reversals and refunds sent on the merchant's behalf
+    Network,  // Stand-in approvals, chargebacks and settlement
+    System,   // Scheduled jobs such as authorization expiry
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransitionError {
+    pub transaction_id: Uuid,
+    pub from: TransactionStatus,
+    pub to: TransactionStatus,
+}
+
+impl fmt::Display for TransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Transaction cannot go from {:?} to {:?}", self.from, self.to)
+    }
+}
+
+impl std::error::Error for TransitionError {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransitionEvent {
+    pub transaction_id: Uuid,
+    pub from: TransactionStatus,
+    pub to: TransactionStatus,
+    pub actor: Actor,
+    pub reason: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl Transaction {
+    /// Whether the transaction may move to `to`. Purchases are authorized before they are
+    /// captured; refunds and chargebacks are booked as captured when they are created.
+    pub fn can_transition_to(&self, to: TransactionStatus) -> bool {
+        use TransactionStatus::*;
+        match self.transaction_type {
+            TransactionType::Purchase => matches!(
+                (self.status, to),
+                (Pending, Authorized) | (Pending, Declined)
+                    | (Authorized, Captured) | (Authorized, Reversed) | (Authorized, Expired)
+                    | (Captured, Settled) | (Captured, Reversed)
+            ),
+            TransactionType::Refund | TransactionType::Chargeback => {
+                matches!((self.status, to), (Pending, Captured) | (Captured, Settled))
+            }
+        }
+    }
+
+    pub fn check_transition(&self, to: TransactionStatus) -> Result<(), TransitionError> {
+        if self.can_transition_to(to) {
+            Ok(())
+        } else {
+            Err(TransitionError { transaction_id: self.id, from: self.status, to })
+        }
+    }
+}
+
+/// Applies status transitions and keeps every one of them per transaction, written through
+/// to a repository.
+pub struct TransitionLog {
+    events: HashMap<Uuid, Vec<TransitionEvent>>,
+    repository: Box<dyn TransitionRepository>,
+}
+
+impl TransitionLog {
+    pub fn new() -> Self {
+        Self {
+            events: HashMap::new(),
+            repository: Box::new(InMemoryTransitionRepository::default()),
+        }
+    }
+
+    /// Restores the log from its repository.
+    pub fn load(repository: Box<dyn TransitionRepository>) -> Result<Self, EuropayError> {
+        let mut log = Self {
+            repository,
+            ..Self::new()
+        };
+        for event in log.repository.all()? {
+            log.events.entry(event.transaction_id).or_default().push(event);
+        }
+        Ok(log)
+    }
+
+    pub fn apply(&mut self, transaction: &mut Transaction, to: TransactionStatus, actor: Actor, reason: &str, now: DateTime<Utc>) -> Result<(), EuropayError> {
+        transaction.check_transition(to)?;
+        let event = TransitionEvent {
+            transaction_id: transaction.id,
+            from: transaction.status,
+            to,
+            actor,
+            reason: reason.to_string(),
+            occurred_at: now,
+        };
+        self.repository.append(&event)?;
+        self.events.entry(transaction.id).or_default().push(event);
+        transaction.status = to;
+        transaction.processed_at = Some(now);
+        Ok(())
+    }
+
+    /// The transaction's transitions, oldest first.
+    pub fn events_for(&self, transaction_id: Uuid) -> &[TransitionEvent] {
+        self.events.get(&transaction_id).map_or(&[], |events| events.as_slice())
+    }
+}
+
+impl Default for TransitionLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
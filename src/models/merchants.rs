@@ -2,7 +2,9 @@
 
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+
+use crate::core::error::EuropayError;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Merchant {
@@ -10,6 +12,7 @@ pub struct Merchant {
     pub name: String,
     pub category: String,
     pub acquirer_id: Uuid, // Bank that processes for merchant
+    pub card_acceptor_id: String, // ISO 8583 field 42
     pub status: MerchantStatus,
     pub registered_at: DateTime<Utc>,
 }
@@ -23,13 +26,50 @@ pub enum MerchantStatus {
 
 impl Merchant {
     pub fn new(name: String, category: String, acquirer_id: Uuid) -> Self {
+        let id = Uuid::new_v4();
         Self {
-            id: Uuid::new_v4(),
+            id,
             name,
             category,
             acquirer_id,
+            card_acceptor_id: id.simple().to_string()[..15].to_uppercase(),
             status: MerchantStatus::Active,
             registered_at: Utc::now(),
         }
     }
-}
\ No newline at end of file
+}
+
+// Merchant category codes whose authorizations may stay open for longer, as (first, last, days)
+const EXTENDED_AUTHORIZATION_MCCS: &[(u16, u16, i64)] = &[
+    (3351, 3500, 31), // Car rental agencies
+    (3501, 3999, 31), // Lodging
+    (4411, 4411, 31), // Cruise lines
+    (7011, 7011, 31), // Lodging
+    (7512, 7512, 31), // Car rental agencies
+    (7513, 7513, 31), // Truck and trailer rentals
+];
+
+const DEFAULT_AUTHORIZATION_DAYS: i64 = 7;
+
+/// How long an authorization at a merchant of this category code stays valid before it expires.
+pub fn authorization_window(mcc: &str) -> Duration {
+    let days = mcc.parse::<u16>().ok()
+        .and_then(|code| EXTENDED_AUTHORIZATION_MCCS.iter().find(|(first, last, _)| (*first..=*last).contains(&code)))
+        .map_or(DEFAULT_AUTHORIZATION_DAYS, |(_, _, days)| *days);
+    Duration::days(days)
+}
+
+impl Merchant {
+    /// Suspended merchants are declined until reinstated; closing is final.
+    pub fn set_status(&mut self, status: MerchantStatus) -> Result<(), EuropayError> {
+        if self.status == MerchantStatus::Closed && status != MerchantStatus::Closed {
+            return Err(EuropayError::InvalidState("Merchant is closed".to_string()));
+        }
+        self.status = status;
+        Ok(())
+    }
+
+    pub fn authorization_window(&self) -> Duration {
+        authorization_window(&self.category)
+    }
+}
@@ -3,4 +3,6 @@
 pub mod accounts;
 pub mod cards;
 pub mod transactions;
-pub mod merchants;
\ No newline at end of file
+pub mod merchants;
+pub mod ledger;
+pub mod lifecycle;
\ No newline at end of file
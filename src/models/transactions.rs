@@ -3,11 +3,18 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
-use crate::models::accounts::Account;
-use crate::models::cards::PaymentCard;
-use crate::models::merchants::Merchant;
+use crate::models::accounts::{Account, AccountStatus};
+use crate::models::cards::{CardStatus, PaymentCard};
+use crate::models::ledger::{Ledger, LedgerAccountType, Posting};
+use crate::models::lifecycle::{Actor, TransitionEvent, TransitionLog};
+use crate::models::merchants::{Merchant, MerchantStatus};
+use crate::repositories::{AccountRepository, CaptureRepository, CardRepository, MerchantRepository, ReferenceRepository, Repositories, TransactionRepository};
+use crate::services::messaging::IdentifierResolver;
 use crate::services::security::SecurityManager;
-use crate::core::currency::Currency;
+use crate::core::currency::{CurrencyConverter, DccQuote};
+use crate::core::error::EuropayError;
+use crate::core::money::{Money, RoundingMode};
+use crate::utils::validate_amount;
 use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,15 +22,23 @@ pub struct Transaction {
     pub id: Uuid,
     pub card_id: Uuid,
     pub merchant_id: Uuid,
-    pub amount: f64,
-    pub currency: Currency,
+    #[serde(flatten)]
+    pub amount: Money,
+    pub billing_amount: Money, // Amount charged to the cardholder, in the account currency
+    pub dcc: Option<DccQuote>,
+    pub reversed_amount: Money, // Total reversed so far; `amount` is what remains
+    pub captured_amount: Money,
+    pub refunded_amount: Money,
+    pub original_transaction_id: Option<Uuid>, // The purchase a refund belongs to
     pub status: TransactionStatus,
+    pub approval_code: Option<String>, // Field 38 of an approved authorization
     pub transaction_type: TransactionType,
     pub created_at: DateTime<Utc>,
     pub processed_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>, // When an uncaptured authorization lapses
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TransactionStatus {
     Pending,
     Authorized,
@@ -31,6 +46,7 @@ pub enum TransactionStatus {
     Settled,
     Declined,
     Reversed,
+    Expired,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -40,109 +56,1088 @@ pub enum TransactionType {
     Chargeback,
 }
 
+/// The issuer's answer to an authorization request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthorizationDecision {
+    Approved(Uuid),
+    Declined(Uuid, EuropayError), // The declined transaction and the reason
+}
+
+/// One capture against an authorization; an authorization may be captured in several parts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Capture {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    #[serde(flatten)]
+    pub amount: Money,
+    pub billing_amount: Money,
+    pub is_final: bool,
+    pub captured_at: DateTime<Utc>,
+}
+
 impl Transaction {
-    pub fn new(card_id: Uuid, merchant_id: Uuid, amount: f64, currency: Currency, transaction_type: TransactionType) -> Self {
+    pub fn new(card_id: Uuid, merchant_id: Uuid, amount: Money, transaction_type: TransactionType) -> Self {
         Self {
             id: Uuid::new_v4(),
             card_id,
             merchant_id,
             amount,
-            currency,
+            billing_amount: amount,
+            dcc: None,
+            reversed_amount: Money::zero(amount.currency()),
+            captured_amount: Money::zero(amount.currency()),
+            refunded_amount: Money::zero(amount.currency()),
+            original_transaction_id: None,
             status: TransactionStatus::Pending,
+            approval_code: None,
             transaction_type,
             created_at: Utc::now(),
             processed_at: None,
+            expires_at: None,
         }
     }
 }
 
+// Where the processor writes its records through to
+struct Store {
+    accounts: Box<dyn AccountRepository>,
+    cards: Box<dyn CardRepository>,
+    merchants: Box<dyn MerchantRepository>,
+    transactions: Box<dyn TransactionRepository>,
+    captures: Box<dyn CaptureRepository>,
+    network_references: Box<dyn ReferenceRepository>,
+    advices: Box<dyn ReferenceRepository>,
+}
+
+/// Processes card transactions. Its state is held in memory and written through to the
+/// repositories on every change; the in-memory copy is what the processor reads.
 pub struct PaymentProcessor {
     accounts: HashMap<Uuid, Account>,
     cards: HashMap<Uuid, PaymentCard>,
     merchants: HashMap<Uuid, Merchant>,
     transactions: HashMap<Uuid, Transaction>,
+    captures: HashMap<Uuid, Capture>,
     security: SecurityManager,
+    converter: CurrencyConverter,
+    dcc_markup_bps: u32,
+    network_references: HashMap<String, Uuid>, // original data elements reference -> transaction
+    advices: HashMap<String, Uuid>,            // applied store-and-forward advices
+    ledger: Ledger,
+    transitions: TransitionLog,
+    store: Store,
 }
 
 impl PaymentProcessor {
-    pub fn new() -> Self {
+    /// A processor that keeps nothing beyond the process's lifetime.
+    pub fn new(converter: CurrencyConverter, dcc_markup_bps: u32) -> Self {
+        // Nothing to restore from empty in-memory repositories
+        Self::load(converter, dcc_markup_bps, Repositories::in_memory()).expect("in-memory repositories do not fail")
+    }
+
+    /// Restores the processor from its repositories.
+    pub fn load(converter: CurrencyConverter, dcc_markup_bps: u32, repositories: Repositories) -> Result<Self, EuropayError> {
+        let Repositories { accounts, cards, merchants, transactions, captures, network_references, advices, transitions, ledger } = repositories;
+        let store = Store { accounts, cards, merchants, transactions, captures, network_references, advices };
+        let mut processor = Self::with_store(converter, dcc_markup_bps, store, Ledger::load(ledger)?, TransitionLog::load(transitions)?);
+        for mut account in processor.store.accounts.all()? {
+            account.ledger_balance = processor.ledger.balance_of(LedgerAccountType::Cardholder, Some(account.id), account.currency);
+            processor.accounts.insert(account.id, account);
+        }
+        processor.cards.extend(processor.store.cards.all()?.into_iter().map(|card| (card.id, card)));
+        processor.merchants.extend(processor.store.merchants.all()?.into_iter().map(|merchant| (merchant.id, merchant)));
+        processor.transactions.extend(processor.store.transactions.all()?.into_iter().map(|tx| (tx.id, tx)));
+        processor.captures.extend(processor.store.captures.all()?.into_iter().map(|capture| (capture.id, capture)));
+        processor.network_references.extend(processor.store.network_references.all()?);
+        processor.advices.extend(processor.store.advices.all()?);
+        Ok(processor)
+    }
+
+    fn with_store(converter: CurrencyConverter, dcc_markup_bps: u32, store: Store, ledger: Ledger, transitions: TransitionLog) -> Self {
         Self {
             accounts: HashMap::new(),
             cards: HashMap::new(),
             merchants: HashMap::new(),
             transactions: HashMap::new(),
+            captures: HashMap::new(),
             security: SecurityManager::new(),
+            converter,
+            dcc_markup_bps,
+            network_references: HashMap::new(),
+            advices: HashMap::new(),
+            ledger,
+            transitions,
+            store,
         }
     }
 
-    pub fn add_account(&mut self, account: Account) {
+    pub fn add_account(&mut self, mut account: Account) -> Result<(), EuropayError> {
+        account.ledger_balance = self.ledger.balance_of(LedgerAccountType::Cardholder, Some(account.id), account.currency);
+        self.store.accounts.save(&account)?;
         self.accounts.insert(account.id, account);
+        Ok(())
     }
 
-    pub fn add_card(&mut self, card: PaymentCard) {
+    /// Funds a cardholder account from the issuer's settlement account.
+    pub fn deposit(&mut self, account_id: Uuid, amount: Money) -> Result<Uuid, EuropayError> {
+        let amount = validate_amount(amount)?;
+        let account = self.accounts.get_mut(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        if account.status == AccountStatus::Closed {
+            return Err(EuropayError::InvalidState("Account is closed".to_string()));
+        }
+        if amount.currency() != account.currency {
+            return Err(EuropayError::CurrencyMismatch);
+        }
+        let cardholder = self.ledger.account_id(LedgerAccountType::Cardholder, Some(account_id), account.currency);
+        let issuer = self.ledger.account_id(LedgerAccountType::IssuerSettlement, None, account.currency);
+        let entry_id = self.ledger.post("Deposit", Some(account_id), vec![Posting::debit(issuer, amount), Posting::credit(cardholder, amount)])?;
+        account.ledger_balance = self.ledger.balance(cardholder);
+        self.save_account(account_id)?;
+        Ok(entry_id)
+    }
+
+    pub fn ledger(&self) -> &Ledger {
+        &self.ledger
+    }
+
+    /// For postings between settlement accounts; cardholder postings go through the
+    /// processor so that account balances stay in step with the ledger.
+    pub fn ledger_mut(&mut self) -> &mut Ledger {
+        &mut self.ledger
+    }
+
+    pub fn add_card(&mut self, card: PaymentCard) -> Result<(), EuropayError> {
+        self.store.cards.save(&card)?;
         self.cards.insert(card.id, card);
+        Ok(())
     }
 
-    pub fn add_merchant(&mut self, merchant: Merchant) {
+    pub fn add_merchant(&mut self, merchant: Merchant) -> Result<(), EuropayError> {
+        self.store.merchants.save(&merchant)?;
         self.merchants.insert(merchant.id, merchant);
+        Ok(())
     }
 
-    pub fn authorize_transaction(&mut self, card_id: Uuid, merchant_id: Uuid, amount: f64, currency: &Currency) -> Result<Uuid, String> {
-        let card = self.cards.get(&card_id).ok_or("Card not found")?;
-        let merchant = self.merchants.get(&merchant_id).ok_or("Merchant not found")?;
-        let account = self.accounts.get(&card.account_id).ok_or("Account not found")?;
+    /// Issues a card on an open account. A PAN can only be issued once.
+    pub fn issue_card(&mut self, card: PaymentCard) -> Result<Uuid, EuropayError> {
+        let account = self.accounts.get(&card.account_id).ok_or(EuropayError::AccountNotFound)?;
+        if account.status == AccountStatus::Closed {
+            return Err(EuropayError::InvalidState("Account is closed".to_string()));
+        }
+        if self.cards.values().any(|existing| existing.pan == card.pan) {
+            return Err(EuropayError::InvalidState("Card number already issued".to_string()));
+        }
+        let card_id = card.id;
+        self.add_card(card)?;
+        Ok(card_id)
+    }
 
-        if card.status != crate::models::cards::CardStatus::Active {
-            return Err("Card not active".to_string());
+    /// Closing an account blocks its cards; it must have no funds or holds left.
+    pub fn update_account_status(&mut self, account_id: Uuid, status: AccountStatus) -> Result<(), EuropayError> {
+        let account = self.accounts.get_mut(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        account.set_status(status.clone())?;
+        self.save_account(account_id)?;
+        if status == AccountStatus::Closed {
+            for card in self.cards.values_mut().filter(|card| card.account_id == account_id && card.status == CardStatus::Active) {
+                card.status = CardStatus::Blocked;
+                self.store.cards.save(card)?;
+            }
         }
-        if card.is_expired() {
-            return Err("Card expired".to_string());
+        Ok(())
+    }
+
+    pub fn update_card_status(&mut self, card_id: Uuid, status: CardStatus) -> Result<(), EuropayError> {
+        let card = self.cards.get_mut(&card_id).ok_or(EuropayError::CardNotFound)?;
+        if status == CardStatus::Active && self.accounts.get(&card.account_id).is_none_or(|account| account.status == AccountStatus::Closed) {
+            return Err(EuropayError::InvalidState("Account is closed".to_string()));
+        }
+        card.set_status(status)?;
+        self.save_card(card_id)
+    }
+
+    pub fn update_merchant_status(&mut self, merchant_id: Uuid, status: MerchantStatus) -> Result<(), EuropayError> {
+        let merchant = self.merchants.get_mut(&merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+        merchant.set_status(status)?;
+        self.save_merchant(merchant_id)
+    }
+
+    /// Authorizes a purchase; a decline is returned as the error.
+    pub fn authorize_transaction(&mut self, card_id: Uuid, merchant_id: Uuid, amount: Money) -> Result<Uuid, EuropayError> {
+        match self.authorize(card_id, merchant_id, amount)? {
+            AuthorizationDecision::Approved(tx_id) => Ok(tx_id),
+            AuthorizationDecision::Declined(_, reason) => Err(reason),
+        }
+    }
+
+    /// Decides an authorization. Approvals and declines are both recorded as transactions;
+    /// an error means the request itself was invalid and nothing was recorded.
+    pub fn authorize(&mut self, card_id: Uuid, merchant_id: Uuid, amount: Money) -> Result<AuthorizationDecision, EuropayError> {
+        let mut transaction = self.build_authorization(card_id, merchant_id, amount)?;
+        let account_id = self.cards.get(&card_id).ok_or(EuropayError::CardNotFound)?.account_id;
+        let tx_id = transaction.id;
+
+        match self.hold_authorized_funds(&mut transaction, account_id) {
+            Ok(()) => {
+                self.transitions.apply(&mut transaction, TransactionStatus::Authorized, Actor::Issuer, "Approved", Utc::now())?;
+                transaction.approval_code = Some(tx_id.simple().to_string()[..6].to_uppercase());
+                self.transactions.insert(tx_id, transaction);
+                self.save_account(account_id)?;
+                self.save_transaction(tx_id)?;
+                Ok(AuthorizationDecision::Approved(tx_id))
+            }
+            Err(reason) if reason.is_decline() => {
+                self.transitions.apply(&mut transaction, TransactionStatus::Declined, Actor::Issuer, &reason.to_string(), Utc::now())?;
+                transaction.expires_at = None;
+                self.transactions.insert(tx_id, transaction);
+                self.save_transaction(tx_id)?;
+                Ok(AuthorizationDecision::Declined(tx_id, reason))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Runs the issuer's checks and holds the funds, so that concurrent authorizations
+    // cannot spend them twice
+    fn hold_authorized_funds(&mut self, transaction: &mut Transaction, account_id: Uuid) -> Result<(), EuropayError> {
+        self.check_authorization(transaction)?;
+        let card = self.cards.get(&transaction.card_id).ok_or(EuropayError::CardNotFound)?;
+        if self.security.check_fraud(&transaction.amount, &card.pan) {
+            return Err(EuropayError::FraudSuspected);
         }
-        if account.balance < amount {
-            return Err("Insufficient funds".to_string());
+        let account = self.accounts.get_mut(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        account.place_hold(transaction.id, &transaction.billing_amount)
+    }
+
+    /// Raises the authorized amount of an open authorization, e.g. when a hotel stay or car
+    /// rental is extended. The authorization's validity restarts from now.
+    pub fn increment_authorization(&mut self, tx_id: Uuid, additional: Money) -> Result<(), EuropayError> {
+        let additional = validate_amount(additional)?;
+        let transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        if transaction.status != TransactionStatus::Authorized {
+            return Err(EuropayError::InvalidState("Transaction not authorized".to_string()));
+        }
+        if transaction.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+            return Err(EuropayError::InvalidState("Authorization expired".to_string()));
         }
-        if self.security.check_fraud(amount, &card.pan) {
-            return Err("Transaction flagged for fraud".to_string());
+        if additional.currency() != transaction.amount.currency() {
+            return Err(EuropayError::CurrencyMismatch);
         }
+        self.check_parties(transaction.card_id, transaction.merchant_id)?;
 
-        let mut transaction = Transaction::new(card_id, merchant_id, amount, currency.clone(), TransactionType::Purchase);
-        transaction.status = TransactionStatus::Authorized;
+        // Bill the increment at the rate of the original authorization
+        let amount = transaction.amount.checked_add(&additional)?;
+        let billing_amount = billing_share(transaction, &amount)?;
+        let captured_billing = billing_share(transaction, &transaction.captured_amount)?;
+
+        let card = self.cards.get(&transaction.card_id).ok_or(EuropayError::CardNotFound)?;
+        let merchant = self.merchants.get(&transaction.merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+        if self.security.check_fraud(&amount, &card.pan) {
+            return Err(EuropayError::FraudSuspected);
+        }
+        let window = merchant.authorization_window();
+        let outstanding = billing_amount.checked_sub(&captured_billing)?;
+        let account_id = card.account_id;
+        let account = self.accounts.get_mut(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        account.place_hold(tx_id, &outstanding)?;
+
+        let transaction = self.transactions.get_mut(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        transaction.amount = amount;
+        transaction.billing_amount = billing_amount;
         transaction.processed_at = Some(Utc::now());
+        transaction.expires_at = Some(Utc::now() + window);
+        self.save_account(account_id)?;
+        self.save_transaction(tx_id)
+    }
+
+    /// Lapses authorizations left uncaptured past their merchant category's window and
+    /// releases their hold. Partially captured ones are completed at the captured amount.
+    /// Returns the authorizations that were closed; any that fail are logged and left open.
+    pub fn expire_authorizations(&mut self, now: DateTime<Utc>) -> Vec<Uuid> {
+        let due: Vec<Uuid> = self.transactions.values()
+            .filter(|t| t.status == TransactionStatus::Authorized && t.expires_at.is_some_and(|expires_at| expires_at <= now))
+            .map(|t| t.id)
+            .collect();
+
+        due.into_iter()
+            .filter(|tx_id| match self.expire_authorization(*tx_id, now) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Could not expire authorization {}: {}", tx_id, e);
+                    false
+                }
+            })
+            .collect()
+    }
+
+    fn expire_authorization(&mut self, tx_id: Uuid, now: DateTime<Utc>) -> Result<(), EuropayError> {
+        let mut transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?.clone();
+        let account_id = self.cards.get(&transaction.card_id).ok_or(EuropayError::CardNotFound)?.account_id;
+        if transaction.captured_amount.is_zero() {
+            self.transitions.apply(&mut transaction, TransactionStatus::Expired, Actor::System, "Authorization window elapsed", now)?;
+        } else {
+            // The uncaptured remainder is released as a partial reversal, as on a final capture
+            let released = transaction.amount.checked_sub(&transaction.captured_amount)?;
+            transaction.billing_amount = billing_share(&transaction, &transaction.captured_amount)?;
+            transaction.reversed_amount = transaction.reversed_amount.checked_add(&released)?;
+            transaction.amount = transaction.captured_amount;
+            self.transitions.apply(&mut transaction, TransactionStatus::Captured, Actor::System, "Authorization window elapsed; completed at captured amount", now)?;
+        }
+
+        let account = self.accounts.get_mut(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        account.release_hold(tx_id);
+        self.transactions.insert(tx_id, transaction);
+        self.save_account(account_id)?;
+        self.save_transaction(tx_id)
+    }
+
+    /// Records an authorization approved on our behalf (stand-in), reported by a 0120 advice.
+    /// The advice cannot be declined, so funds and fraud checks are skipped; replays of the
+    /// same advice return the transaction recorded the first time.
+    pub fn record_authorization_advice(&mut self, advice_reference: &str, card_id: Uuid, merchant_id: Uuid, amount: Money) -> Result<Uuid, EuropayError> {
+        if let Some(tx_id) = self.advices.get(advice_reference) {
+            return Ok(*tx_id);
+        }
+
+        let mut transaction = self.prepare_authorization(card_id, merchant_id, amount)?;
+        let card = self.cards.get(&card_id).ok_or(EuropayError::CardNotFound)?;
+        let account = self.accounts.get_mut(&card.account_id).ok_or(EuropayError::AccountNotFound)?;
+        account.force_hold(transaction.id, &transaction.billing_amount);
+        self.transitions.apply(&mut transaction, TransactionStatus::Authorized, Actor::Network, "Stand-in approval advice", Utc::now())?;
 
         let tx_id = transaction.id;
+        let account_id = card.account_id;
         self.transactions.insert(tx_id, transaction);
+        self.save_account(account_id)?;
+        self.save_transaction(tx_id)?;
+        self.store.advices.save(advice_reference, tx_id)?;
+        self.advices.insert(advice_reference.to_string(), tx_id);
         Ok(tx_id)
     }
 
-    pub fn capture_transaction(&mut self, tx_id: Uuid) -> Result<(), String> {
-        let transaction = self.transactions.get_mut(&tx_id).ok_or("Transaction not found")?;
-        if transaction.status != TransactionStatus::Authorized {
-            return Err("Transaction not authorized".to_string());
+    // Validates an authorization and builds the pending transaction, quoting DCC if needed
+    fn prepare_authorization(&self, card_id: Uuid, merchant_id: Uuid, amount: Money) -> Result<Transaction, EuropayError> {
+        let mut transaction = self.build_authorization(card_id, merchant_id, amount)?;
+        self.check_authorization(&mut transaction)?;
+        Ok(transaction)
+    }
+
+    // The pending transaction for a well-formed request naming a known card and merchant
+    fn build_authorization(&self, card_id: Uuid, merchant_id: Uuid, amount: Money) -> Result<Transaction, EuropayError> {
+        let amount = validate_amount(amount)?;
+        let card = self.cards.get(&card_id).ok_or(EuropayError::CardNotFound)?;
+        let merchant = self.merchants.get(&merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+        self.accounts.get(&card.account_id).ok_or(EuropayError::AccountNotFound)?;
+
+        let mut transaction = Transaction::new(card_id, merchant_id, amount, TransactionType::Purchase);
+        transaction.expires_at = Some(transaction.created_at + merchant.authorization_window());
+        Ok(transaction)
+    }
+
+    // The checks that decline an authorization. Cross-currency purchases are billed in the
+    // account currency at a DCC rate
+    /// The card, its account and the merchant must all be active, and the card unexpired.
+    fn check_parties(&self, card_id: Uuid, merchant_id: Uuid) -> Result<(), EuropayError> {
+        let card = self.cards.get(&card_id).ok_or(EuropayError::CardNotFound)?;
+        let merchant = self.merchants.get(&merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+        let account = self.accounts.get(&card.account_id).ok_or(EuropayError::AccountNotFound)?;
+
+        if card.status != CardStatus::Active {
+            return Err(EuropayError::CardNotActive);
+        }
+        if card.is_expired() {
+            return Err(EuropayError::CardExpired);
+        }
+        if account.status != AccountStatus::Active {
+            return Err(EuropayError::AccountNotActive);
+        }
+        if merchant.status != MerchantStatus::Active {
+            return Err(EuropayError::MerchantNotActive);
         }
+        Ok(())
+    }
 
-        let card = self.cards.get(&transaction.card_id).ok_or("Card not found")?;
-        let account = self.accounts.get_mut(&card.account_id).ok_or("Account not found")?;
+    fn check_authorization(&self, transaction: &mut Transaction) -> Result<(), EuropayError> {
+        let amount = transaction.amount;
+        if !amount.currency().is_active_on(Utc::now().date_naive()) {
+            return Err(EuropayError::CurrencyUnavailable("Currency withdrawn".to_string()));
+        }
+        self.check_parties(transaction.card_id, transaction.merchant_id)?;
+        let card = self.cards.get(&transaction.card_id).ok_or(EuropayError::CardNotFound)?;
+        let account = self.accounts.get(&card.account_id).ok_or(EuropayError::AccountNotFound)?;
 
-        account.debit(transaction.amount)?;
-        transaction.status = TransactionStatus::Captured;
-        transaction.processed_at = Some(Utc::now());
+        if amount.currency() != account.currency {
+            let quote = self.converter.quote_dcc(&amount, account.currency, self.dcc_markup_bps).map_err(EuropayError::CurrencyUnavailable)?;
+            transaction.billing_amount = quote.cardholder_amount;
+            transaction.dcc = Some(quote);
+        }
         Ok(())
     }
 
-    pub fn settle_transaction(&mut self, tx_id: Uuid) -> Result<(), String> {
-        let transaction = self.transactions.get_mut(&tx_id).ok_or("Transaction not found")?;
-        if transaction.status != TransactionStatus::Captured {
-            return Err("Transaction not captured".to_string());
+    /// Reverses the whole remaining amount. Reversing an already reversed transaction is a no-op.
+    pub fn reverse_transaction(&mut self, tx_id: Uuid) -> Result<(), EuropayError> {
+        let transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        if transaction.status == TransactionStatus::Reversed {
+            return Ok(());
+        }
+        self.reverse_to(tx_id, Money::zero(transaction.amount.currency()))
+    }
+
+    /// Reduces the transaction to `actual_amount`, e.g. when the final purchase was smaller
+    /// than authorized. Repeating the same partial reversal is a no-op.
+    pub fn partially_reverse_transaction(&mut self, tx_id: Uuid, actual_amount: Money) -> Result<(), EuropayError> {
+        let transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        if actual_amount == transaction.amount {
+            return Ok(());
         }
+        self.reverse_to(tx_id, actual_amount)
+    }
+
+    fn reverse_to(&mut self, tx_id: Uuid, actual_amount: Money) -> Result<(), EuropayError> {
+        let transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?.clone();
+        transaction.check_transition(TransactionStatus::Reversed)?;
+        if actual_amount.currency() != transaction.amount.currency() {
+            return Err(EuropayError::CurrencyMismatch);
+        }
+        if actual_amount.is_negative() || actual_amount > transaction.amount {
+            return Err(EuropayError::InvalidAmount("Replacement amount exceeds original".to_string()));
+        }
+        if actual_amount < transaction.refunded_amount {
+            return Err(EuropayError::InvalidAmount("Replacement amount below refunded amount".to_string()));
+        }
+        // Money already taken by partial captures has to be refunded, not reversed
+        if transaction.status == TransactionStatus::Authorized && actual_amount < transaction.captured_amount {
+            return Err(EuropayError::InvalidAmount("Replacement amount below captured amount".to_string()));
+        }
+
+        // The billing amount shrinks in proportion; a full reversal releases all of it
+        let reversed = transaction.amount.checked_sub(&actual_amount)?;
+        let billing_amount = billing_share(&transaction, &actual_amount)?;
+        let released = transaction.billing_amount.checked_sub(&billing_amount)?;
 
-        // In real system, transfer funds to merchant's acquirer
-        // For simplicity, just mark as settled
-        transaction.status = TransactionStatus::Settled;
+        if transaction.status == TransactionStatus::Captured {
+            self.post_card_movement("Reversal", &transaction, &reversed, &released, true)?;
+        } else {
+            // Shrink the hold to what is still authorized and not yet captured
+            let captured_billing = billing_share(&transaction, &transaction.captured_amount)?;
+            let outstanding = billing_amount.checked_sub(&captured_billing)?;
+            let account_id = self.cards.get(&transaction.card_id).ok_or(EuropayError::CardNotFound)?.account_id;
+            let account = self.accounts.get_mut(&account_id).ok_or(EuropayError::AccountNotFound)?;
+            if outstanding.is_positive() {
+                account.force_hold(tx_id, &outstanding);
+            } else {
+                account.release_hold(tx_id);
+            }
+            self.save_account(account_id)?;
+        }
+
+        let transaction = self.transactions.get_mut(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        transaction.reversed_amount = transaction.reversed_amount.checked_add(&reversed)?;
+        transaction.amount = actual_amount;
+        transaction.billing_amount = billing_amount;
+        if transaction.status == TransactionStatus::Captured {
+            transaction.captured_amount = actual_amount;
+        }
+        if actual_amount.is_zero() {
+            self.transitions.apply(transaction, TransactionStatus::Reversed, Actor::Acquirer, "Reversed in full", Utc::now())?;
+        }
         transaction.processed_at = Some(Utc::now());
+        self.save_transaction(tx_id)
+    }
+
+    /// Refunds part or all of a captured purchase to the cardholder. Refunds may be repeated
+    /// until the captured amount is used up; each is recorded as its own transaction.
+    pub fn refund_transaction(&mut self, original_id: Uuid, amount: Money) -> Result<Uuid, EuropayError> {
+        let amount = validate_amount(amount)?;
+        let original = self.transactions.get(&original_id).ok_or(EuropayError::TransactionNotFound)?;
+        if original.transaction_type != TransactionType::Purchase {
+            return Err(EuropayError::InvalidState("Only purchases can be refunded".to_string()));
+        }
+        if !matches!(original.status, TransactionStatus::Captured | TransactionStatus::Settled) {
+            return Err(EuropayError::InvalidState("Transaction not captured".to_string()));
+        }
+        if amount.currency() != original.amount.currency() {
+            return Err(EuropayError::CurrencyMismatch);
+        }
+        let refunded = original.refunded_amount.checked_add(&amount)?;
+        if refunded > original.amount {
+            return Err(EuropayError::InvalidAmount("Refund exceeds captured amount".to_string()));
+        }
+
+        // Bill each refund at the purchase's rate; taking the difference of cumulative
+        // amounts keeps the refunds summing to the billed total
+        let billed_after = billing_share(original, &refunded)?;
+        let billed_before = billing_share(original, &original.refunded_amount)?;
+        let billing_amount = billed_after.checked_sub(&billed_before)?;
+
+        let mut refund = Transaction::new(original.card_id, original.merchant_id, amount, TransactionType::Refund);
+        refund.billing_amount = billing_amount;
+        refund.dcc = original.dcc.clone();
+        refund.original_transaction_id = Some(original_id);
+
+        self.post_card_movement("Refund", &refund, &amount, &billing_amount, true)?;
+        self.transitions.apply(&mut refund, TransactionStatus::Captured, Actor::Acquirer, "Refund booked", Utc::now())?;
+
+        let original = self.transactions.get_mut(&original_id).ok_or(EuropayError::TransactionNotFound)?;
+        original.refunded_amount = refunded;
+        let refund_id = refund.id;
+        self.transactions.insert(refund_id, refund);
+        self.save_transaction(original_id)?;
+        self.save_transaction(refund_id)?;
+        Ok(refund_id)
+    }
+
+    /// Records a chargeback against a settled purchase as its own transaction. Its funds are
+    /// posted by [`Self::post_chargeback`] as the dispute decides where they belong.
+    pub fn record_chargeback(&mut self, original_id: Uuid, amount: Money) -> Result<Uuid, EuropayError> {
+        let original = self.transactions.get(&original_id).ok_or(EuropayError::TransactionNotFound)?;
+        let billed = billing_share(original, &amount)?;
+        let mut chargeback = Transaction::new(original.card_id, original.merchant_id, amount, TransactionType::Chargeback);
+        chargeback.billing_amount = billed;
+        chargeback.dcc = original.dcc.clone();
+        chargeback.original_transaction_id = Some(original_id);
+        self.transitions.apply(&mut chargeback, TransactionStatus::Captured, Actor::Network, "Chargeback booked", Utc::now())?;
+
+        let chargeback_id = chargeback.id;
+        self.transactions.insert(chargeback_id, chargeback);
+        self.save_transaction(chargeback_id)?;
+        Ok(chargeback_id)
+    }
+
+    /// Posts a chargeback's funds back to the cardholder, or on to the merchant again when
+    /// the acquirer wins them back.
+    pub fn post_chargeback(&mut self, chargeback_id: Uuid, to_merchant: bool, description: &str) -> Result<(), EuropayError> {
+        let chargeback = self.transactions.get(&chargeback_id).ok_or(EuropayError::TransactionNotFound)?.clone();
+        if chargeback.transaction_type != TransactionType::Chargeback {
+            return Err(EuropayError::InvalidState("Not a chargeback".to_string()));
+        }
+        self.post_card_movement(description, &chargeback, &chargeback.amount, &chargeback.billing_amount, !to_merchant)
+    }
+
+    /// Remembers which transaction a network request created, so that reversals carrying
+    /// its original data elements (field 90) can find it.
+    pub fn record_network_reference(&mut self, reference: String, tx_id: Uuid) -> Result<(), EuropayError> {
+        self.store.network_references.save(&reference, tx_id)?;
+        self.network_references.entry(reference).or_insert(tx_id);
         Ok(())
     }
 
+    pub fn transaction_for_network_reference(&self, reference: &str) -> Option<Uuid> {
+        self.network_references.get(reference).copied()
+    }
+
+    /// Captures whatever remains of the authorization in full.
+    pub fn capture_transaction(&mut self, tx_id: Uuid) -> Result<Uuid, EuropayError> {
+        let transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        let remaining = transaction.amount.checked_sub(&transaction.captured_amount)?;
+        self.capture_partial(tx_id, remaining, true)
+    }
+
+    /// Captures part of an authorization, e.g. one shipment of an order. The final capture
+    /// completes the transaction and releases whatever was authorized but not captured.
+    pub fn capture_partial(&mut self, tx_id: Uuid, amount: Money, is_final: bool) -> Result<Uuid, EuropayError> {
+        let transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?.clone();
+        transaction.check_transition(TransactionStatus::Captured)?;
+        if amount.currency() != transaction.amount.currency() {
+            return Err(EuropayError::CurrencyMismatch);
+        }
+        if amount.is_negative() || (amount.is_zero() && !is_final) {
+            return Err(EuropayError::InvalidAmount("Amount must be positive".to_string()));
+        }
+        let captured = transaction.captured_amount.checked_add(&amount)?;
+        if captured > transaction.amount {
+            return Err(EuropayError::InvalidAmount("Capture exceeds authorized amount".to_string()));
+        }
+
+        let billed_after = billing_share(&transaction, &captured)?;
+        let billed_before = billing_share(&transaction, &transaction.captured_amount)?;
+        let billing_amount = billed_after.checked_sub(&billed_before)?;
+
+        let account_id = self.cards.get(&transaction.card_id).ok_or(EuropayError::CardNotFound)?.account_id;
+        let account = self.accounts.get(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        if !account.can_debit(Some(tx_id), &billing_amount)? {
+            return Err(EuropayError::InsufficientFunds);
+        }
+        self.post_card_movement("Capture", &transaction, &amount, &billing_amount, false)?;
+
+        let account = self.accounts.get_mut(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        account.consume_hold(tx_id, &billing_amount)?;
+        let transaction = self.transactions.get_mut(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        transaction.captured_amount = captured;
+        if is_final {
+            account.release_hold(tx_id);
+            // The uncaptured remainder is released as a partial reversal of the authorization
+            let released = transaction.amount.checked_sub(&captured)?;
+            transaction.reversed_amount = transaction.reversed_amount.checked_add(&released)?;
+            transaction.amount = captured;
+            transaction.billing_amount = billed_after;
+            self.transitions.apply(transaction, TransactionStatus::Captured, Actor::Acquirer, "Final capture", Utc::now())?;
+        }
+        transaction.processed_at = Some(Utc::now());
+        self.save_account(account_id)?;
+        self.save_transaction(tx_id)?;
+
+        let capture = Capture {
+            id: Uuid::new_v4(),
+            transaction_id: tx_id,
+            amount,
+            billing_amount,
+            is_final,
+            captured_at: Utc::now(),
+        };
+        let capture_id = capture.id;
+        self.store.captures.save(&capture)?;
+        self.captures.insert(capture_id, capture);
+        Ok(capture_id)
+    }
+
+    pub fn captures_for(&self, tx_id: Uuid) -> Vec<&Capture> {
+        let mut captures: Vec<&Capture> = self.captures.values().filter(|c| c.transaction_id == tx_id).collect();
+        captures.sort_by_key(|c| c.captured_at);
+        captures
+    }
+
+    pub fn settle_transaction(&mut self, tx_id: Uuid) -> Result<(), EuropayError> {
+        let transaction = self.transactions.get_mut(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+
+        // The funds move to the acquirer with its settlement batch
+        self.transitions.apply(transaction, TransactionStatus::Settled, Actor::Network, "Settled", Utc::now())?;
+        self.save_transaction(tx_id)
+    }
+
     pub fn get_transaction(&self, tx_id: Uuid) -> Option<&Transaction> {
         self.transactions.get(&tx_id)
     }
-}
\ No newline at end of file
+
+    /// The transaction's status transitions, oldest first.
+    pub fn transitions_for(&self, tx_id: Uuid) -> &[TransitionEvent] {
+        self.transitions.events_for(tx_id)
+    }
+
+    pub fn get_account(&self, account_id: Uuid) -> Option<&Account> {
+        self.accounts.get(&account_id)
+    }
+
+    pub fn get_card(&self, card_id: Uuid) -> Option<&PaymentCard> {
+        self.cards.get(&card_id)
+    }
+
+    pub fn get_merchant(&self, merchant_id: Uuid) -> Option<&Merchant> {
+        self.merchants.get(&merchant_id)
+    }
+
+    pub fn accounts(&self) -> impl Iterator<Item = &Account> {
+        self.accounts.values()
+    }
+
+    pub fn cards(&self) -> impl Iterator<Item = &PaymentCard> {
+        self.cards.values()
+    }
+
+    pub fn merchants(&self) -> impl Iterator<Item = &Merchant> {
+        self.merchants.values()
+    }
+
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.transactions.values()
+    }
+
+    // Posts `amount` of the transaction, billed to the cardholder as `billing`, from the
+    // cardholder to the merchant (or back when `to_cardholder`) and refreshes the balance
+    fn post_card_movement(&mut self, description: &str, transaction: &Transaction, amount: &Money, billing: &Money, to_cardholder: bool) -> Result<(), EuropayError> {
+        if amount.is_zero() && billing.is_zero() {
+            return Ok(());
+        }
+        let card = self.cards.get(&transaction.card_id).ok_or(EuropayError::CardNotFound)?;
+        let merchant = self.merchants.get(&transaction.merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+        let account = self.accounts.get_mut(&card.account_id).ok_or(EuropayError::AccountNotFound)?;
+        let mut postings = card_postings(&mut self.ledger, account, merchant, transaction, amount, billing)?;
+        if to_cardholder {
+            postings = postings.iter().map(Posting::reversed).collect();
+        }
+        self.ledger.post(description, Some(transaction.id), postings)?;
+        account.ledger_balance = self.ledger.balance_of(LedgerAccountType::Cardholder, Some(account.id), account.currency);
+        self.store.accounts.save(account)
+    }
+
+    fn save_account(&mut self, account_id: Uuid) -> Result<(), EuropayError> {
+        let account = self.accounts.get(&account_id).ok_or(EuropayError::AccountNotFound)?;
+        self.store.accounts.save(account)
+    }
+
+    fn save_card(&mut self, card_id: Uuid) -> Result<(), EuropayError> {
+        let card = self.cards.get(&card_id).ok_or(EuropayError::CardNotFound)?;
+        self.store.cards.save(card)
+    }
+
+    fn save_merchant(&mut self, merchant_id: Uuid) -> Result<(), EuropayError> {
+        let merchant = self.merchants.get(&merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+        self.store.merchants.save(merchant)
+    }
+
+    fn save_transaction(&mut self, tx_id: Uuid) -> Result<(), EuropayError> {
+        let transaction = self.transactions.get(&tx_id).ok_or(EuropayError::TransactionNotFound)?;
+        self.store.transactions.save(transaction)
+    }
+}
+
+// Cardholder to issuer settlement, then acquirer settlement to merchant. A DCC purchase is
+// billed in the account currency, so the conversion at the reference rate goes through FX
+// and the markup on top of it is booked as fee revenue.
+fn card_postings(ledger: &mut Ledger, account: &Account, merchant: &Merchant, transaction: &Transaction, amount: &Money, billing: &Money) -> Result<Vec<Posting>, EuropayError> {
+    let currency = amount.currency();
+    let mut postings = vec![Posting::debit(ledger.account_id(LedgerAccountType::Cardholder, Some(account.id), account.currency), *billing)];
+    if billing.currency() != currency {
+        let quote = transaction.dcc.as_ref().ok_or_else(|| EuropayError::Internal("DCC quote missing".to_string()))?;
+        let converted = quote.rate.apply(amount, billing.currency(), RoundingMode::HalfUp)?;
+        let converted = if converted > *billing { *billing } else { converted };
+        let markup = billing.checked_sub(&converted)?;
+        postings.push(Posting::credit(ledger.account_id(LedgerAccountType::Fx, None, billing.currency()), converted));
+        postings.push(Posting::credit(ledger.account_id(LedgerAccountType::Fees, None, billing.currency()), markup));
+        postings.push(Posting::debit(ledger.account_id(LedgerAccountType::Fx, None, currency), *amount));
+    }
+    postings.push(Posting::credit(ledger.account_id(LedgerAccountType::IssuerSettlement, None, currency), *amount));
+    postings.push(Posting::debit(ledger.account_id(LedgerAccountType::AcquirerSettlement, Some(merchant.acquirer_id), currency), *amount));
+    postings.push(Posting::credit(ledger.account_id(LedgerAccountType::Merchant, Some(merchant.id), currency), *amount));
+    Ok(postings)
+}
+
+// The part of the billed amount corresponding to `amount` of the transaction amount
+fn billing_share(transaction: &Transaction, amount: &Money) -> Result<Money, EuropayError> {
+    transaction.billing_amount
+        .checked_mul_ratio(amount.minor_units() as i128, transaction.amount.minor_units() as i128, RoundingMode::HalfUp)
+        .map_err(EuropayError::from)
+}
+
+impl IdentifierResolver for PaymentProcessor {
+    fn card_id_for_pan(&self, pan: &str) -> Option<Uuid> {
+        self.cards.values().find(|card| card.pan == pan).map(|card| card.id)
+    }
+
+    fn merchant_id_for_acceptor(&self, card_acceptor_id: &str) -> Option<Uuid> {
+        self.merchants.values()
+            .find(|merchant| merchant.card_acceptor_id == card_acceptor_id)
+            .map(|merchant| merchant.id)
+    }
+}
+
+// Test fixtures shared by the modules that drive a processor
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+    use std::sync::Arc;
+    use crate::core::currency::Currency;
+    use crate::core::rates::InMemoryRateProvider;
+
+    pub const PAN: &str = "4000000000000002";
+
+    /// The records set up by [`add_cardholder`].
+    pub struct Cardholder {
+        pub account_id: Uuid,
+        pub card_id: Uuid,
+        pub merchant_id: Uuid,
+        pub acquirer_id: Uuid,
+        pub card_acceptor_id: String,
+    }
+
+    /// A processor without FX rates or DCC markup.
+    pub fn processor() -> PaymentProcessor {
+        PaymentProcessor::new(CurrencyConverter::new(Arc::new(InMemoryRateProvider::new())), 0)
+    }
+
+    /// Opens an account holding `balance` with a card on [`PAN`], and a restaurant merchant
+    /// to spend it at.
+    pub fn add_cardholder(processor: &mut PaymentProcessor, currency: Currency, balance: &str) -> Cardholder {
+        let account = Account::new("Jane Doe".to_string(), currency);
+        let card = PaymentCard::new(account.id, PAN.to_string(), 12, 2099, "123".to_string(), "JANE DOE".to_string());
+        let merchant = Merchant::new("Café Central".to_string(), "5812".to_string(), Uuid::new_v4());
+        let cardholder = Cardholder {
+            account_id: account.id,
+            card_id: card.id,
+            merchant_id: merchant.id,
+            acquirer_id: merchant.acquirer_id,
+            card_acceptor_id: merchant.card_acceptor_id.clone(),
+        };
+        processor.add_account(account).unwrap();
+        processor.deposit(cardholder.account_id, Money::parse(balance, currency).unwrap()).unwrap();
+        processor.add_card(card).unwrap();
+        processor.add_merchant(merchant).unwrap();
+        cardholder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::core::currency::Currency;
+    use crate::core::rates::{InMemoryRateProvider, Rate, RateSnapshot};
+    use crate::services::settlement::SettlementService;
+
+    fn setup(account_currency: Currency, balance: &str) -> (PaymentProcessor, Uuid, Uuid) {
+        let snapshot = RateSnapshot::new(Currency::EUR, Utc::now())
+            .with_rate(Currency::GBP, Rate::parse("0.85").unwrap());
+        let converter = CurrencyConverter::new(Arc::new(InMemoryRateProvider::with_snapshot(snapshot)));
+        let mut processor = PaymentProcessor::new(converter, 300);
+        let cardholder = fixtures::add_cardholder(&mut processor, account_currency, balance);
+        (processor, cardholder.card_id, cardholder.merchant_id)
+    }
+
+    #[test]
+    fn test_authorize_with_dcc_checks_funds_in_account_currency() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::GBP, "90.00");
+        let amount = Money::parse("100.00", Currency::EUR).unwrap();
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, amount).unwrap();
+
+        let transaction = processor.get_transaction(tx_id).unwrap();
+        assert_eq!(transaction.amount, amount);
+        assert_eq!(transaction.billing_amount, Money::parse("87.55", Currency::GBP).unwrap());
+        assert!(transaction.dcc.is_some());
+
+        let too_much = Money::parse("110.00", Currency::EUR).unwrap();
+        assert_eq!(processor.authorize_transaction(card_id, merchant_id, too_much), Err(EuropayError::InsufficientFunds));
+    }
+
+    #[test]
+    fn test_capture_debits_billing_amount() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::GBP, "90.00");
+        let amount = Money::parse("100.00", Currency::EUR).unwrap();
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, amount).unwrap();
+        processor.capture_transaction(tx_id).unwrap();
+
+        let card = processor.cards.get(&card_id).unwrap();
+        let account = processor.accounts.get(&card.account_id).unwrap();
+        assert_eq!(account.ledger_balance, Money::parse("2.45", Currency::GBP).unwrap());
+    }
+
+    #[test]
+    fn test_reversal_of_captured_transaction_credits_account() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::EUR, "100.00");
+        let amount = Money::parse("60.00", Currency::EUR).unwrap();
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, amount).unwrap();
+        processor.capture_transaction(tx_id).unwrap();
+
+        let actual = Money::parse("45.00", Currency::EUR).unwrap();
+        processor.partially_reverse_transaction(tx_id, actual).unwrap();
+        processor.partially_reverse_transaction(tx_id, actual).unwrap();
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+        assert_eq!(processor.accounts.get(&account_id).unwrap().ledger_balance, Money::parse("55.00", Currency::EUR).unwrap());
+
+        processor.reverse_transaction(tx_id).unwrap();
+        processor.reverse_transaction(tx_id).unwrap();
+        assert_eq!(processor.accounts.get(&account_id).unwrap().ledger_balance, Money::parse("100.00", Currency::EUR).unwrap());
+        let transaction = processor.get_transaction(tx_id).unwrap();
+        assert_eq!(transaction.status, TransactionStatus::Reversed);
+        assert_eq!(transaction.reversed_amount, amount);
+        assert!(processor.partially_reverse_transaction(tx_id, Money::parse("10.00", Currency::EUR).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_dcc_purchase_refund_and_settlement_post_balanced_entries() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::GBP, "90.00");
+        let acquirer_id = processor.get_merchant(merchant_id).unwrap().acquirer_id;
+        let gbp = |s: &str| Money::parse(s, Currency::GBP).unwrap();
+        let eur = |s: &str| Money::parse(s, Currency::EUR).unwrap();
+
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, eur("100.00")).unwrap();
+        processor.capture_transaction(tx_id).unwrap();
+        let mut settlement = SettlementService::new();
+        let batch_id = settlement.create_batch(Uuid::new_v4(), acquirer_id, &[tx_id], &processor).unwrap();
+        settlement.process_settlement(batch_id, &mut processor).unwrap();
+        assert_eq!(processor.get_transaction(tx_id).unwrap().status, TransactionStatus::Settled);
+        processor.refund_transaction(tx_id, eur("40.00")).unwrap();
+
+        // Billed 87.55 at 0.8755 and refunded 35.02; the rest of the 3% markup stays as fees
+        let ledger = processor.ledger();
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+        assert_eq!(ledger.balance_of(LedgerAccountType::Cardholder, Some(account_id), Currency::GBP), gbp("37.47"));
+        assert_eq!(processor.accounts.get(&account_id).unwrap().ledger_balance, gbp("37.47"));
+        assert_eq!(ledger.balance_of(LedgerAccountType::Fees, None, Currency::GBP), gbp("1.53"));
+        assert_eq!(ledger.balance_of(LedgerAccountType::Fx, None, Currency::GBP), gbp("51.00"));
+        assert_eq!(ledger.balance_of(LedgerAccountType::Fx, None, Currency::EUR), eur("-60.00"));
+        assert_eq!(ledger.balance_of(LedgerAccountType::Merchant, Some(merchant_id), Currency::EUR), eur("60.00"));
+        // The refund is owed back by the acquirer until the next batch
+        assert_eq!(ledger.balance_of(LedgerAccountType::IssuerSettlement, None, Currency::EUR), eur("-40.00"));
+        assert_eq!(ledger.balance_of(LedgerAccountType::AcquirerSettlement, Some(acquirer_id), Currency::EUR), eur("40.00"));
+        assert_eq!(ledger.entries_for(tx_id).unwrap().len(), 1);
+        ledger.verify().unwrap();
+    }
+
+    #[test]
+    fn test_transitions_are_checked_and_recorded() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::EUR, "100.00");
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, Money::parse("20.00", Currency::EUR).unwrap()).unwrap();
+        assert!(processor.settle_transaction(tx_id).is_err());
+        processor.capture_transaction(tx_id).unwrap();
+        processor.settle_transaction(tx_id).unwrap();
+
+        let transaction = processor.get_transaction(tx_id).unwrap();
+        let error = transaction.check_transition(TransactionStatus::Reversed).unwrap_err();
+        assert_eq!((error.from, error.to), (TransactionStatus::Settled, TransactionStatus::Reversed));
+        assert_eq!(processor.reverse_transaction(tx_id), Err(error.into()));
+
+        let events: Vec<_> = processor.transitions_for(tx_id).iter().map(|e| (e.from, e.to, e.actor)).collect();
+        assert_eq!(events, vec![
+            (TransactionStatus::Pending, TransactionStatus::Authorized, Actor::Issuer),
+            (TransactionStatus::Authorized, TransactionStatus::Captured, Actor::Acquirer),
+            (TransactionStatus::Captured, TransactionStatus::Settled, Actor::Network),
+        ]);
+    }
+
+    #[test]
+    fn test_partial_refunds_up_to_captured_amount() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::GBP, "90.00");
+        let amount = Money::parse("100.00", Currency::EUR).unwrap();
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, amount).unwrap();
+        let third = Money::parse("33.33", Currency::EUR).unwrap();
+        assert_eq!(processor.refund_transaction(tx_id, third), Err(EuropayError::InvalidState("Transaction not captured".to_string())));
+        processor.capture_transaction(tx_id).unwrap();
+
+        let refund_id = processor.refund_transaction(tx_id, third).unwrap();
+        processor.refund_transaction(tx_id, third).unwrap();
+        let refund = processor.get_transaction(refund_id).unwrap();
+        assert_eq!(refund.transaction_type, TransactionType::Refund);
+        assert_eq!(refund.original_transaction_id, Some(tx_id));
+        assert_eq!(refund.billing_amount, Money::parse("29.18", Currency::GBP).unwrap());
+
+        let rest = Money::parse("33.35", Currency::EUR).unwrap();
+        assert_eq!(processor.refund_transaction(tx_id, rest), Err(EuropayError::InvalidAmount("Refund exceeds captured amount".to_string())));
+        processor.refund_transaction(tx_id, Money::parse("33.34", Currency::EUR).unwrap()).unwrap();
+        assert_eq!(processor.get_transaction(tx_id).unwrap().refunded_amount, amount);
+
+        // The refunds add back exactly what the capture debited
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+        assert_eq!(processor.accounts.get(&account_id).unwrap().ledger_balance, Money::parse("90.00", Currency::GBP).unwrap());
+    }
+
+    #[test]
+    fn test_multiple_captures_release_remainder_on_final() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::EUR, "500.00");
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, Money::parse("300.00", Currency::EUR).unwrap()).unwrap();
+
+        processor.capture_partial(tx_id, Money::parse("120.00", Currency::EUR).unwrap(), false).unwrap();
+        assert_eq!(processor.get_transaction(tx_id).unwrap().status, TransactionStatus::Authorized);
+        assert_eq!(
+            processor.capture_partial(tx_id, Money::parse("190.00", Currency::EUR).unwrap(), false),
+            Err(EuropayError::InvalidAmount("Capture exceeds authorized amount".to_string()))
+        );
+        processor.capture_partial(tx_id, Money::parse("80.00", Currency::EUR).unwrap(), true).unwrap();
+
+        let transaction = processor.get_transaction(tx_id).unwrap();
+        assert_eq!(transaction.status, TransactionStatus::Captured);
+        assert_eq!(transaction.amount, Money::parse("200.00", Currency::EUR).unwrap());
+        assert_eq!(transaction.reversed_amount, Money::parse("100.00", Currency::EUR).unwrap());
+        let captures = processor.captures_for(tx_id);
+        assert_eq!(captures.len(), 2);
+        assert!(captures.iter().any(|c| c.is_final));
+
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+        assert_eq!(processor.accounts.get(&account_id).unwrap().ledger_balance, Money::parse("300.00", Currency::EUR).unwrap());
+        assert!(processor.capture_partial(tx_id, Money::parse("1.00", Currency::EUR).unwrap(), true).is_err());
+    }
+
+    #[test]
+    fn test_incremental_authorization_and_expiry() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::EUR, "400.00");
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, Money::parse("150.00", Currency::EUR).unwrap()).unwrap();
+        processor.increment_authorization(tx_id, Money::parse("100.00", Currency::EUR).unwrap()).unwrap();
+        assert_eq!(processor.get_transaction(tx_id).unwrap().amount, Money::parse("250.00", Currency::EUR).unwrap());
+        assert_eq!(
+            processor.increment_authorization(tx_id, Money::parse("200.00", Currency::EUR).unwrap()),
+            Err(EuropayError::InsufficientFunds)
+        );
+
+        // Restaurant (5812) authorizations lapse after the default window
+        let expires_at = processor.get_transaction(tx_id).unwrap().expires_at.unwrap();
+        assert!(processor.expire_authorizations(expires_at - chrono::Duration::seconds(1)).is_empty());
+        assert_eq!(processor.expire_authorizations(expires_at), vec![tx_id]);
+        assert_eq!(processor.get_transaction(tx_id).unwrap().status, TransactionStatus::Expired);
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+        assert!(processor.accounts.get(&account_id).unwrap().holds.is_empty());
+        assert!(processor.increment_authorization(tx_id, Money::parse("1.00", Currency::EUR).unwrap()).is_err());
+        assert!(processor.capture_transaction(tx_id).is_err());
+    }
+
+    #[test]
+    fn test_increment_rechecks_card_account_and_merchant() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::EUR, "400.00");
+        let eur = |s: &str| Money::parse(s, Currency::EUR).unwrap();
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, eur("100.00")).unwrap();
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+
+        processor.update_card_status(card_id, CardStatus::Blocked).unwrap();
+        assert_eq!(processor.increment_authorization(tx_id, eur("50.00")), Err(EuropayError::CardNotActive));
+        processor.update_card_status(card_id, CardStatus::Active).unwrap();
+
+        processor.update_account_status(account_id, AccountStatus::Frozen).unwrap();
+        assert_eq!(processor.increment_authorization(tx_id, eur("50.00")), Err(EuropayError::AccountNotActive));
+        processor.update_account_status(account_id, AccountStatus::Active).unwrap();
+
+        processor.update_merchant_status(merchant_id, MerchantStatus::Suspended).unwrap();
+        assert_eq!(processor.increment_authorization(tx_id, eur("50.00")), Err(EuropayError::MerchantNotActive));
+        processor.update_merchant_status(merchant_id, MerchantStatus::Active).unwrap();
+
+        processor.cards.get_mut(&card_id).unwrap().expiry_year = 2000;
+        assert_eq!(processor.increment_authorization(tx_id, eur("50.00")), Err(EuropayError::CardExpired));
+
+        // None of the rejected increments touched the authorization or its hold
+        assert_eq!(processor.get_transaction(tx_id).unwrap().amount, eur("100.00"));
+        assert_eq!(processor.accounts.get(&account_id).unwrap().available_balance().unwrap(), eur("300.00"));
+    }
+
+    #[test]
+    fn test_expiry_completes_partially_captured_authorization() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::EUR, "200.00");
+        let eur = |s: &str| Money::parse(s, Currency::EUR).unwrap();
+        let tx_id = processor.authorize_transaction(card_id, merchant_id, eur("120.00")).unwrap();
+        processor.capture_partial(tx_id, eur("45.00"), false).unwrap();
+
+        let expires_at = processor.get_transaction(tx_id).unwrap().expires_at.unwrap();
+        assert_eq!(processor.expire_authorizations(expires_at), vec![tx_id]);
+        let transaction = processor.get_transaction(tx_id).unwrap();
+        assert_eq!(transaction.status, TransactionStatus::Captured);
+        assert_eq!((transaction.amount, transaction.reversed_amount), (eur("45.00"), eur("75.00")));
+        let last = processor.transitions_for(tx_id).last().unwrap();
+        assert_eq!((last.to, last.actor), (TransactionStatus::Captured, Actor::System));
+
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+        let account = processor.accounts.get(&account_id).unwrap();
+        assert!(account.holds.is_empty());
+        assert_eq!(account.ledger_balance, eur("155.00"));
+        assert!(processor.expire_authorizations(expires_at).is_empty());
+    }
+
+    #[test]
+    fn test_authorization_window_by_merchant_category() {
+        use crate::models::merchants::authorization_window;
+        assert_eq!(authorization_window("7011"), chrono::Duration::days(31));
+        assert_eq!(authorization_window("3390"), chrono::Duration::days(31));
+        assert_eq!(authorization_window("5812"), chrono::Duration::days(7));
+    }
+
+    #[test]
+    fn test_holds_reserve_funds_until_capture_or_release() {
+        let (mut processor, card_id, merchant_id) = setup(Currency::EUR, "100.00");
+        let account_id = processor.cards.get(&card_id).unwrap().account_id;
+        let available = |processor: &PaymentProcessor| processor.accounts.get(&account_id).unwrap().available_balance().unwrap();
+
+        let first = processor.authorize_transaction(card_id, merchant_id, Money::parse("70.00", Currency::EUR).unwrap()).unwrap();
+        assert_eq!(available(&processor), Money::parse("30.00", Currency::EUR).unwrap());
+        // The second authorization alone would fit the ledger balance but not what is left available
+        let second = Money::parse("40.00", Currency::EUR).unwrap();
+        assert_eq!(processor.authorize_transaction(card_id, merchant_id, second), Err(EuropayError::InsufficientFunds));
+
+        processor.capture_partial(first, Money::parse("50.00", Currency::EUR).unwrap(), false).unwrap();
+        let account = processor.accounts.get(&account_id).unwrap();
+        assert_eq!(account.ledger_balance, Money::parse("50.00", Currency::EUR).unwrap());
+        assert_eq!(account.held_amount().unwrap(), Money::parse("20.00", Currency::EUR).unwrap());
+
+        processor.partially_reverse_transaction(first, Money::parse("60.00", Currency::EUR).unwrap()).unwrap();
+        assert_eq!(available(&processor), Money::parse("40.00", Currency::EUR).unwrap());
+        let second = processor.authorize_transaction(card_id, merchant_id, second).unwrap();
+
+        processor.capture_transaction(first).unwrap();
+        processor.reverse_transaction(second).unwrap();
+        let account = processor.accounts.get(&account_id).unwrap();
+        assert!(account.holds.is_empty());
+        assert_eq!(account.ledger_balance, Money::parse("40.00", Currency::EUR).unwrap());
+    }
+}
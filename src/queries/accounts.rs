@@ -0,0 +1,11 @@
+// Account queries
+
+pub const SELECT_BY_ID: &str = "SELECT data FROM accounts WHERE id = ?1";
+
+pub const SELECT_ALL: &str = "SELECT data FROM accounts ORDER BY created_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO accounts (id, currency, status, created_at, data)
+    VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (id) DO UPDATE SET status = excluded.status, data = excluded.data
+";
@@ -0,0 +1,11 @@
+// Pending settlement adjustment queries
+
+pub const SELECT_ALL: &str = "SELECT data FROM settlement_adjustments ORDER BY created_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO settlement_adjustments (id, issuer_id, acquirer_id, created_at, data)
+    VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (id) DO UPDATE SET data = excluded.data
+";
+
+pub const DELETE: &str = "DELETE FROM settlement_adjustments WHERE id = ?1";
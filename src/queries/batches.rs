@@ -0,0 +1,11 @@
+// Settlement batch queries
+
+pub const SELECT_BY_ID: &str = "SELECT data FROM settlement_batches WHERE id = ?1";
+
+pub const SELECT_ALL: &str = "SELECT data FROM settlement_batches ORDER BY created_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO settlement_batches (id, issuer_id, acquirer_id, status, created_at, data)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+    ON CONFLICT (id) DO UPDATE SET status = excluded.status, data = excluded.data
+";
@@ -0,0 +1,11 @@
+// Capture queries
+
+pub const SELECT_BY_ID: &str = "SELECT data FROM captures WHERE id = ?1";
+
+pub const SELECT_ALL: &str = "SELECT data FROM captures ORDER BY captured_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO captures (id, transaction_id, captured_at, data)
+    VALUES (?1, ?2, ?3, ?4)
+    ON CONFLICT (id) DO UPDATE SET data = excluded.data
+";
@@ -0,0 +1,11 @@
+// Card queries
+
+pub const SELECT_BY_ID: &str = "SELECT data, encrypted_pan FROM cards WHERE id = ?1";
+
+pub const SELECT_ALL: &str = "SELECT data, encrypted_pan FROM cards ORDER BY issued_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO cards (id, account_id, pan_hash, encrypted_pan, status, issued_at, data)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+    ON CONFLICT (id) DO UPDATE SET status = excluded.status, data = excluded.data
+";
@@ -0,0 +1,11 @@
+// Dispute queries
+
+pub const SELECT_BY_ID: &str = "SELECT data FROM disputes WHERE id = ?1";
+
+pub const SELECT_ALL: &str = "SELECT data FROM disputes ORDER BY opened_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO disputes (id, transaction_id, stage, opened_at, data)
+    VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (id) DO UPDATE SET stage = excluded.stage, data = excluded.data
+";
@@ -0,0 +1,26 @@
+// Ledger queries
+
+pub const SELECT_ACCOUNTS: &str = "SELECT id, account_type, owner_id, currency, opened_at FROM ledger_accounts";
+
+pub const INSERT_ACCOUNT: &str = "
+    INSERT OR IGNORE INTO ledger_accounts (id, account_type, owner_id, currency, opened_at)
+    VALUES (?1, ?2, ?3, ?4, ?5)
+";
+
+pub const SELECT_ENTRIES: &str = "SELECT id, description, reference, posted_at FROM journal_entries ORDER BY sequence";
+
+pub const SELECT_POSTINGS: &str = "
+    SELECT entry_id, ledger_account_id, side, minor_units, currency
+    FROM postings
+    ORDER BY entry_id, position
+";
+
+pub const INSERT_ENTRY: &str = "
+    INSERT INTO journal_entries (id, description, reference, posted_at)
+    VALUES (?1, ?2, ?3, ?4)
+";
+
+pub const INSERT_POSTING: &str = "
+    INSERT INTO postings (entry_id, position, ledger_account_id, side, minor_units, currency)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+";
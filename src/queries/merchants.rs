@@ -0,0 +1,11 @@
+// Merchant queries
+
+pub const SELECT_BY_ID: &str = "SELECT data FROM merchants WHERE id = ?1";
+
+pub const SELECT_ALL: &str = "SELECT data FROM merchants ORDER BY registered_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO merchants (id, acquirer_id, card_acceptor_id, category, status, registered_at, data)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+    ON CONFLICT (id) DO UPDATE SET status = excluded.status, data = excluded.data
+";
@@ -0,0 +1,135 @@
+// Schema migrations, applied in order. The schema version is kept in `PRAGMA user_version`,
+// so a migration must never be edited once released; add a new one instead.
+
+pub const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "
+    CREATE TABLE accounts (
+        id TEXT PRIMARY KEY,
+        currency TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE cards (
+        id TEXT PRIMARY KEY,
+        account_id TEXT NOT NULL REFERENCES accounts (id),
+        pan_hash TEXT NOT NULL UNIQUE,
+        encrypted_pan BLOB NOT NULL,
+        status TEXT NOT NULL,
+        issued_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX cards_account_id ON cards (account_id);
+
+    CREATE TABLE merchants (
+        id TEXT PRIMARY KEY,
+        acquirer_id TEXT NOT NULL,
+        card_acceptor_id TEXT NOT NULL,
+        category TEXT NOT NULL,
+        status TEXT NOT NULL,
+        registered_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX merchants_card_acceptor_id ON merchants (card_acceptor_id);
+
+    CREATE TABLE transactions (
+        id TEXT PRIMARY KEY,
+        card_id TEXT NOT NULL,
+        merchant_id TEXT NOT NULL,
+        original_transaction_id TEXT,
+        transaction_type TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX transactions_merchant_id ON transactions (merchant_id, created_at);
+    CREATE INDEX transactions_card_id ON transactions (card_id, created_at);
+
+    CREATE TABLE settlement_batches (
+        id TEXT PRIMARY KEY,
+        issuer_id TEXT NOT NULL,
+        acquirer_id TEXT NOT NULL,
+        status TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+
+    CREATE TABLE ledger_accounts (
+        id TEXT PRIMARY KEY,
+        account_type TEXT NOT NULL,
+        owner_id TEXT,
+        currency TEXT NOT NULL,
+        opened_at TEXT NOT NULL
+    );
+
+    CREATE TABLE journal_entries (
+        sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+        id TEXT NOT NULL UNIQUE,
+        description TEXT NOT NULL,
+        reference TEXT,
+        posted_at TEXT NOT NULL
+    );
+    CREATE INDEX journal_entries_reference ON journal_entries (reference);
+
+    CREATE TABLE postings (
+        entry_id TEXT NOT NULL REFERENCES journal_entries (id),
+        position INTEGER NOT NULL,
+        ledger_account_id TEXT NOT NULL REFERENCES ledger_accounts (id),
+        side TEXT NOT NULL,
+        minor_units INTEGER NOT NULL,
+        currency TEXT NOT NULL,
+        PRIMARY KEY (entry_id, position)
+    );
+    ",
+    // 2: captures, network references, transitions, disputes and pending adjustments
+    "
+    CREATE TABLE captures (
+        id TEXT PRIMARY KEY,
+        transaction_id TEXT NOT NULL REFERENCES transactions (id),
+        captured_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX captures_transaction_id ON captures (transaction_id);
+
+    CREATE TABLE network_references (
+        reference TEXT PRIMARY KEY,
+        transaction_id TEXT NOT NULL REFERENCES transactions (id)
+    );
+
+    CREATE TABLE authorization_advices (
+        reference TEXT PRIMARY KEY,
+        transaction_id TEXT NOT NULL REFERENCES transactions (id)
+    );
+
+    -- Written as the transition is applied, before the transaction it moves is saved
+    CREATE TABLE transitions (
+        sequence INTEGER PRIMARY KEY AUTOINCREMENT,
+        transaction_id TEXT NOT NULL,
+        from_status TEXT NOT NULL,
+        to_status TEXT NOT NULL,
+        actor TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        occurred_at TEXT NOT NULL
+    );
+    CREATE INDEX transitions_transaction_id ON transitions (transaction_id);
+
+    CREATE TABLE disputes (
+        id TEXT PRIMARY KEY,
+        transaction_id TEXT NOT NULL REFERENCES transactions (id),
+        stage TEXT NOT NULL,
+        opened_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE INDEX disputes_transaction_id ON disputes (transaction_id);
+
+    CREATE TABLE settlement_adjustments (
+        id TEXT PRIMARY KEY,
+        issuer_id TEXT NOT NULL,
+        acquirer_id TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        data TEXT NOT NULL
+    );
+    ",
+];
@@ -1 +1,17 @@
-// Queries module
\ No newline at end of file
+// Queries module
+//
+// SQL for the SQLite repositories. Aggregates are stored as a JSON document alongside
+// the columns they are looked up or filtered by; the ledger is stored in full columns.
+
+pub mod accounts;
+pub mod adjustments;
+pub mod batches;
+pub mod captures;
+pub mod cards;
+pub mod disputes;
+pub mod ledger;
+pub mod merchants;
+pub mod migrations;
+pub mod references;
+pub mod transactions;
+pub mod transitions;
\ No newline at end of file
@@ -0,0 +1,18 @@
+// Network reference queries, one table per kind of reference
+
+pub struct ReferenceQueries {
+    pub select_all: &'static str,
+    pub insert: &'static str,
+}
+
+// Field 90 original data elements of the requests that created a transaction
+pub const NETWORK: ReferenceQueries = ReferenceQueries {
+    select_all: "SELECT reference, transaction_id FROM network_references",
+    insert: "INSERT OR IGNORE INTO network_references (reference, transaction_id) VALUES (?1, ?2)",
+};
+
+// Store-and-forward advices already applied
+pub const ADVICES: ReferenceQueries = ReferenceQueries {
+    select_all: "SELECT reference, transaction_id FROM authorization_advices",
+    insert: "INSERT OR IGNORE INTO authorization_advices (reference, transaction_id) VALUES (?1, ?2)",
+};
@@ -0,0 +1,11 @@
+// Transaction queries
+
+pub const SELECT_BY_ID: &str = "SELECT data FROM transactions WHERE id = ?1";
+
+pub const SELECT_ALL: &str = "SELECT data FROM transactions ORDER BY created_at, id";
+
+pub const UPSERT: &str = "
+    INSERT INTO transactions (id, card_id, merchant_id, original_transaction_id, transaction_type, status, created_at, data)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+    ON CONFLICT (id) DO UPDATE SET status = excluded.status, data = excluded.data
+";
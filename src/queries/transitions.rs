@@ -0,0 +1,12 @@
+// Transaction status transition queries
+
+pub const SELECT_ALL: &str = "
+    SELECT transaction_id, from_status, to_status, actor, reason, occurred_at
+    FROM transitions
+    ORDER BY sequence
+";
+
+pub const INSERT: &str = "
+    INSERT INTO transitions (transaction_id, from_status, to_status, actor, reason, occurred_at)
+    VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+";
@@ -0,0 +1,129 @@
+// In-memory repositories, for tests and nodes that need no durability
+
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::core::error::EuropayError;
+use crate::models::accounts::Account;
+use crate::models::cards::PaymentCard;
+use crate::models::ledger::{JournalEntry, LedgerAccount};
+use crate::models::lifecycle::TransitionEvent;
+use crate::models::merchants::Merchant;
+use crate::models::transactions::{Capture, Transaction};
+use crate::repositories::{
+    AccountRepository, AdjustmentRepository, BatchRepository, CaptureRepository, CardRepository, DisputeRepository,
+    LedgerRepository, MerchantRepository, ReferenceRepository, TransactionRepository, TransitionRepository,
+};
+use crate::services::disputes::Dispute;
+use crate::services::settlement::{SettlementAdjustment, SettlementBatch};
+
+// The aggregates are keyed by id, so the stores only differ in the type they hold
+macro_rules! in_memory_repository {
+    ($name:ident, $trait:ident, $type:ty) => {
+        #[derive(Default)]
+        pub struct $name {
+            records: HashMap<Uuid, $type>,
+        }
+
+        impl $trait for $name {
+            fn get(&self, id: Uuid) -> Result<Option<$type>, EuropayError> {
+                Ok(self.records.get(&id).cloned())
+            }
+
+            fn all(&self) -> Result<Vec<$type>, EuropayError> {
+                Ok(self.records.values().cloned().collect())
+            }
+
+            fn save(&mut self, record: &$type) -> Result<(), EuropayError> {
+                self.records.insert(record.id, record.clone());
+                Ok(())
+            }
+        }
+    };
+}
+
+in_memory_repository!(InMemoryAccountRepository, AccountRepository, Account);
+in_memory_repository!(InMemoryCardRepository, CardRepository, PaymentCard);
+in_memory_repository!(InMemoryMerchantRepository, MerchantRepository, Merchant);
+in_memory_repository!(InMemoryTransactionRepository, TransactionRepository, Transaction);
+in_memory_repository!(InMemoryCaptureRepository, CaptureRepository, Capture);
+in_memory_repository!(InMemoryBatchRepository, BatchRepository, SettlementBatch);
+in_memory_repository!(InMemoryDisputeRepository, DisputeRepository, Dispute);
+
+#[derive(Default)]
+pub struct InMemoryReferenceRepository {
+    references: HashMap<String, Uuid>,
+}
+
+impl ReferenceRepository for InMemoryReferenceRepository {
+    fn all(&self) -> Result<Vec<(String, Uuid)>, EuropayError> {
+        Ok(self.references.iter().map(|(reference, id)| (reference.clone(), *id)).collect())
+    }
+
+    fn save(&mut self, reference: &str, transaction_id: Uuid) -> Result<(), EuropayError> {
+        self.references.entry(reference.to_string()).or_insert(transaction_id);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryTransitionRepository {
+    events: Vec<TransitionEvent>,
+}
+
+impl TransitionRepository for InMemoryTransitionRepository {
+    fn all(&self) -> Result<Vec<TransitionEvent>, EuropayError> {
+        Ok(self.events.clone())
+    }
+
+    fn append(&mut self, event: &TransitionEvent) -> Result<(), EuropayError> {
+        self.events.push(event.clone());
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryAdjustmentRepository {
+    adjustments: Vec<SettlementAdjustment>,
+}
+
+impl AdjustmentRepository for InMemoryAdjustmentRepository {
+    fn all(&self) -> Result<Vec<SettlementAdjustment>, EuropayError> {
+        Ok(self.adjustments.clone())
+    }
+
+    fn save(&mut self, adjustment: &SettlementAdjustment) -> Result<(), EuropayError> {
+        self.adjustments.retain(|existing| existing.id != adjustment.id);
+        self.adjustments.push(adjustment.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<(), EuropayError> {
+        self.adjustments.retain(|adjustment| adjustment.id != id);
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryLedgerRepository {
+    accounts: HashMap<Uuid, LedgerAccount>,
+    entries: Vec<JournalEntry>,
+}
+
+impl LedgerRepository for InMemoryLedgerRepository {
+    fn accounts(&self) -> Result<Vec<LedgerAccount>, EuropayError> {
+        Ok(self.accounts.values().cloned().collect())
+    }
+
+    fn entries(&self) -> Result<Vec<JournalEntry>, EuropayError> {
+        Ok(self.entries.clone())
+    }
+
+    fn append(&mut self, accounts: &[LedgerAccount], entry: &JournalEntry) -> Result<(), EuropayError> {
+        for account in accounts {
+            self.accounts.entry(account.id).or_insert_with(|| account.clone());
+        }
+        self.entries.push(entry.clone());
+        Ok(())
+    }
+}
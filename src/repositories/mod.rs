@@ -0,0 +1,118 @@
+// Repositories: durable storage behind the processor's, settlement service's and dispute service's state
+
+use uuid::Uuid;
+
+use crate::core::error::EuropayError;
+use crate::models::accounts::Account;
+use crate::models::cards::PaymentCard;
+use crate::models::ledger::{JournalEntry, LedgerAccount};
+use crate::models::lifecycle::TransitionEvent;
+use crate::models::merchants::Merchant;
+use crate::models::transactions::{Capture, Transaction};
+use crate::services::disputes::Dispute;
+use crate::services::settlement::{SettlementAdjustment, SettlementBatch};
+
+pub mod memory;
+pub mod sqlite;
+
+pub trait AccountRepository: Send {
+    fn get(&self, id: Uuid) -> Result<Option<Account>, EuropayError>;
+    fn all(&self) -> Result<Vec<Account>, EuropayError>;
+    /// Inserts the account or replaces the stored one with the same id.
+    fn save(&mut self, account: &Account) -> Result<(), EuropayError>;
+}
+
+pub trait CardRepository: Send {
+    fn get(&self, id: Uuid) -> Result<Option<PaymentCard>, EuropayError>;
+    fn all(&self) -> Result<Vec<PaymentCard>, EuropayError>;
+    fn save(&mut self, card: &PaymentCard) -> Result<(), EuropayError>;
+}
+
+pub trait MerchantRepository: Send {
+    fn get(&self, id: Uuid) -> Result<Option<Merchant>, EuropayError>;
+    fn all(&self) -> Result<Vec<Merchant>, EuropayError>;
+    fn save(&mut self, merchant: &Merchant) -> Result<(), EuropayError>;
+}
+
+pub trait TransactionRepository: Send {
+    fn get(&self, id: Uuid) -> Result<Option<Transaction>, EuropayError>;
+    fn all(&self) -> Result<Vec<Transaction>, EuropayError>;
+    fn save(&mut self, transaction: &Transaction) -> Result<(), EuropayError>;
+}
+
+pub trait CaptureRepository: Send {
+    fn get(&self, id: Uuid) -> Result<Option<Capture>, EuropayError>;
+    fn all(&self) -> Result<Vec<Capture>, EuropayError>;
+    fn save(&mut self, capture: &Capture) -> Result<(), EuropayError>;
+}
+
+/// Network references that resolve to a transaction, such as field 90 original data elements.
+/// A reference, once recorded, always resolves to the same transaction.
+pub trait ReferenceRepository: Send {
+    fn all(&self) -> Result<Vec<(String, Uuid)>, EuropayError>;
+    fn save(&mut self, reference: &str, transaction_id: Uuid) -> Result<(), EuropayError>;
+}
+
+/// The transition log is append-only, like the journal.
+pub trait TransitionRepository: Send {
+    /// Every event, in the order it was appended.
+    fn all(&self) -> Result<Vec<TransitionEvent>, EuropayError>;
+    fn append(&mut self, event: &TransitionEvent) -> Result<(), EuropayError>;
+}
+
+pub trait BatchRepository: Send {
+    fn get(&self, id: Uuid) -> Result<Option<SettlementBatch>, EuropayError>;
+    fn all(&self) -> Result<Vec<SettlementBatch>, EuropayError>;
+    fn save(&mut self, batch: &SettlementBatch) -> Result<(), EuropayError>;
+}
+
+pub trait DisputeRepository: Send {
+    fn get(&self, id: Uuid) -> Result<Option<Dispute>, EuropayError>;
+    fn all(&self) -> Result<Vec<Dispute>, EuropayError>;
+    fn save(&mut self, dispute: &Dispute) -> Result<(), EuropayError>;
+}
+
+/// Adjustments waiting for a settlement batch; they are removed once batched.
+pub trait AdjustmentRepository: Send {
+    fn all(&self) -> Result<Vec<SettlementAdjustment>, EuropayError>;
+    fn save(&mut self, adjustment: &SettlementAdjustment) -> Result<(), EuropayError>;
+    fn remove(&mut self, id: Uuid) -> Result<(), EuropayError>;
+}
+
+/// The journal is append-only: entries are never changed once posted.
+pub trait LedgerRepository: Send {
+    fn accounts(&self) -> Result<Vec<LedgerAccount>, EuropayError>;
+    /// Every entry, in the order it was posted.
+    fn entries(&self) -> Result<Vec<JournalEntry>, EuropayError>;
+    /// Stores an entry together with any ledger accounts it is the first to use.
+    fn append(&mut self, accounts: &[LedgerAccount], entry: &JournalEntry) -> Result<(), EuropayError>;
+}
+
+/// The repositories the payment processor loads from and writes through to.
+pub struct Repositories {
+    pub accounts: Box<dyn AccountRepository>,
+    pub cards: Box<dyn CardRepository>,
+    pub merchants: Box<dyn MerchantRepository>,
+    pub transactions: Box<dyn TransactionRepository>,
+    pub captures: Box<dyn CaptureRepository>,
+    pub network_references: Box<dyn ReferenceRepository>,
+    pub advices: Box<dyn ReferenceRepository>, // store-and-forward advices already applied
+    pub transitions: Box<dyn TransitionRepository>,
+    pub ledger: Box<dyn LedgerRepository>,
+}
+
+impl Repositories {
+    pub fn in_memory() -> Self {
+        Self {
+            accounts: Box::new(memory::InMemoryAccountRepository::default()),
+            cards: Box::new(memory::InMemoryCardRepository::default()),
+            merchants: Box::new(memory::InMemoryMerchantRepository::default()),
+            transactions: Box::new(memory::InMemoryTransactionRepository::default()),
+            captures: Box::new(memory::InMemoryCaptureRepository::default()),
+            network_references: Box::new(memory::InMemoryReferenceRepository::default()),
+            advices: Box::new(memory::InMemoryReferenceRepository::default()),
+            transitions: Box::new(memory::InMemoryTransitionRepository::default()),
+            ledger: Box::new(memory::InMemoryLedgerRepository::default()),
+        }
+    }
+}
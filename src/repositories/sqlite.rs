@@ -0,0 +1,590 @@
+// SQLite repositories. All of them share one connection, opened from `DatabaseConfig::url`.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use uuid::Uuid;
+
+use crate::core::currency::Currency;
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
+use crate::models::accounts::Account;
+use crate::models::cards::PaymentCard;
+use crate::models::ledger::{JournalEntry, LedgerAccount, Posting};
+use crate::models::lifecycle::TransitionEvent;
+use crate::models::merchants::Merchant;
+use crate::models::transactions::{Capture, Transaction};
+use crate::queries;
+use crate::queries::references::ReferenceQueries;
+use crate::repositories::{
+    AccountRepository, AdjustmentRepository, BatchRepository, CaptureRepository, CardRepository, DisputeRepository,
+    LedgerRepository, MerchantRepository, ReferenceRepository, Repositories, TransactionRepository, TransitionRepository,
+};
+use crate::services::disputes::Dispute;
+use crate::services::security::SecurityManager;
+use crate::services::settlement::{SettlementAdjustment, SettlementBatch};
+
+#[derive(Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+    security: Arc<SecurityManager>, // encrypts card data at rest
+}
+
+impl SqliteStore {
+    /// Opens `sqlite::memory:` or `sqlite://<path>` and brings its schema up to date. Card
+    /// data is encrypted with `security`, whose key must be the same on every open.
+    pub fn open(url: &str, security: SecurityManager) -> Result<Self, EuropayError> {
+        let path = url.strip_prefix("sqlite:")
+            .map(|rest| rest.strip_prefix("//").unwrap_or(rest))
+            .ok_or_else(|| EuropayError::Storage(format!("Unsupported database URL: {}", url)))?;
+        let mut connection = match path {
+            ":memory:" => Connection::open_in_memory()?,
+            path => Connection::open(path)?,
+        };
+        connection.pragma_update(None, "foreign_keys", true)?;
+        migrate(&mut connection)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            security: Arc::new(security),
+        })
+    }
+
+    pub fn repositories(&self) -> Repositories {
+        Repositories {
+            accounts: Box::new(SqliteAccountRepository(self.clone())),
+            cards: Box::new(SqliteCardRepository(self.clone())),
+            merchants: Box::new(SqliteMerchantRepository(self.clone())),
+            transactions: Box::new(SqliteTransactionRepository(self.clone())),
+            captures: Box::new(SqliteCaptureRepository(self.clone())),
+            network_references: Box::new(SqliteReferenceRepository(self.clone(), &queries::references::NETWORK)),
+            advices: Box::new(SqliteReferenceRepository(self.clone(), &queries::references::ADVICES)),
+            transitions: Box::new(SqliteTransitionRepository(self.clone())),
+            ledger: Box::new(SqliteLedgerRepository(self.clone())),
+        }
+    }
+
+    pub fn batches(&self) -> SqliteBatchRepository {
+        SqliteBatchRepository(self.clone())
+    }
+
+    pub fn adjustments(&self) -> SqliteAdjustmentRepository {
+        SqliteAdjustmentRepository(self.clone())
+    }
+
+    pub fn disputes(&self) -> SqliteDisputeRepository {
+        SqliteDisputeRepository(self.clone())
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Connection>, EuropayError> {
+        self.connection.lock().map_err(|_| EuropayError::Storage("Database connection poisoned".to_string()))
+    }
+
+    fn get_document<T: DeserializeOwned>(&self, sql: &str, id: Uuid) -> Result<Option<T>, EuropayError> {
+        let data: Option<String> = self.lock()?.query_row(sql, params![id.to_string()], |row| row.get(0)).optional()?;
+        data.map(|data| serde_json::from_str(&data)).transpose().map_err(EuropayError::from)
+    }
+
+    fn all_documents<T: DeserializeOwned>(&self, sql: &str) -> Result<Vec<T>, EuropayError> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(sql)?;
+        let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+        rows.map(|data| Ok(serde_json::from_str(&data?)?)).collect()
+    }
+}
+
+// Applies the migrations newer than the database's schema version, each in its own transaction
+fn migrate(connection: &mut Connection) -> Result<(), EuropayError> {
+    let version: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (index, migration) in queries::migrations::MIGRATIONS.iter().enumerate().skip(version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(migration)?;
+        transaction.pragma_update(None, "user_version", index + 1)?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+// Enums are stored by their serde name, e.g. "Active"
+fn label<T: Serialize>(value: &T) -> Result<String, EuropayError> {
+    match serde_json::to_value(value)? {
+        serde_json::Value::String(label) => Ok(label),
+        other => Err(EuropayError::Storage(format!("Expected a string label, got {}", other))),
+    }
+}
+
+fn from_label<T: DeserializeOwned>(label: String) -> Result<T, EuropayError> {
+    Ok(serde_json::from_value(serde_json::Value::String(label))?)
+}
+
+// Fixed-width UTC timestamps, so that they sort as text
+fn timestamp(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+fn parse_timestamp(at: &str) -> Result<DateTime<Utc>, EuropayError> {
+    Ok(DateTime::parse_from_rfc3339(at).map_err(|e| EuropayError::Storage(e.to_string()))?.with_timezone(&Utc))
+}
+
+fn parse_uuid(id: &str) -> Result<Uuid, EuropayError> {
+    Uuid::parse_str(id).map_err(|e| EuropayError::Storage(e.to_string()))
+}
+
+fn parse_currency(code: &str) -> Result<Currency, EuropayError> {
+    Currency::from_code(code).ok_or_else(|| EuropayError::Storage(format!("Unknown currency {}", code)))
+}
+
+pub struct SqliteAccountRepository(SqliteStore);
+
+impl AccountRepository for SqliteAccountRepository {
+    fn get(&self, id: Uuid) -> Result<Option<Account>, EuropayError> {
+        self.0.get_document(queries::accounts::SELECT_BY_ID, id)
+    }
+
+    fn all(&self) -> Result<Vec<Account>, EuropayError> {
+        self.0.all_documents(queries::accounts::SELECT_ALL)
+    }
+
+    fn save(&mut self, account: &Account) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::accounts::UPSERT, params![
+            account.id.to_string(),
+            account.currency.code(),
+            label(&account.status)?,
+            timestamp(&account.created_at),
+            serde_json::to_string(account)?,
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteCardRepository(SqliteStore);
+
+impl SqliteCardRepository {
+    // The document carries neither PAN nor CVV; the PAN is decrypted back into it
+    fn card(&self, data: String, encrypted_pan: Vec<u8>) -> Result<PaymentCard, EuropayError> {
+        let mut card: PaymentCard = serde_json::from_str(&data)?;
+        let pan = self.0.security.decrypt_data(&encrypted_pan).map_err(EuropayError::Storage)?;
+        card.pan = String::from_utf8(pan).map_err(|e| EuropayError::Storage(e.to_string()))?;
+        Ok(card)
+    }
+}
+
+impl CardRepository for SqliteCardRepository {
+    fn get(&self, id: Uuid) -> Result<Option<PaymentCard>, EuropayError> {
+        let row = self.0.lock()?.query_row(queries::cards::SELECT_BY_ID, params![id.to_string()], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        }).optional()?;
+        row.map(|(data, encrypted_pan)| self.card(data, encrypted_pan)).transpose()
+    }
+
+    fn all(&self) -> Result<Vec<PaymentCard>, EuropayError> {
+        let rows = {
+            let connection = self.0.lock()?;
+            let mut statement = connection.prepare(queries::cards::SELECT_ALL)?;
+            let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<Vec<(String, Vec<u8>)>, _>>()?
+        };
+        rows.into_iter().map(|(data, encrypted_pan)| self.card(data, encrypted_pan)).collect()
+    }
+
+    fn save(&mut self, card: &PaymentCard) -> Result<(), EuropayError> {
+        let encrypted_pan = self.0.security.encrypt_data(card.pan.as_bytes()).map_err(EuropayError::Storage)?;
+        self.0.lock()?.execute(queries::cards::UPSERT, params![
+            card.id.to_string(),
+            card.account_id.to_string(),
+            self.0.security.pan_hash(&card.pan),
+            encrypted_pan,
+            label(&card.status)?,
+            timestamp(&card.issued_at),
+            serde_json::to_string(card)?,
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteMerchantRepository(SqliteStore);
+
+impl MerchantRepository for SqliteMerchantRepository {
+    fn get(&self, id: Uuid) -> Result<Option<Merchant>, EuropayError> {
+        self.0.get_document(queries::merchants::SELECT_BY_ID, id)
+    }
+
+    fn all(&self) -> Result<Vec<Merchant>, EuropayError> {
+        self.0.all_documents(queries::merchants::SELECT_ALL)
+    }
+
+    fn save(&mut self, merchant: &Merchant) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::merchants::UPSERT, params![
+            merchant.id.to_string(),
+            merchant.acquirer_id.to_string(),
+            merchant.card_acceptor_id,
+            merchant.category,
+            label(&merchant.status)?,
+            timestamp(&merchant.registered_at),
+            serde_json::to_string(merchant)?,
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteTransactionRepository(SqliteStore);
+
+impl TransactionRepository for SqliteTransactionRepository {
+    fn get(&self, id: Uuid) -> Result<Option<Transaction>, EuropayError> {
+        self.0.get_document(queries::transactions::SELECT_BY_ID, id)
+    }
+
+    fn all(&self) -> Result<Vec<Transaction>, EuropayError> {
+        self.0.all_documents(queries::transactions::SELECT_ALL)
+    }
+
+    fn save(&mut self, transaction: &Transaction) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::transactions::UPSERT, params![
+            transaction.id.to_string(),
+            transaction.card_id.to_string(),
+            transaction.merchant_id.to_string(),
+            transaction.original_transaction_id.map(|id| id.to_string()),
+            label(&transaction.transaction_type)?,
+            label(&transaction.status)?,
+            timestamp(&transaction.created_at),
+            serde_json::to_string(transaction)?,
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteCaptureRepository(SqliteStore);
+
+impl CaptureRepository for SqliteCaptureRepository {
+    fn get(&self, id: Uuid) -> Result<Option<Capture>, EuropayError> {
+        self.0.get_document(queries::captures::SELECT_BY_ID, id)
+    }
+
+    fn all(&self) -> Result<Vec<Capture>, EuropayError> {
+        self.0.all_documents(queries::captures::SELECT_ALL)
+    }
+
+    fn save(&mut self, capture: &Capture) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::captures::UPSERT, params![
+            capture.id.to_string(),
+            capture.transaction_id.to_string(),
+            timestamp(&capture.captured_at),
+            serde_json::to_string(capture)?,
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteReferenceRepository(SqliteStore, &'static ReferenceQueries);
+
+impl ReferenceRepository for SqliteReferenceRepository {
+    fn all(&self) -> Result<Vec<(String, Uuid)>, EuropayError> {
+        let connection = self.0.lock()?;
+        let mut statement = connection.prepare(self.1.select_all)?;
+        let rows = statement.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        rows.map(|row| {
+            let (reference, transaction_id) = row?;
+            Ok((reference, parse_uuid(&transaction_id)?))
+        }).collect()
+    }
+
+    fn save(&mut self, reference: &str, transaction_id: Uuid) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(self.1.insert, params![reference, transaction_id.to_string()])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteTransitionRepository(SqliteStore);
+
+impl TransitionRepository for SqliteTransitionRepository {
+    fn all(&self) -> Result<Vec<TransitionEvent>, EuropayError> {
+        let connection = self.0.lock()?;
+        let mut statement = connection.prepare(queries::transitions::SELECT_ALL)?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?, row.get::<_, String>(4)?, row.get::<_, String>(5)?))
+        })?;
+        rows.map(|row| {
+            let (transaction_id, from, to, actor, reason, occurred_at) = row?;
+            Ok(TransitionEvent {
+                transaction_id: parse_uuid(&transaction_id)?,
+                from: from_label(from)?,
+                to: from_label(to)?,
+                actor: from_label(actor)?,
+                reason,
+                occurred_at: parse_timestamp(&occurred_at)?,
+            })
+        }).collect()
+    }
+
+    fn append(&mut self, event: &TransitionEvent) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::transitions::INSERT, params![
+            event.transaction_id.to_string(),
+            label(&event.from)?,
+            label(&event.to)?,
+            label(&event.actor)?,
+            event.reason,
+            timestamp(&event.occurred_at),
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteBatchRepository(SqliteStore);
+
+impl BatchRepository for SqliteBatchRepository {
+    fn get(&self, id: Uuid) -> Result<Option<SettlementBatch>, EuropayError> {
+        self.0.get_document(queries::batches::SELECT_BY_ID, id)
+    }
+
+    fn all(&self) -> Result<Vec<SettlementBatch>, EuropayError> {
+        self.0.all_documents(queries::batches::SELECT_ALL)
+    }
+
+    fn save(&mut self, batch: &SettlementBatch) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::batches::UPSERT, params![
+            batch.id.to_string(),
+            batch.issuer_id.to_string(),
+            batch.acquirer_id.to_string(),
+            label(&batch.status)?,
+            timestamp(&batch.created_at),
+            serde_json::to_string(batch)?,
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteAdjustmentRepository(SqliteStore);
+
+impl AdjustmentRepository for SqliteAdjustmentRepository {
+    fn all(&self) -> Result<Vec<SettlementAdjustment>, EuropayError> {
+        self.0.all_documents(queries::adjustments::SELECT_ALL)
+    }
+
+    fn save(&mut self, adjustment: &SettlementAdjustment) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::adjustments::UPSERT, params![
+            adjustment.id.to_string(),
+            adjustment.issuer_id.to_string(),
+            adjustment.acquirer_id.to_string(),
+            timestamp(&adjustment.created_at),
+            serde_json::to_string(adjustment)?,
+        ])?;
+        Ok(())
+    }
+
+    fn remove(&mut self, id: Uuid) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::adjustments::DELETE, params![id.to_string()])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteDisputeRepository(SqliteStore);
+
+impl DisputeRepository for SqliteDisputeRepository {
+    fn get(&self, id: Uuid) -> Result<Option<Dispute>, EuropayError> {
+        self.0.get_document(queries::disputes::SELECT_BY_ID, id)
+    }
+
+    fn all(&self) -> Result<Vec<Dispute>, EuropayError> {
+        self.0.all_documents(queries::disputes::SELECT_ALL)
+    }
+
+    fn save(&mut self, dispute: &Dispute) -> Result<(), EuropayError> {
+        self.0.lock()?.execute(queries::disputes::UPSERT, params![
+            dispute.id.to_string(),
+            dispute.transaction_id.to_string(),
+            label(&dispute.stage)?,
+            timestamp(&dispute.opened_at),
+            serde_json::to_string(dispute)?,
+        ])?;
+        Ok(())
+    }
+}
+
+pub struct SqliteLedgerRepository(SqliteStore);
+
+impl LedgerRepository for SqliteLedgerRepository {
+    fn accounts(&self) -> Result<Vec<LedgerAccount>, EuropayError> {
+        let connection = self.0.lock()?;
+        let mut statement = connection.prepare(queries::ledger::SELECT_ACCOUNTS)?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, String>(3)?, row.get::<_, String>(4)?))
+        })?;
+        rows.map(|row| {
+            let (id, account_type, owner_id, currency, opened_at) = row?;
+            Ok(LedgerAccount {
+                id: parse_uuid(&id)?,
+                account_type: from_label(account_type)?,
+                owner_id: owner_id.as_deref().map(parse_uuid).transpose()?,
+                currency: parse_currency(&currency)?,
+                opened_at: parse_timestamp(&opened_at)?,
+            })
+        }).collect()
+    }
+
+    fn entries(&self) -> Result<Vec<JournalEntry>, EuropayError> {
+        let connection = self.0.lock()?;
+
+        let mut postings: HashMap<String, Vec<Posting>> = HashMap::new();
+        let mut statement = connection.prepare(queries::ledger::SELECT_POSTINGS)?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?, row.get::<_, i64>(3)?, row.get::<_, String>(4)?))
+        })?;
+        for row in rows {
+            let (entry_id, ledger_account_id, side, minor_units, currency) = row?;
+            postings.entry(entry_id).or_default().push(Posting {
+                ledger_account_id: parse_uuid(&ledger_account_id)?,
+                side: from_label(side)?,
+                amount: Money::from_minor(minor_units, parse_currency(&currency)?),
+            });
+        }
+
+        let mut statement = connection.prepare(queries::ledger::SELECT_ENTRIES)?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, String>(3)?))
+        })?;
+        rows.map(|row| {
+            let (id, description, reference, posted_at) = row?;
+            Ok(JournalEntry {
+                postings: postings.remove(&id).unwrap_or_default(),
+                id: parse_uuid(&id)?,
+                description,
+                reference: reference.as_deref().map(parse_uuid).transpose()?,
+                posted_at: parse_timestamp(&posted_at)?,
+            })
+        }).collect()
+    }
+
+    fn append(&mut self, accounts: &[LedgerAccount], entry: &JournalEntry) -> Result<(), EuropayError> {
+        let mut connection = self.0.lock()?;
+        let transaction = connection.transaction()?;
+        for account in accounts {
+            transaction.execute(queries::ledger::INSERT_ACCOUNT, params![
+                account.id.to_string(),
+                label(&account.account_type)?,
+                account.owner_id.map(|id| id.to_string()),
+                account.currency.code(),
+                timestamp(&account.opened_at),
+            ])?;
+        }
+        transaction.execute(queries::ledger::INSERT_ENTRY, params![
+            entry.id.to_string(),
+            entry.description,
+            entry.reference.map(|id| id.to_string()),
+            timestamp(&entry.posted_at),
+        ])?;
+        for (position, posting) in entry.postings.iter().enumerate() {
+            transaction.execute(queries::ledger::INSERT_POSTING, params![
+                entry.id.to_string(),
+                position as i64,
+                posting.ledger_account_id.to_string(),
+                label(&posting.side)?,
+                posting.amount.minor_units(),
+                posting.amount.currency().code(),
+            ])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::CurrencyConverter;
+    use crate::core::rates::InMemoryRateProvider;
+    use crate::models::transactions::{fixtures, PaymentProcessor, TransactionStatus};
+    use crate::services::disputes::{ChargebackRequest, DisputeDeadlines, DisputeService, DisputeStage};
+    use crate::services::settlement::{SettlementService, SettlementStatus};
+
+    // Stands in for `SecurityConfig::data_key`, which must not change between opens
+    const DATA_KEY: [u8; 32] = [7; 32];
+
+    fn open(url: &str) -> Result<SqliteStore, EuropayError> {
+        SqliteStore::open(url, SecurityManager::with_key(&DATA_KEY).unwrap())
+    }
+
+    fn load_processor(store: &SqliteStore) -> PaymentProcessor {
+        let converter = CurrencyConverter::new(Arc::new(InMemoryRateProvider::new()));
+        PaymentProcessor::load(converter, 0, store.repositories()).unwrap()
+    }
+
+    fn load_services(store: &SqliteStore) -> (SettlementService, DisputeService) {
+        let settlement = SettlementService::load(Box::new(store.batches()), Box::new(store.adjustments())).unwrap();
+        let disputes = DisputeService::load(DisputeDeadlines::default(), Box::new(store.disputes())).unwrap();
+        (settlement, disputes)
+    }
+
+    #[test]
+    fn test_state_survives_a_restart() {
+        let path = std::env::temp_dir().join(format!("europay-{}.db", Uuid::new_v4()));
+        let url = format!("sqlite://{}", path.display());
+        let eur = |amount: &str| Money::parse(amount, Currency::EUR).unwrap();
+
+        let store = open(&url).unwrap();
+        let mut processor = load_processor(&store);
+        let fixtures::Cardholder { account_id, card_id, merchant_id, acquirer_id, .. } = fixtures::add_cardholder(&mut processor, Currency::EUR, "100.00");
+
+        let captured = processor.authorize_transaction(card_id, merchant_id, eur("30.00")).unwrap();
+        processor.capture_partial(captured, eur("10.00"), false).unwrap();
+        processor.capture_transaction(captured).unwrap();
+        let held = processor.authorize_transaction(card_id, merchant_id, eur("20.00")).unwrap();
+        processor.record_network_reference("010000000111111".to_string(), held).unwrap();
+        let advised = processor.record_authorization_advice("012000000222222", card_id, merchant_id, eur("5.00")).unwrap();
+
+        let (mut settlement, mut disputes) = load_services(&store);
+        let issuer_id = Uuid::new_v4();
+        let batch_id = settlement.create_batch(issuer_id, acquirer_id, &[captured], &processor).unwrap();
+        settlement.process_settlement(batch_id, &mut processor).unwrap();
+        let chargeback = ChargebackRequest { transaction_id: captured, issuer_id, reason_code: "4837".to_string(), amount: eur("30.00") };
+        let dispute_id = disputes.open_chargeback(&mut processor, chargeback, &mut settlement, chrono::Utc::now()).unwrap();
+        drop((processor, settlement, disputes, store));
+
+        // Reopening runs no migration twice and restores everything written above
+        let store = open(&url).unwrap();
+        let mut processor = load_processor(&store);
+        let account = processor.get_account(account_id).unwrap();
+        assert_eq!(account.ledger_balance, eur("100.00"));
+        assert_eq!(account.available_balance().unwrap(), eur("75.00"));
+        assert_eq!(processor.get_transaction(captured).unwrap().status, TransactionStatus::Settled);
+        assert_eq!(processor.get_transaction(held).unwrap().status, TransactionStatus::Authorized);
+        assert_eq!(processor.captures_for(captured).len(), 2);
+        let statuses: Vec<_> = processor.transitions_for(captured).iter().map(|event| event.to).collect();
+        assert_eq!(statuses, [TransactionStatus::Authorized, TransactionStatus::Captured, TransactionStatus::Settled]);
+        assert_eq!(processor.transaction_for_network_reference("010000000111111"), Some(held));
+        // A repeated advice is still recognised as already applied
+        assert_eq!(processor.record_authorization_advice("012000000222222", card_id, merchant_id, eur("5.00")).unwrap(), advised);
+        assert_eq!(processor.get_card(card_id).unwrap().pan, fixtures::PAN);
+        assert_eq!(processor.get_card(card_id).unwrap().cvv, "");
+        assert!(processor.get_merchant(merchant_id).is_some());
+        // Single records also read back by id, without a full load
+        assert_eq!(store.repositories().cards.get(card_id).unwrap().unwrap().pan, fixtures::PAN);
+        assert_eq!(store.batches().get(batch_id).unwrap().unwrap().status, SettlementStatus::Completed);
+        assert!(store.disputes().get(Uuid::new_v4()).unwrap().is_none());
+        assert_eq!(processor.ledger().entries().unwrap().len(), 5);
+        processor.ledger().verify().unwrap();
+
+        let (mut settlement, disputes) = load_services(&store);
+        assert_eq!(settlement.get_batch(&batch_id).unwrap().status, SettlementStatus::Completed);
+        assert_eq!(settlement.pending_adjustments().len(), 1);
+        assert_eq!(disputes.get_dispute(dispute_id).unwrap().stage, DisputeStage::Chargeback);
+
+        // A batched adjustment is no longer pending after the next restart
+        settlement.create_batch(issuer_id, acquirer_id, &[], &processor).unwrap();
+        assert!(load_services(&store).0.pending_adjustments().is_empty());
+
+        drop((processor, settlement, disputes, store));
+
+        // Neither the PAN nor the CVV is on disk in clear
+        let file = std::fs::read(&path).unwrap();
+        assert!(!file.windows(16).any(|window| window == fixtures::PAN.as_bytes()));
+        assert!(!file.windows(5).any(|window| window == b"\"123\""));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_open_rejects_unknown_url() {
+        assert!(open("sqlite::memory:").is_ok());
+        assert!(matches!(open("postgres://localhost/europay"), Err(EuropayError::Storage(_))));
+    }
+}
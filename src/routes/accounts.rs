@@ -0,0 +1,17 @@
+// Account routes
+
+use axum::{routing::{get, post}, Router};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::controllers::accounts;
+use crate::models::transactions::PaymentProcessor;
+
+pub fn create_routes(processor: Arc<Mutex<PaymentProcessor>>) -> Router<()> {
+    Router::new()
+        .route("/", get(accounts::list_accounts).post(accounts::create_account))
+        .route("/:id", get(accounts::get_account))
+        .route("/:id/status", post(accounts::update_account_status))
+        .route("/:id/deposits", post(accounts::deposit))
+        .with_state(processor)
+}
\ No newline at end of file
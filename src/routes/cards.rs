@@ -0,0 +1,16 @@
+// Card routes
+
+use axum::{routing::{get, post}, Router};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::controllers::cards;
+use crate::models::transactions::PaymentProcessor;
+
+pub fn create_routes(processor: Arc<Mutex<PaymentProcessor>>) -> Router<()> {
+    Router::new()
+        .route("/", get(cards::list_cards).post(cards::issue_card))
+        .route("/:id", get(cards::get_card))
+        .route("/:id/status", post(cards::update_card_status))
+        .with_state(processor)
+}
\ No newline at end of file
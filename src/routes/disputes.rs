@@ -0,0 +1,18 @@
+// Dispute routes
+
+use axum::{routing::{get, post}, Router};
+
+use crate::controllers::disputes::{self, DisputeState};
+
+pub fn create_routes(state: DisputeState) -> Router<()> {
+    Router::new()
+        .route("/", get(disputes::list_disputes))
+        .route("/:id", get(disputes::get_dispute))
+        .route("/chargeback", post(disputes::raise_chargeback))
+        .route("/representment", post(disputes::submit_representment))
+        .route("/pre-arbitration", post(disputes::submit_pre_arbitration))
+        .route("/arbitration", post(disputes::request_arbitration))
+        .route("/accept", post(disputes::accept_dispute))
+        .route("/ruling", post(disputes::record_ruling))
+        .with_state(state)
+}
@@ -0,0 +1,16 @@
+// Merchant routes
+
+use axum::{routing::{get, post}, Router};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::controllers::merchants;
+use crate::models::transactions::PaymentProcessor;
+
+pub fn create_routes(processor: Arc<Mutex<PaymentProcessor>>) -> Router<()> {
+    Router::new()
+        .route("/", get(merchants::list_merchants).post(merchants::create_merchant))
+        .route("/:id", get(merchants::get_merchant))
+        .route("/:id/status", post(merchants::update_merchant_status))
+        .with_state(processor)
+}
\ No newline at end of file
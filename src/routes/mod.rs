@@ -2,4 +2,8 @@
 
 pub mod transactions;
 pub mod network;
-pub mod settlement;
\ No newline at end of file
+pub mod settlement;
+pub mod disputes;
+pub mod accounts;
+pub mod cards;
+pub mod merchants;
\ No newline at end of file
@@ -1,10 +1,15 @@
 // Network routes
 
 use axum::{routing::post, Router};
+use std::sync::Arc;
 
 use crate::controllers::network;
+use crate::services::network::HttpNetworkService;
 
-pub fn create_routes() -> Router {
+pub fn create_routes(network: Arc<HttpNetworkService>) -> Router<()> {
     Router::new()
         .route("/message", post(network::handle_network_message))
+        .route("/nodes", post(network::register_node))
+        .route("/authorize", post(network::forward_authorization))
+        .with_state(network)
 }
\ No newline at end of file
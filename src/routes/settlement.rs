@@ -1,15 +1,20 @@
 // Settlement routes
 
-use axum::{routing::post, Router};
+use axum::{middleware::from_fn_with_state, routing::{get, post}, Router};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::controllers::settlement;
-use crate::services::settlement::SettlementService;
+use crate::controllers::settlement::{self, SettlementState};
+use crate::middlewares::idempotency_middleware;
+use crate::services::idempotency::IdempotencyStore;
 
-pub fn create_routes(settlement_service: Arc<Mutex<SettlementService>>) -> Router<()> {
+pub fn create_routes(state: SettlementState, idempotency: Arc<Mutex<IdempotencyStore>>) -> Router<()> {
     Router::new()
         .route("/batch", post(settlement::create_settlement_batch))
         .route("/process", post(settlement::process_settlement))
-        .with_state(settlement_service)
+        .route_layer(from_fn_with_state(idempotency, idempotency_middleware))
+        .route("/batches", get(settlement::list_batches))
+        .route("/batches/:id", get(settlement::get_batch))
+        .route("/adjustments", get(settlement::list_pending_adjustments))
+        .with_state(state)
 }
\ No newline at end of file
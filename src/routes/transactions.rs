@@ -1,16 +1,25 @@
 // Transaction routes
 
-use axum::{routing::post, Router};
+use axum::{middleware::from_fn_with_state, routing::{get, post}, Router};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::controllers::transactions;
+use crate::middlewares::idempotency_middleware;
 use crate::models::transactions::PaymentProcessor;
+use crate::services::idempotency::IdempotencyStore;
 
-pub fn create_routes(processor: Arc<Mutex<PaymentProcessor>>) -> Router<()> {
+pub fn create_routes(processor: Arc<Mutex<PaymentProcessor>>, idempotency: Arc<Mutex<IdempotencyStore>>) -> Router<()> {
     Router::new()
         .route("/authorize", post(transactions::authorize_transaction))
+        .route("/increment", post(transactions::increment_authorization))
         .route("/capture", post(transactions::capture_transaction))
         .route("/settle", post(transactions::settle_transaction))
+        .route("/refund", post(transactions::refund_transaction))
+        .route_layer(from_fn_with_state(idempotency, idempotency_middleware))
+        .route("/", get(transactions::list_transactions))
+        .route("/:id", get(transactions::get_transaction))
+        .route("/:id/transitions", get(transactions::get_transitions))
+        .route("/:id/captures", get(transactions::get_captures))
         .with_state(processor)
 }
\ No newline at end of file
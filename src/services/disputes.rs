@@ -0,0 +1,396 @@
+// Chargeback and dispute lifecycle between issuers and acquirers
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
+use crate::models::transactions::{PaymentProcessor, Transaction, TransactionStatus, TransactionType};
+use crate::repositories::memory::InMemoryDisputeRepository;
+use crate::repositories::DisputeRepository;
+use crate::services::settlement::{SettlementAdjustment, SettlementService};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisputeStage {
+    Chargeback,     // Raised by the issuer; the acquirer may represent or accept
+    Representment,  // The acquirer contested; the issuer may go to pre-arbitration or accept
+    PreArbitration, // The issuer contested again; the acquirer may accept or let it go to arbitration
+    Arbitration,    // Awaiting the network's ruling
+    Closed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisputeParty {
+    Issuer,
+    Acquirer,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisputeEvidence {
+    pub submitted_by: DisputeParty,
+    pub stage: DisputeStage,
+    pub description: String,
+    pub submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dispute {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    pub chargeback_transaction_id: Uuid,
+    pub issuer_id: Uuid,
+    pub acquirer_id: Uuid,
+    pub reason_code: String,
+    pub amount: Money,
+    pub stage: DisputeStage,
+    pub deadline: Option<DateTime<Utc>>, // For the party expected to act next
+    pub winner: Option<DisputeParty>,
+    pub evidence: Vec<DisputeEvidence>,
+    pub opened_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChargebackRequest {
+    pub transaction_id: Uuid,
+    pub issuer_id: Uuid,
+    pub reason_code: String, // Card scheme reason code, e.g. 4837
+    #[serde(flatten)]
+    pub amount: Money,
+}
+
+/// Time each party has to act at each stage before the dispute is decided against it.
+#[derive(Debug, Clone, Copy)]
+pub struct DisputeDeadlines {
+    pub representment: Duration,
+    pub pre_arbitration: Duration,
+    pub pre_arbitration_response: Duration,
+}
+
+impl Default for DisputeDeadlines {
+    fn default() -> Self {
+        Self {
+            representment: Duration::days(45),
+            pre_arbitration: Duration::days(30),
+            pre_arbitration_response: Duration::days(30),
+        }
+    }
+}
+
+/// Runs disputes through their stages. Disputes are written through to a repository.
+pub struct DisputeService {
+    disputes: HashMap<Uuid, Dispute>,
+    deadlines: DisputeDeadlines,
+    repository: Box<dyn DisputeRepository>,
+}
+
+impl DisputeService {
+    pub fn new(deadlines: DisputeDeadlines) -> Self {
+        Self {
+            disputes: HashMap::new(),
+            deadlines,
+            repository: Box::new(InMemoryDisputeRepository::default()),
+        }
+    }
+
+    /// Restores the disputes from the repository.
+    pub fn load(deadlines: DisputeDeadlines, repository: Box<dyn DisputeRepository>) -> Result<Self, EuropayError> {
+        let disputes = repository.all()?.into_iter().map(|dispute| (dispute.id, dispute)).collect();
+        Ok(Self {
+            disputes,
+            deadlines,
+            repository,
+        })
+    }
+
+    /// Records the chargeback and opens a dispute for it. The amount is posted back to the
+    /// cardholder and returned to the issuer in the next settlement batch.
+    pub fn open_chargeback(
+        &mut self,
+        processor: &mut PaymentProcessor,
+        request: ChargebackRequest,
+        settlement: &mut SettlementService,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid, EuropayError> {
+        let transaction = processor.get_transaction(request.transaction_id).ok_or(EuropayError::TransactionNotFound)?;
+        validate_chargeback(transaction, &request.reason_code, &request.amount)?;
+        if self.disputes.values().any(|d| d.transaction_id == transaction.id && d.stage != DisputeStage::Closed) {
+            return Err(EuropayError::InvalidState("Transaction already disputed".to_string()));
+        }
+        let acquirer_id = processor.get_merchant(transaction.merchant_id).ok_or(EuropayError::MerchantNotFound)?.acquirer_id;
+        let chargeback_transaction_id = processor.record_chargeback(request.transaction_id, request.amount)?;
+
+        let dispute = Dispute {
+            id: Uuid::new_v4(),
+            transaction_id: request.transaction_id,
+            chargeback_transaction_id,
+            issuer_id: request.issuer_id,
+            acquirer_id,
+            reason_code: request.reason_code,
+            amount: request.amount,
+            stage: DisputeStage::Chargeback,
+            deadline: Some(now + self.deadlines.representment),
+            winner: None,
+            evidence: Vec::new(),
+            opened_at: now,
+            closed_at: None,
+        };
+        adjust(processor, settlement, &dispute, false, "Chargeback", now)?;
+
+        let dispute_id = dispute.id;
+        self.repository.save(&dispute)?;
+        self.disputes.insert(dispute_id, dispute);
+        Ok(dispute_id)
+    }
+
+    /// The acquirer contests the chargeback with evidence; the funds go back to it.
+    pub fn represent(&mut self, processor: &mut PaymentProcessor, dispute_id: Uuid, evidence: String, settlement: &mut SettlementService, now: DateTime<Utc>) -> Result<(), EuropayError> {
+        let deadline = self.deadlines.pre_arbitration;
+        let dispute = self.open_dispute(dispute_id, DisputeStage::Chargeback, now)?;
+        adjust(processor, settlement, dispute, true, "Representment", now)?;
+        dispute.advance(DisputeStage::Representment, DisputeParty::Acquirer, evidence, now + deadline, now);
+        self.save_dispute(dispute_id)
+    }
+
+    /// The issuer rejects the representment with further evidence.
+    pub fn pre_arbitrate(&mut self, dispute_id: Uuid, evidence: String, now: DateTime<Utc>) -> Result<(), EuropayError> {
+        let deadline = self.deadlines.pre_arbitration_response;
+        let dispute = self.open_dispute(dispute_id, DisputeStage::Representment, now)?;
+        dispute.advance(DisputeStage::PreArbitration, DisputeParty::Issuer, evidence, now + deadline, now);
+        self.save_dispute(dispute_id)
+    }
+
+    /// The acquirer declines the pre-arbitration and the case goes to the network.
+    pub fn escalate_to_arbitration(&mut self, dispute_id: Uuid, evidence: String, now: DateTime<Utc>) -> Result<(), EuropayError> {
+        let dispute = self.open_dispute(dispute_id, DisputeStage::PreArbitration, now)?;
+        dispute.advance(DisputeStage::Arbitration, DisputeParty::Acquirer, evidence, now, now);
+        dispute.deadline = None;
+        self.save_dispute(dispute_id)
+    }
+
+    /// The party expected to act concedes, closing the dispute in the other's favour.
+    pub fn accept(&mut self, processor: &mut PaymentProcessor, dispute_id: Uuid, party: DisputeParty, settlement: &mut SettlementService, now: DateTime<Utc>) -> Result<(), EuropayError> {
+        let dispute = self.disputes.get_mut(&dispute_id).ok_or(EuropayError::DisputeNotFound)?;
+        let expected = match dispute.stage {
+            DisputeStage::Chargeback | DisputeStage::PreArbitration => DisputeParty::Acquirer,
+            DisputeStage::Representment => DisputeParty::Issuer,
+            DisputeStage::Arbitration => return Err(EuropayError::InvalidState("Dispute is in arbitration".to_string())),
+            DisputeStage::Closed => return Err(EuropayError::InvalidState("Dispute closed".to_string())),
+        };
+        if party != expected {
+            return Err(EuropayError::InvalidState("Not this party's turn to act".to_string()));
+        }
+        decide(processor, dispute, other(party), settlement, now)?;
+        self.save_dispute(dispute_id)
+    }
+
+    /// Records the network's arbitration ruling.
+    pub fn rule(&mut self, processor: &mut PaymentProcessor, dispute_id: Uuid, winner: DisputeParty, settlement: &mut SettlementService, now: DateTime<Utc>) -> Result<(), EuropayError> {
+        let dispute = self.disputes.get_mut(&dispute_id).ok_or(EuropayError::DisputeNotFound)?;
+        if dispute.stage != DisputeStage::Arbitration {
+            return Err(EuropayError::InvalidState("Dispute not in arbitration".to_string()));
+        }
+        decide(processor, dispute, winner, settlement, now)?;
+        self.save_dispute(dispute_id)
+    }
+
+    /// Closes disputes whose deadline passed against the party that failed to act.
+    pub fn expire_overdue(&mut self, processor: &mut PaymentProcessor, settlement: &mut SettlementService, now: DateTime<Utc>) -> Result<Vec<Uuid>, EuropayError> {
+        let mut expired = Vec::new();
+        for dispute in self.disputes.values_mut() {
+            if dispute.deadline.is_some_and(|deadline| deadline < now) {
+                let winner = match dispute.stage {
+                    DisputeStage::Representment => DisputeParty::Acquirer,
+                    _ => DisputeParty::Issuer,
+                };
+                decide(processor, dispute, winner, settlement, now)?;
+                self.repository.save(dispute)?;
+                expired.push(dispute.id);
+            }
+        }
+        Ok(expired)
+    }
+
+    pub fn get_dispute(&self, dispute_id: Uuid) -> Option<&Dispute> {
+        self.disputes.get(&dispute_id)
+    }
+
+    pub fn disputes_for_transaction(&self, transaction_id: Uuid) -> Vec<&Dispute> {
+        self.disputes.values().filter(|d| d.transaction_id == transaction_id).collect()
+    }
+
+    pub fn disputes(&self) -> impl Iterator<Item = &Dispute> {
+        self.disputes.values()
+    }
+
+    fn save_dispute(&mut self, dispute_id: Uuid) -> Result<(), EuropayError> {
+        let dispute = self.disputes.get(&dispute_id).ok_or(EuropayError::DisputeNotFound)?;
+        self.repository.save(dispute)
+    }
+
+    fn open_dispute(&mut self, dispute_id: Uuid, stage: DisputeStage, now: DateTime<Utc>) -> Result<&mut Dispute, EuropayError> {
+        let dispute = self.disputes.get_mut(&dispute_id).ok_or(EuropayError::DisputeNotFound)?;
+        if dispute.stage != stage {
+            return Err(EuropayError::InvalidState(format!("Dispute not in {:?} stage", stage)));
+        }
+        if dispute.deadline.is_some_and(|deadline| deadline < now) {
+            return Err(EuropayError::InvalidState("Dispute deadline passed".to_string()));
+        }
+        Ok(dispute)
+    }
+}
+
+impl Dispute {
+    fn advance(&mut self, stage: DisputeStage, party: DisputeParty, description: String, deadline: DateTime<Utc>, now: DateTime<Utc>) {
+        self.evidence.push(DisputeEvidence { submitted_by: party, stage, description, submitted_at: now });
+        self.stage = stage;
+        self.deadline = Some(deadline);
+    }
+}
+
+fn validate_chargeback(transaction: &Transaction, reason_code: &str, amount: &Money) -> Result<(), EuropayError> {
+    if transaction.transaction_type != TransactionType::Purchase || transaction.status != TransactionStatus::Settled {
+        return Err(EuropayError::InvalidState("Only settled purchases can be charged back".to_string()));
+    }
+    if reason_code.trim().is_empty() {
+        return Err(EuropayError::InvalidRequest("Reason code required".to_string()));
+    }
+    if amount.currency() != transaction.amount.currency() {
+        return Err(EuropayError::CurrencyMismatch);
+    }
+    let disputable = transaction.amount.checked_sub(&transaction.refunded_amount)?;
+    if !amount.is_positive() || *amount > disputable {
+        return Err(EuropayError::InvalidAmount("Chargeback exceeds disputable amount".to_string()));
+    }
+    Ok(())
+}
+
+fn other(party: DisputeParty) -> DisputeParty {
+    match party {
+        DisputeParty::Issuer => DisputeParty::Acquirer,
+        DisputeParty::Acquirer => DisputeParty::Issuer,
+    }
+}
+
+// The funds follow the stage: they sit with the issuer after a chargeback or pre-arbitration
+// and with the acquirer after a representment, so closing only moves them if they are on
+// the wrong side
+fn decide(processor: &mut PaymentProcessor, dispute: &mut Dispute, winner: DisputeParty, settlement: &mut SettlementService, now: DateTime<Utc>) -> Result<(), EuropayError> {
+    let funds_with = match dispute.stage {
+        DisputeStage::Chargeback => DisputeParty::Issuer,
+        _ => DisputeParty::Acquirer,
+    };
+    if winner != funds_with {
+        adjust(processor, settlement, dispute, winner == DisputeParty::Acquirer, "Dispute decided", now)?;
+    }
+    dispute.stage = DisputeStage::Closed;
+    dispute.winner = Some(winner);
+    dispute.deadline = None;
+    dispute.closed_at = Some(now);
+    Ok(())
+}
+
+// Moves the disputed funds in the ledger and queues the matching interbank adjustment
+fn adjust(processor: &mut PaymentProcessor, settlement: &mut SettlementService, dispute: &Dispute, to_acquirer: bool, description: &str, now: DateTime<Utc>) -> Result<(), EuropayError> {
+    let description = format!("{} ({})", description, dispute.reason_code);
+    processor.post_chargeback(dispute.chargeback_transaction_id, to_acquirer, &description)?;
+    let amount = if to_acquirer {
+        dispute.amount
+    } else {
+        Money::zero(dispute.amount.currency()).checked_sub(&dispute.amount)?
+    };
+    settlement.add_adjustment(SettlementAdjustment {
+        id: Uuid::new_v4(),
+        issuer_id: dispute.issuer_id,
+        acquirer_id: dispute.acquirer_id,
+        amount,
+        reference: dispute.id,
+        description,
+        created_at: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::Currency;
+    use crate::models::ledger::LedgerAccountType;
+    use crate::models::transactions::fixtures;
+
+    fn settled_purchase(amount: &str) -> (PaymentProcessor, Uuid, Uuid) {
+        let mut processor = fixtures::processor();
+        let cardholder = fixtures::add_cardholder(&mut processor, Currency::EUR, "500.00");
+
+        let tx_id = processor.authorize_transaction(cardholder.card_id, cardholder.merchant_id, Money::parse(amount, Currency::EUR).unwrap()).unwrap();
+        processor.capture_transaction(tx_id).unwrap();
+        processor.settle_transaction(tx_id).unwrap();
+        (processor, tx_id, cardholder.acquirer_id)
+    }
+
+    fn chargeback(transaction_id: Uuid, issuer_id: Uuid, amount: &str) -> ChargebackRequest {
+        ChargebackRequest {
+            transaction_id,
+            issuer_id,
+            reason_code: "4837".to_string(),
+            amount: Money::parse(amount, Currency::EUR).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_arbitration_won_by_issuer_nets_into_next_batch() {
+        let (mut processor, tx_id, acquirer_id) = settled_purchase("80.00");
+        let issuer_id = Uuid::new_v4();
+        let mut disputes = DisputeService::new(DisputeDeadlines::default());
+        let mut settlement = SettlementService::new();
+        let now = Utc::now();
+
+        let dispute_id = disputes.open_chargeback(&mut processor, chargeback(tx_id, issuer_id, "80.00"), &mut settlement, now).unwrap();
+        assert!(disputes.open_chargeback(&mut processor, chargeback(tx_id, issuer_id, "10.00"), &mut settlement, now).is_err());
+        assert!(disputes.pre_arbitrate(dispute_id, "Not received".to_string(), now).is_err());
+
+        disputes.represent(&mut processor, dispute_id, "Signed delivery note".to_string(), &mut settlement, now + Duration::days(10)).unwrap();
+        disputes.pre_arbitrate(dispute_id, "Signature does not match".to_string(), now + Duration::days(20)).unwrap();
+        assert!(disputes.accept(&mut processor, dispute_id, DisputeParty::Issuer, &mut settlement, now).is_err());
+        disputes.escalate_to_arbitration(dispute_id, "Courier confirmation".to_string(), now + Duration::days(25)).unwrap();
+        disputes.rule(&mut processor, dispute_id, DisputeParty::Issuer, &mut settlement, now + Duration::days(60)).unwrap();
+
+        let dispute = disputes.get_dispute(dispute_id).unwrap();
+        assert_eq!(dispute.stage, DisputeStage::Closed);
+        assert_eq!(dispute.winner, Some(DisputeParty::Issuer));
+        assert_eq!(dispute.evidence.len(), 3);
+        let chargeback = processor.get_transaction(dispute.chargeback_transaction_id).unwrap();
+        assert_eq!(chargeback.transaction_type, TransactionType::Chargeback);
+        // The merchant's funds went back, forth and back again with the dispute
+        let merchant_id = processor.get_transaction(tx_id).unwrap().merchant_id;
+        assert!(processor.ledger().balance_of(LedgerAccountType::Merchant, Some(merchant_id), Currency::EUR).is_zero());
+        processor.ledger().verify().unwrap();
+
+        // Chargeback -80, representment +80, ruling -80
+        assert_eq!(settlement.pending_adjustments().len(), 3);
+        let batch_id = settlement.create_batch(issuer_id, acquirer_id, &[], &processor).unwrap();
+        let batch = settlement.get_batch(&batch_id).unwrap();
+        assert_eq!(batch.total_amount, Money::parse("-80.00", Currency::EUR).unwrap());
+        assert!(settlement.pending_adjustments().is_empty());
+    }
+
+    #[test]
+    fn test_missed_deadline_decides_against_silent_party() {
+        let (mut processor, tx_id, acquirer_id) = settled_purchase("40.00");
+        let issuer_id = Uuid::new_v4();
+        let mut disputes = DisputeService::new(DisputeDeadlines::default());
+        let mut settlement = SettlementService::new();
+        let now = Utc::now();
+
+        let dispute_id = disputes.open_chargeback(&mut processor, chargeback(tx_id, issuer_id, "15.00"), &mut settlement, now).unwrap();
+        assert!(disputes.expire_overdue(&mut processor, &mut settlement, now + Duration::days(44)).unwrap().is_empty());
+        assert_eq!(disputes.expire_overdue(&mut processor, &mut settlement, now + Duration::days(46)).unwrap(), vec![dispute_id]);
+        assert!(disputes.represent(&mut processor, dispute_id, "Too late".to_string(), &mut settlement, now + Duration::days(46)).is_err());
+
+        // The acquirer never responded, so the chargeback stands
+        assert_eq!(disputes.get_dispute(dispute_id).unwrap().winner, Some(DisputeParty::Issuer));
+        let batch_id = settlement.create_batch(issuer_id, acquirer_id, &[], &processor).unwrap();
+        assert_eq!(settlement.get_batch(&batch_id).unwrap().total_amount, Money::parse("-15.00", Currency::EUR).unwrap());
+    }
+}
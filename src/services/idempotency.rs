@@ -0,0 +1,112 @@
+// Idempotency keys: replaying the stored response for a retried request
+
+use chrono::{DateTime, Duration, Utc};
+use ring::digest;
+use std::collections::HashMap;
+
+use crate::core::error::EuropayError;
+
+/// A response kept for replay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+struct IdempotencyRecord {
+    fingerprint: String,
+    response: Option<StoredResponse>, // None while the first request is still being processed
+    created_at: DateTime<Utc>,
+}
+
+/// Responses to requests sent with an `Idempotency-Key`, kept for a configurable window.
+pub struct IdempotencyStore {
+    records: HashMap<String, IdempotencyRecord>,
+    window: Duration,
+}
+
+impl IdempotencyStore {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            records: HashMap::new(),
+            window,
+        }
+    }
+
+    /// SHA-256 over the method, path and body, so a key cannot be reused for a different request.
+    pub fn fingerprint(method: &str, path: &str, body: &[u8]) -> String {
+        let mut context = digest::Context::new(&digest::SHA256);
+        context.update(method.as_bytes());
+        context.update(b" ");
+        context.update(path.as_bytes());
+        context.update(b"\n");
+        context.update(body);
+        context.finish().as_ref().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Claims `key` for a request. Returns the stored response if the request was already
+    /// answered, or `None` if the caller should process it and then call `complete`.
+    pub fn begin(&mut self, key: &str, fingerprint: &str, now: DateTime<Utc>) -> Result<Option<StoredResponse>, EuropayError> {
+        self.purge_expired(now);
+        match self.records.get(key) {
+            Some(record) if record.fingerprint != fingerprint => Err(EuropayError::IdempotencyConflict(
+                "Idempotency key was already used with a different request".to_string(),
+            )),
+            Some(IdempotencyRecord { response: Some(response), .. }) => Ok(Some(response.clone())),
+            Some(_) => Err(EuropayError::IdempotencyConflict(
+                "A request with this idempotency key is still being processed".to_string(),
+            )),
+            None => {
+                self.records.insert(key.to_string(), IdempotencyRecord {
+                    fingerprint: fingerprint.to_string(),
+                    response: None,
+                    created_at: now,
+                });
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn complete(&mut self, key: &str, response: StoredResponse) {
+        if let Some(record) = self.records.get_mut(key) {
+            record.response = Some(response);
+        }
+    }
+
+    /// Releases a claimed key without storing a response, so the request can be retried.
+    pub fn abandon(&mut self, key: &str) {
+        if self.records.get(key).is_some_and(|record| record.response.is_none()) {
+            self.records.remove(key);
+        }
+    }
+
+    pub fn purge_expired(&mut self, now: DateTime<Utc>) {
+        let window = self.window;
+        self.records.retain(|_, record| now - record.created_at < window);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay_conflict_and_expiry() {
+        let mut store = IdempotencyStore::new(Duration::hours(24));
+        let now = Utc::now();
+        let fingerprint = IdempotencyStore::fingerprint("POST", "/authorize", b"{\"amount\":\"4.00\"}");
+        let response = StoredResponse { status: 200, content_type: Some("application/json".to_string()), body: b"{}".to_vec() };
+
+        assert_eq!(store.begin("key-1", &fingerprint, now).unwrap(), None);
+        assert!(matches!(store.begin("key-1", &fingerprint, now), Err(EuropayError::IdempotencyConflict(_))));
+        store.complete("key-1", response.clone());
+        assert_eq!(store.begin("key-1", &fingerprint, now).unwrap(), Some(response));
+
+        let other = IdempotencyStore::fingerprint("POST", "/authorize", b"{\"amount\":\"40.00\"}");
+        assert!(matches!(store.begin("key-1", &other, now), Err(EuropayError::IdempotencyConflict(_))));
+
+        // Once the window has passed the key can be used again
+        assert_eq!(store.begin("key-1", &other, now + Duration::hours(25)).unwrap(), None);
+    }
+}
@@ -0,0 +1,657 @@
+// ISO 8583:1987 field dictionary and binary codec
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use crate::services::messaging::{Iso8583Message, MAX_FIELD, SECONDARY_BITMAP_FIELD, TERTIARY_BITMAP_FIELD};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthType {
+    Fixed(usize),
+    LlVar(usize),  // up to 99, two-digit length prefix
+    LllVar(usize), // up to 999, three-digit length prefix
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Numeric,             // n
+    AlphaNumeric,        // an
+    AlphaNumericSpecial, // ans
+    SignedNumeric,       // x+n, 'C' or 'D' followed by digits
+    Track2,              // z
+    Binary,              // b, carried in the message as a hex string
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Ascii,
+    Bcd,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FieldSpec {
+    pub number: u8,
+    pub name: &'static str,
+    pub length: LengthType,
+    pub content: ContentType,
+}
+
+const fn fixed(number: u8, name: &'static str, content: ContentType, len: usize) -> FieldSpec {
+    FieldSpec { number, name, length: LengthType::Fixed(len), content }
+}
+
+const fn llvar(number: u8, name: &'static str, content: ContentType, max: usize) -> FieldSpec {
+    FieldSpec { number, name, length: LengthType::LlVar(max), content }
+}
+
+const fn lllvar(number: u8, name: &'static str, content: ContentType, max: usize) -> FieldSpec {
+    FieldSpec { number, name, length: LengthType::LllVar(max), content }
+}
+
+use ContentType::{AlphaNumeric as AN, AlphaNumericSpecial as ANS, Binary, Numeric as N, SignedNumeric, Track2};
+
+// Lengths are in characters, except binary fields which are in bytes
+const ISO8583_1987: &[FieldSpec] = &[
+    fixed(1, "Secondary bitmap", Binary, 8),
+    llvar(2, "Primary account number", N, 19),
+    fixed(3, "Processing code", N, 6),
+    fixed(4, "Amount, transaction", N, 12),
+    fixed(5, "Amount, settlement", N, 12),
+    fixed(6, "Amount, cardholder billing", N, 12),
+    fixed(7, "Transmission date and time", N, 10),
+    fixed(8, "Amount, cardholder billing fee", N, 8),
+    fixed(9, "Conversion rate, settlement", N, 8),
+    fixed(10, "Conversion rate, cardholder billing", N, 8),
+    fixed(11, "System trace audit number", N, 6),
+    fixed(12, "Time, local transaction", N, 6),
+    fixed(13, "Date, local transaction", N, 4),
+    fixed(14, "Date, expiration", N, 4),
+    fixed(15, "Date, settlement", N, 4),
+    fixed(16, "Date, conversion", N, 4),
+    fixed(17, "Date, capture", N, 4),
+    fixed(18, "Merchant type", N, 4),
+    fixed(19, "Acquiring institution country code", N, 3),
+    fixed(20, "PAN extended, country code", N, 3),
+    fixed(21, "Forwarding institution country code", N, 3),
+    fixed(22, "Point of service entry mode", N, 3),
+    fixed(23, "Card sequence number", N, 3),
+    fixed(24, "Network international identifier", N, 3),
+    fixed(25, "Point of service condition code", N, 2),
+    fixed(26, "Point of service capture code", N, 2),
+    fixed(27, "Authorizing identification response length", N, 1),
+    fixed(28, "Amount, transaction fee", SignedNumeric, 9),
+    fixed(29, "Amount, settlement fee", SignedNumeric, 9),
+    fixed(30, "Amount, transaction processing fee", SignedNumeric, 9),
+    fixed(31, "Amount, settlement processing fee", SignedNumeric, 9),
+    llvar(32, "Acquiring institution identification code", N, 11),
+    llvar(33, "Forwarding institution identification code", N, 11),
+    llvar(34, "Primary account number, extended", ANS, 28),
+    llvar(35, "Track 2 data", Track2, 37),
+    lllvar(36, "Track 3 data", N, 104),
+    fixed(37, "Retrieval reference number", AN, 12),
+    fixed(38, "Authorization identification response", AN, 6),
+    fixed(39, "Response code", AN, 2),
+    fixed(40, "Service restriction code", AN, 3),
+    fixed(41, "Card acceptor terminal identification", ANS, 8),
+    fixed(42, "Card acceptor identification code", ANS, 15),
+    fixed(43, "Card acceptor name/location", ANS, 40),
+    llvar(44, "Additional response data", AN, 25),
+    llvar(45, "Track 1 data", ANS, 76),
+    lllvar(46, "Additional data - ISO", ANS, 999),
+    lllvar(47, "Additional data - national", ANS, 999),
+    lllvar(48, "Additional data - private", ANS, 999),
+    fixed(49, "Currency code, transaction", N, 3),
+    fixed(50, "Currency code, settlement", N, 3),
+    fixed(51, "Currency code, cardholder billing", N, 3),
+    fixed(52, "Personal identification number data", Binary, 8),
+    fixed(53, "Security related control information", N, 16),
+    lllvar(54, "Additional amounts", AN, 120),
+    lllvar(55, "ICC data", Binary, 255),
+    lllvar(56, "Reserved ISO", ANS, 999),
+    lllvar(57, "Reserved national", ANS, 999),
+    lllvar(58, "Reserved national", ANS, 999),
+    lllvar(59, "Reserved national", ANS, 999),
+    lllvar(60, "Reserved national", ANS, 999),
+    lllvar(61, "Reserved private", ANS, 999),
+    lllvar(62, "Reserved private", ANS, 999),
+    lllvar(63, "Reserved private", ANS, 999),
+    fixed(64, "Message authentication code", Binary, 8),
+    fixed(65, "Tertiary bitmap", Binary, 8),
+    fixed(66, "Settlement code", N, 1),
+    fixed(67, "Extended payment code", N, 2),
+    fixed(68, "Receiving institution country code", N, 3),
+    fixed(69, "Settlement institution country code", N, 3),
+    fixed(70, "Network management information code", N, 3),
+    fixed(71, "Message number", N, 4),
+    fixed(72, "Message number, last", N, 4),
+    fixed(73, "Date, action", N, 6),
+    fixed(74, "Credits, number", N, 10),
+    fixed(75, "Credits, reversal number", N, 10),
+    fixed(76, "Debits, number", N, 10),
+    fixed(77, "Debits, reversal number", N, 10),
+    fixed(78, "Transfer, number", N, 10),
+    fixed(79, "Transfer, reversal number", N, 10),
+    fixed(80, "Inquiries, number", N, 10),
+    fixed(81, "Authorizations, number", N, 10),
+    fixed(82, "Credits, processing fee amount", N, 12),
+    fixed(83, "Credits, transaction fee amount", N, 12),
+    fixed(84, "Debits, processing fee amount", N, 12),
+    fixed(85, "Debits, transaction fee amount", N, 12),
+    fixed(86, "Credits, amount", N, 16),
+    fixed(87, "Credits, reversal amount", N, 16),
+    fixed(88, "Debits, amount", N, 16),
+    fixed(89, "Debits, reversal amount", N, 16),
+    fixed(90, "Original data elements", N, 42),
+    fixed(91, "File update code", AN, 1),
+    fixed(92, "File security code", AN, 2),
+    fixed(93, "Response indicator", AN, 5),
+    fixed(94, "Service indicator", AN, 7),
+    fixed(95, "Replacement amounts", AN, 42),
+    fixed(96, "Message security code", Binary, 8),
+    fixed(97, "Amount, net settlement", SignedNumeric, 17),
+    fixed(98, "Payee", ANS, 25),
+    llvar(99, "Settlement institution identification code", N, 11),
+    llvar(100, "Receiving institution identification code", N, 11),
+    llvar(101, "File name", ANS, 17),
+    llvar(102, "Account identification 1", ANS, 28),
+    llvar(103, "Account identification 2", ANS, 28),
+    lllvar(104, "Transaction description", ANS, 100),
+    lllvar(105, "Reserved ISO", ANS, 999),
+    lllvar(106, "Reserved ISO", ANS, 999),
+    lllvar(107, "Reserved ISO", ANS, 999),
+    lllvar(108, "Reserved ISO", ANS, 999),
+    lllvar(109, "Reserved ISO", ANS, 999),
+    lllvar(110, "Reserved ISO", ANS, 999),
+    lllvar(111, "Reserved ISO", ANS, 999),
+    lllvar(112, "Reserved national", ANS, 999),
+    lllvar(113, "Reserved national", ANS, 999),
+    lllvar(114, "Reserved national", ANS, 999),
+    lllvar(115, "Reserved national", ANS, 999),
+    lllvar(116, "Reserved national", ANS, 999),
+    lllvar(117, "Reserved national", ANS, 999),
+    lllvar(118, "Reserved national", ANS, 999),
+    lllvar(119, "Reserved national", ANS, 999),
+    lllvar(120, "Reserved private", ANS, 999),
+    lllvar(121, "Reserved private", ANS, 999),
+    lllvar(122, "Reserved private", ANS, 999),
+    lllvar(123, "Reserved private", ANS, 999),
+    lllvar(124, "Reserved private", ANS, 999),
+    lllvar(125, "Reserved private", ANS, 999),
+    lllvar(126, "Reserved private", ANS, 999),
+    lllvar(127, "Reserved private", ANS, 999),
+    fixed(128, "Message authentication code", Binary, 8),
+];
+
+/// Field definitions keyed by field number.
+#[derive(Debug, Clone)]
+pub struct FieldDictionary {
+    fields: HashMap<u8, FieldSpec>,
+}
+
+// Fields 129–192 behind the tertiary bitmap are not defined by the standard; default them to private use
+static ISO8583_1987_DICTIONARY: LazyLock<FieldDictionary> = LazyLock::new(|| {
+    (129..=MAX_FIELD).fold(FieldDictionary::from_specs(ISO8583_1987), |dictionary, n| {
+        dictionary.with_field(lllvar(n, "Reserved private", ANS, 999))
+    })
+});
+
+impl FieldDictionary {
+    pub fn from_specs(specs: &[FieldSpec]) -> Self {
+        Self {
+            fields: specs.iter().map(|spec| (spec.number, *spec)).collect(),
+        }
+    }
+
+    pub fn iso8583_1987() -> &'static FieldDictionary {
+        &ISO8583_1987_DICTIONARY
+    }
+
+    /// Overrides or adds a field definition, for hosts that deviate from the standard.
+    pub fn with_field(mut self, spec: FieldSpec) -> Self {
+        self.fields.insert(spec.number, spec);
+        self
+    }
+
+    pub fn get(&self, number: u8) -> Option<&FieldSpec> {
+        self.fields.get(&number)
+    }
+}
+
+/// How numeric data and length prefixes are put on the wire. Alphanumeric data is always ASCII.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecConfig {
+    pub mti: Encoding,
+    pub numeric: Encoding,
+    pub length_prefix: Encoding,
+}
+
+impl CodecConfig {
+    pub fn ascii() -> Self {
+        Self { mti: Encoding::Ascii, numeric: Encoding::Ascii, length_prefix: Encoding::Ascii }
+    }
+
+    pub fn bcd() -> Self {
+        Self { mti: Encoding::Bcd, numeric: Encoding::Bcd, length_prefix: Encoding::Bcd }
+    }
+}
+
+pub struct Iso8583Codec {
+    dictionary: FieldDictionary,
+    config: CodecConfig,
+}
+
+impl Default for Iso8583Codec {
+    fn default() -> Self {
+        Self::new(FieldDictionary::iso8583_1987().clone(), CodecConfig::ascii())
+    }
+}
+
+impl Iso8583Codec {
+    pub fn new(dictionary: FieldDictionary, config: CodecConfig) -> Self {
+        Self { dictionary, config }
+    }
+
+    pub fn encode(&self, message: &Iso8583Message) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        self.encode_mti(&message.mti, &mut data)?;
+
+        let mut numbers: Vec<u8> = message.fields.keys().copied().collect();
+        let bitmap = message.bitmap();
+        if bitmap.len() == 24 {
+            numbers.push(TERTIARY_BITMAP_FIELD);
+        }
+        numbers.sort_unstable();
+
+        // The secondary bitmap directly follows the primary; the tertiary travels in field 65's slot
+        data.extend_from_slice(&bitmap[..bitmap.len().min(16)]);
+        for n in numbers {
+            if n == TERTIARY_BITMAP_FIELD {
+                data.extend_from_slice(&bitmap[16..24]);
+                continue;
+            }
+            let spec = self.dictionary.get(n).ok_or_else(|| format!("Field {} not in dictionary", n))?;
+            self.encode_field(spec, &message.fields[&n], &mut data)?;
+        }
+        Ok(data)
+    }
+
+    pub fn decode(&self, data: &[u8]) -> Result<Iso8583Message, String> {
+        let mut reader = Reader { data, pos: 0 };
+        let mut message = Iso8583Message::new(self.decode_mti(&mut reader)?);
+        self.decode_fields(&mut reader, &mut message)?;
+        if reader.pos != data.len() {
+            return Err(format!("{} trailing bytes after last field", data.len() - reader.pos));
+        }
+        Ok(message)
+    }
+
+    /// What can be read of a message that does not decode: its MTI and the fields ahead of
+    /// the first malformed one, enough to answer it with a format error. `None` if not even
+    /// the MTI is readable.
+    pub fn decode_partial(&self, data: &[u8]) -> Option<Iso8583Message> {
+        let mut reader = Reader { data, pos: 0 };
+        let mut message = Iso8583Message::new(self.decode_mti(&mut reader).ok()?);
+        let _ = self.decode_fields(&mut reader, &mut message);
+        Some(message)
+    }
+
+    fn decode_fields(&self, reader: &mut Reader, message: &mut Iso8583Message) -> Result<(), String> {
+        let mut bitmap = reader.take(8)?.to_vec();
+        if is_set(&bitmap, SECONDARY_BITMAP_FIELD) {
+            bitmap.extend_from_slice(reader.take(8)?);
+        }
+
+        for n in 2..=MAX_FIELD {
+            if !is_set(&bitmap, n) {
+                continue;
+            }
+            if n == TERTIARY_BITMAP_FIELD {
+                bitmap.extend_from_slice(reader.take(8)?);
+                continue;
+            }
+            let spec = self.dictionary.get(n).ok_or_else(|| format!("Field {} not in dictionary", n))?;
+            let value = self.decode_field(spec, reader)?;
+            message.set_field(n, value)?;
+        }
+        Ok(())
+    }
+
+    fn encode_mti(&self, mti: &str, out: &mut Vec<u8>) -> Result<(), String> {
+        if mti.len() != 4 || !mti.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid MTI: {}", mti));
+        }
+        match self.config.mti {
+            Encoding::Ascii => out.extend_from_slice(mti.as_bytes()),
+            Encoding::Bcd => out.extend(pack_bcd(mti, false)?),
+        }
+        Ok(())
+    }
+
+    fn decode_mti(&self, reader: &mut Reader) -> Result<String, String> {
+        let mti = match self.config.mti {
+            Encoding::Ascii => ascii(reader.take(4)?)?,
+            Encoding::Bcd => unpack_bcd(reader.take(2)?, 4, false),
+        };
+        if !mti.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid MTI: {}", mti));
+        }
+        Ok(mti)
+    }
+
+    fn encode_field(&self, spec: &FieldSpec, value: &str, out: &mut Vec<u8>) -> Result<(), String> {
+        let value = match spec.length {
+            LengthType::Fixed(len) => pad_fixed(spec, value, len)?,
+            LengthType::LlVar(_) | LengthType::LllVar(_) => value.to_string(),
+        };
+        validate_content(spec, &value)?;
+
+        // Binary values travel as hex in the message; their length counts bytes
+        let bytes = match spec.content {
+            ContentType::Binary => hex_decode(&value).map_err(|e| format!("Field {}: {}", spec.number, e))?,
+            _ if self.packs_bcd(spec) => pack_bcd(&value, spec.content == ContentType::Track2)?,
+            _ => value.as_bytes().to_vec(),
+        };
+        let length = match spec.content {
+            ContentType::Binary => bytes.len(),
+            _ => value.chars().count(),
+        };
+
+        match spec.length {
+            LengthType::Fixed(len) if length != len => {
+                return Err(format!("Field {} must be {} long, got {}", spec.number, len, length));
+            }
+            LengthType::Fixed(_) => {}
+            LengthType::LlVar(max) | LengthType::LllVar(max) => {
+                if length > max {
+                    return Err(format!("Field {} exceeds maximum length {}", spec.number, max));
+                }
+                let digits = if matches!(spec.length, LengthType::LlVar(_)) { 2 } else { 3 };
+                self.encode_length(length, digits, out)?;
+            }
+        }
+        out.extend(bytes);
+        Ok(())
+    }
+
+    fn decode_field(&self, spec: &FieldSpec, reader: &mut Reader) -> Result<String, String> {
+        let length = match spec.length {
+            LengthType::Fixed(len) => len,
+            LengthType::LlVar(max) | LengthType::LllVar(max) => {
+                let digits = if matches!(spec.length, LengthType::LlVar(_)) { 2 } else { 3 };
+                let len = self.decode_length(digits, reader)?;
+                if len > max {
+                    return Err(format!("Field {} exceeds maximum length {}", spec.number, max));
+                }
+                len
+            }
+        };
+
+        let value = match spec.content {
+            ContentType::Binary => hex_encode(reader.take(length)?),
+            _ if self.packs_bcd(spec) => {
+                let track2 = spec.content == ContentType::Track2;
+                unpack_bcd(reader.take(length.div_ceil(2))?, length, track2)
+            }
+            _ => ascii(reader.take(length)?)?,
+        };
+        validate_content(spec, &value)?;
+        Ok(value)
+    }
+
+    fn packs_bcd(&self, spec: &FieldSpec) -> bool {
+        self.config.numeric == Encoding::Bcd && matches!(spec.content, ContentType::Numeric | ContentType::Track2)
+    }
+
+    fn encode_length(&self, length: usize, digits: usize, out: &mut Vec<u8>) -> Result<(), String> {
+        let text = format!("{:0width$}", length, width = digits);
+        match self.config.length_prefix {
+            Encoding::Ascii => out.extend_from_slice(text.as_bytes()),
+            Encoding::Bcd => out.extend(pack_bcd(&text, false)?),
+        }
+        Ok(())
+    }
+
+    fn decode_length(&self, digits: usize, reader: &mut Reader) -> Result<usize, String> {
+        let text = match self.config.length_prefix {
+            Encoding::Ascii => ascii(reader.take(digits)?)?,
+            Encoding::Bcd => unpack_bcd(reader.take(digits.div_ceil(2))?, digits, false),
+        };
+        text.parse().map_err(|_| format!("Invalid length prefix: {}", text))
+    }
+}
+
+fn is_set(bitmap: &[u8], n: u8) -> bool {
+    let index = (n - 1) as usize;
+    bitmap.get(index / 8).is_some_and(|byte| byte & (0x80 >> (index % 8)) != 0)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.pos + n > self.data.len() {
+            return Err("Message truncated".to_string());
+        }
+        let slice = &self.data[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(slice)
+    }
+}
+
+// Numeric fixed fields are zero-filled on the left, others space-filled on the right
+fn pad_fixed(spec: &FieldSpec, value: &str, len: usize) -> Result<String, String> {
+    let count = match spec.content {
+        ContentType::Binary => value.len() / 2,
+        _ => value.chars().count(),
+    };
+    if count >= len {
+        return Ok(value.to_string());
+    }
+    Ok(match spec.content {
+        ContentType::Numeric => format!("{:0>width$}", value, width = len),
+        ContentType::Binary => format!("{:0>width$}", value, width = len * 2),
+        _ => format!("{:<width$}", value, width = len),
+    })
+}
+
+fn validate_content(spec: &FieldSpec, value: &str) -> Result<(), String> {
+    let valid = match spec.content {
+        ContentType::Numeric => value.bytes().all(|b| b.is_ascii_digit()),
+        ContentType::AlphaNumeric => value.bytes().all(|b| b.is_ascii_alphanumeric() || b == b' '),
+        ContentType::AlphaNumericSpecial => value.chars().all(|c| !c.is_control()),
+        ContentType::SignedNumeric => {
+            let mut bytes = value.bytes();
+            matches!(bytes.next(), Some(b'C') | Some(b'D')) && bytes.all(|b| b.is_ascii_digit())
+        }
+        ContentType::Track2 => value.bytes().all(|b| b.is_ascii_digit() || b == b'=' || b == b'D'),
+        ContentType::Binary => value.bytes().all(|b| b.is_ascii_hexdigit()),
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Field {} ({}) has invalid content", spec.number, spec.name))
+    }
+}
+
+// Right-justified packing with a leading zero nibble; track 2 is left-justified with an F pad
+fn pack_bcd(digits: &str, track2: bool) -> Result<Vec<u8>, String> {
+    let mut nibbles: Vec<u8> = digits.bytes()
+        .map(|b| match b {
+            b'0'..=b'9' => Ok(b - b'0'),
+            b'=' | b'D' if track2 => Ok(0x0D),
+            _ => Err(format!("Cannot BCD-encode {:?}", digits)),
+        })
+        .collect::<Result<_, _>>()?;
+    if !nibbles.len().is_multiple_of(2) {
+        if track2 {
+            nibbles.push(0x0F);
+        } else {
+            nibbles.insert(0, 0);
+        }
+    }
+    Ok(nibbles.chunks(2).map(|pair| (pair[0] << 4) | pair[1]).collect())
+}
+
+fn unpack_bcd(bytes: &[u8], digits: usize, track2: bool) -> String {
+    let mut text: String = bytes.iter()
+        .flat_map(|b| [b >> 4, b & 0x0F])
+        .map(|nibble| match nibble {
+            0x0D if track2 => '=',
+            n => char::from_digit(n as u32, 16).unwrap_or('?').to_ascii_uppercase(),
+        })
+        .collect();
+    if text.len() > digits {
+        if track2 {
+            text.truncate(digits);
+        } else {
+            text.drain(..text.len() - digits);
+        }
+    }
+    text
+}
+
+fn ascii(bytes: &[u8]) -> Result<String, String> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| "Invalid field data".to_string())
+}
+
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+pub fn hex_decode(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("Odd-length hex string".to_string());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("Invalid hex: {}", hex)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth_request() -> Iso8583Message {
+        let mut message = Iso8583Message::new("0100".to_string());
+        message.set_field(2, "4000000000000002".to_string()).unwrap();
+        message.set_field(3, "000000".to_string()).unwrap();
+        message.set_field(4, "1250".to_string()).unwrap();
+        message.set_field(11, "123456".to_string()).unwrap();
+        message.set_field(35, "4000000000000002=2912101".to_string()).unwrap();
+        message.set_field(41, "TERM01".to_string()).unwrap();
+        message.set_field(49, "978".to_string()).unwrap();
+        message.set_field(52, "0123456789ABCDEF".to_string()).unwrap();
+        message
+    }
+
+    #[test]
+    fn test_ascii_wire_format() {
+        let mut message = Iso8583Message::new("0100".to_string());
+        message.set_field(2, "4000000000000002".to_string()).unwrap();
+        message.set_field(4, "1250".to_string()).unwrap();
+
+        let data = Iso8583Codec::default().encode(&message).unwrap();
+        let mut expected = b"0100".to_vec();
+        expected.extend_from_slice(&[0x50, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend_from_slice(b"164000000000000002000000001250");
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_bcd_wire_format() {
+        let mut message = Iso8583Message::new("0100".to_string());
+        message.set_field(2, "400000000000002".to_string()).unwrap();
+        message.set_field(4, "1250".to_string()).unwrap();
+
+        let codec = Iso8583Codec::new(FieldDictionary::iso8583_1987().clone(), CodecConfig::bcd());
+        let data = codec.encode(&message).unwrap();
+        let mut expected = vec![0x01, 0x00, 0x50, 0, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(&[0x15, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02]);
+        expected.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x12, 0x50]);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn test_round_trip_both_encodings() {
+        for config in [CodecConfig::ascii(), CodecConfig::bcd()] {
+            let codec = Iso8583Codec::new(FieldDictionary::iso8583_1987().clone(), config);
+            let message = auth_request();
+            let decoded = codec.decode(&codec.encode(&message).unwrap()).unwrap();
+            assert_eq!(decoded.mti, "0100");
+            assert_eq!(decoded.get_field(4).map(String::as_str), Some("000000001250"));
+            assert_eq!(decoded.get_field(35), message.get_field(35));
+            assert_eq!(decoded.get_field(41).map(String::as_str), Some("TERM01  "));
+            assert_eq!(decoded.get_field(52), message.get_field(52));
+        }
+    }
+
+    #[test]
+    fn test_rejects_invalid_fields() {
+        let codec = Iso8583Codec::default();
+        let mut message = Iso8583Message::new("0100".to_string());
+        message.set_field(3, "12345A".to_string()).unwrap();
+        assert!(codec.encode(&message).is_err());
+
+        let mut message = Iso8583Message::new("0100".to_string());
+        message.set_field(2, "4".repeat(20)).unwrap();
+        assert!(codec.encode(&message).is_err());
+
+        let mut truncated = b"0100".to_vec();
+        truncated.extend_from_slice(&[0x40, 0, 0, 0, 0, 0, 0, 0]);
+        truncated.extend_from_slice(b"164000");
+        assert!(codec.decode(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_secondary_bitmap() {
+        let mut message = Iso8583Message::new("0800".to_string());
+        message.set_field(11, "000001".to_string()).unwrap();
+        message.set_field(70, "301".to_string()).unwrap();
+
+        let bitmap = message.bitmap();
+        assert_eq!(bitmap.len(), 16);
+        assert_eq!(bitmap[0], 0x80);
+        assert_eq!(bitmap[1], 0x20);
+        assert_eq!(bitmap[8], 0x04);
+
+        let data = Iso8583Codec::default().encode(&message).unwrap();
+        assert_eq!(&data[4..20], bitmap.as_slice());
+        assert_eq!(&data[20..], b"000001301");
+        let decoded = Iso8583Codec::default().decode(&data).unwrap();
+        assert_eq!(decoded.get_field(70).map(String::as_str), Some("301"));
+        assert_eq!(decoded.bitmap(), bitmap);
+    }
+
+    #[test]
+    fn test_tertiary_bitmap() {
+        let mut message = Iso8583Message::new("0200".to_string());
+        message.set_field(3, "000000".to_string()).unwrap();
+        message.set_field(100, "12345".to_string()).unwrap();
+        message.set_field(150, "private".to_string()).unwrap();
+
+        let bitmap = message.bitmap();
+        assert_eq!(bitmap.len(), 24);
+        assert_eq!(bitmap[0] & 0x80, 0x80);
+        assert_eq!(bitmap[8] & 0x80, 0x80);
+
+        for config in [CodecConfig::ascii(), CodecConfig::bcd()] {
+            let codec = Iso8583Codec::new(FieldDictionary::iso8583_1987().clone(), config);
+            let decoded = codec.decode(&codec.encode(&message).unwrap()).unwrap();
+            assert_eq!(decoded.fields, message.fields);
+        }
+    }
+
+    #[test]
+    fn test_bitmap_fields_are_not_settable() {
+        let mut message = Iso8583Message::new("0100".to_string());
+        assert!(message.set_field(0, String::new()).is_err());
+        assert!(message.set_field(1, String::new()).is_err());
+        assert!(message.set_field(65, String::new()).is_err());
+        assert!(message.set_field(193, String::new()).is_err());
+        assert!(message.set_field(192, "x".to_string()).is_ok());
+    }
+}
@@ -0,0 +1,601 @@
+// ISO 8583 TCP listener for acquirer terminals and hosts
+
+use chrono::{DateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
+
+use crate::core::error::EuropayError;
+use crate::core::network::{MessageClass, TransactionRequest, TransactionResponse};
+use crate::models::transactions::PaymentProcessor;
+use crate::services::iso8583::{hex_encode, Iso8583Codec};
+use crate::services::messaging::{
+    replacement_amount, request_from_iso, response_mti_for, response_to_iso,
+    Iso8583Message, OriginalDataElements, MTI_AUTH_ADVICE, MTI_AUTH_ADVICE_REPEAT, MTI_AUTH_REQUEST,
+    MTI_FINANCIAL_REQUEST, MTI_NETWORK_REQUEST, MTI_REVERSAL_ADVICE, MTI_REVERSAL_ADVICE_REPEAT,
+    MTI_REVERSAL_REPEAT, MTI_REVERSAL_REQUEST, NETWORK_ECHO_TEST, NETWORK_KEY_CHANGE,
+    NETWORK_SIGN_OFF, NETWORK_SIGN_ON, RESPONSE_APPROVED, RESPONSE_DUPLICATE_TRANSMISSION, RESPONSE_FORMAT_ERROR,
+    RESPONSE_FUNCTION_NOT_SUPPORTED, RESPONSE_ISSUER_UNAVAILABLE, RESPONSE_SYSTEM_MALFUNCTION,
+};
+
+// Fields copied from a request into its response so the peer can match them
+const ECHOED_FIELDS: [u8; 6] = [3, 4, 11, 37, 41, 49];
+
+/// Accepts frames of a 2-byte big-endian length followed by an ISO 8583 message.
+pub struct Iso8583Server {
+    processor: Arc<Mutex<PaymentProcessor>>,
+    codec: Arc<Iso8583Codec>,
+}
+
+impl Iso8583Server {
+    pub fn new(processor: Arc<Mutex<PaymentProcessor>>, codec: Iso8583Codec) -> Self {
+        Self {
+            processor,
+            codec: Arc::new(codec),
+        }
+    }
+
+    pub async fn run(self: Arc<Self>, listener: TcpListener) {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    info!("ISO 8583 connection from {}", peer);
+                    let server = self.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = server.handle_connection(stream).await {
+                            warn!("ISO 8583 connection {} closed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => warn!("ISO 8583 accept failed: {}", e),
+            }
+        }
+    }
+
+    // Network management messages are handled in arrival order so a sign-on takes effect
+    // before the requests behind it. Other requests are processed concurrently; responses
+    // are written as they complete and the peer matches them to requests by STAN (field 11)
+    async fn handle_connection(self: Arc<Self>, stream: TcpStream) -> Result<(), String> {
+        let (mut reader, mut writer) = stream.into_split();
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(64);
+
+        let writer_task = tokio::spawn(async move {
+            while let Some(frame) = rx.recv().await {
+                if writer.write_all(&frame).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let session = Arc::new(Mutex::new(LinkSession::new()));
+        let in_flight = Arc::new(Mutex::new(HashSet::<String>::new()));
+        let result = loop {
+            let frame = match read_frame(&mut reader).await {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            };
+            let request = match self.codec.decode(&frame) {
+                Ok(message) => message,
+                Err(e) => {
+                    // Answered with a format error if the peer can match the reply by MTI and
+                    // STAN; otherwise it would only time out and reverse, so drop the link
+                    warn!("Undecodable ISO 8583 frame: {}", e);
+                    match self.format_error(&frame) {
+                        Some(response) => {
+                            let _ = tx.send(response).await;
+                            continue;
+                        }
+                        None => break Err(format!("Undecodable ISO 8583 frame: {}", e)),
+                    }
+                }
+            };
+
+            if request.mti == MTI_NETWORK_REQUEST {
+                if let Some(response) = self.handle_request(&request, &session, &in_flight).await {
+                    let _ = tx.send(response).await;
+                }
+                continue;
+            }
+
+            let server = self.clone();
+            let tx = tx.clone();
+            let session = session.clone();
+            let in_flight = in_flight.clone();
+            tokio::spawn(async move {
+                if let Some(response) = server.handle_request(&request, &session, &in_flight).await {
+                    let _ = tx.send(response).await;
+                }
+            });
+        };
+
+        drop(tx);
+        let _ = writer_task.await;
+        result
+    }
+
+    fn format_error(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let partial = self.codec.decode_partial(frame).filter(|message| message.get_field(11).is_some())?;
+        let response = reply(&partial, RESPONSE_FORMAT_ERROR)?;
+        self.codec.encode(&response).ok().map(frame_bytes)
+    }
+
+    async fn handle_request(
+        &self,
+        request: &Iso8583Message,
+        session: &Mutex<LinkSession>,
+        in_flight: &Mutex<HashSet<String>>,
+    ) -> Option<Vec<u8>> {
+        // A STAN may only be in flight once per connection
+        let stan = request.get_field(11).cloned();
+        if let Some(stan) = &stan
+            && !in_flight.lock().await.insert(stan.clone())
+        {
+            let response = reply(request, RESPONSE_DUPLICATE_TRANSMISSION)?;
+            return self.codec.encode(&response).ok().map(frame_bytes);
+        }
+
+        let response = self.handle_message(request, session).await;
+
+        if let Some(stan) = &stan {
+            in_flight.lock().await.remove(stan);
+        }
+        let response = response?;
+        match self.codec.encode(&response) {
+            Ok(data) => Some(frame_bytes(data)),
+            Err(e) => {
+                warn!("Failed to encode {} response: {}", response.mti, e);
+                None
+            }
+        }
+    }
+
+    /// Processes one request on a link and builds its response; `None` for messages that get no reply.
+    pub async fn handle_message(&self, request: &Iso8583Message, session: &Mutex<LinkSession>) -> Option<Iso8583Message> {
+        match request.mti.as_str() {
+            MTI_NETWORK_REQUEST => handle_network_management(request, &mut *session.lock().await),
+            MTI_AUTH_REQUEST | MTI_FINANCIAL_REQUEST | MTI_AUTH_ADVICE | MTI_AUTH_ADVICE_REPEAT
+            | MTI_REVERSAL_REQUEST | MTI_REVERSAL_REPEAT | MTI_REVERSAL_ADVICE | MTI_REVERSAL_ADVICE_REPEAT => {
+                if !session.lock().await.is_signed_on() {
+                    return reply(request, RESPONSE_ISSUER_UNAVAILABLE);
+                }
+                match request.mti.as_str() {
+                    MTI_AUTH_REQUEST | MTI_FINANCIAL_REQUEST => Some(self.handle_transaction(request).await),
+                    MTI_AUTH_ADVICE | MTI_AUTH_ADVICE_REPEAT => reply(request, self.handle_advice(request).await),
+                    _ => reply(request, self.handle_reversal(request).await),
+                }
+            }
+            _ => reply(request, RESPONSE_FUNCTION_NOT_SUPPORTED),
+        }
+    }
+
+    // 0120: record the stand-in authorization once, however often the advice is repeated
+    async fn handle_advice(&self, message: &Iso8583Message) -> &'static str {
+        let mut processor = self.processor.lock().await;
+        let result = OriginalDataElements::of(message).map_err(EuropayError::InvalidRequest).and_then(|reference| {
+            let request = request_from_iso(message, &*processor)?;
+            let tx_id = processor.record_authorization_advice(&reference.reference(), request.card_id, request.merchant_id, request.amount)?;
+            processor.record_network_reference(reference.reference(), tx_id)
+        });
+        match result {
+            Ok(()) => RESPONSE_APPROVED,
+            Err(e) => e.response_code(),
+        }
+    }
+
+    // 0400/0420: locate the original by field 90 and reverse it fully, or down to the
+    // actual amount in field 95
+    async fn handle_reversal(&self, message: &Iso8583Message) -> &'static str {
+        let mut processor = self.processor.lock().await;
+        match reverse_original(&mut processor, message) {
+            Ok(()) => RESPONSE_APPROVED,
+            Err(e) => e.response_code(),
+        }
+    }
+
+    async fn handle_transaction(&self, message: &Iso8583Message) -> Iso8583Message {
+        let mut processor = self.processor.lock().await;
+        let request = match request_from_iso(message, &*processor) {
+            Ok(request) => request,
+            Err(e) => {
+                return reply(message, e.response_code()).unwrap_or_else(|| Iso8583Message::new(message.mti.clone()));
+            }
+        };
+
+        let result = processor
+            .authorize_transaction(request.card_id, request.merchant_id, request.amount)
+            .and_then(|tx_id| match request.message_class {
+                // Single-message transactions are captured as soon as they are approved
+                MessageClass::Financial => processor.capture_transaction(tx_id).map(|_| tx_id),
+                MessageClass::Authorization => Ok(tx_id),
+            });
+        if let (Ok(tx_id), Ok(reference)) = (&result, OriginalDataElements::of(message))
+            && let Err(e) = processor.record_network_reference(reference.reference(), *tx_id)
+        {
+            // The approval stands; only a later reversal by field 90 would not find it
+            warn!("Failed to record network reference for {}: {}", tx_id, e);
+        }
+        let result = result.map(|tx_id| {
+            let approval_code = processor.get_transaction(tx_id).and_then(|t| t.approval_code.clone());
+            (tx_id, approval_code)
+        });
+        drop(processor);
+
+        let response = transaction_response(&request, result);
+        let mut iso = response_to_iso(&response).unwrap_or_else(|_| Iso8583Message::new(message.mti.clone()));
+        echo_fields(message, &mut iso);
+        iso
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    SignedOff,
+    SignedOn,
+}
+
+/// Network management state of one acquirer link (a single TCP connection).
+#[derive(Debug)]
+pub struct LinkSession {
+    pub state: LinkState,
+    pub signed_on_at: Option<DateTime<Utc>>,
+    pub last_echo_at: Option<DateTime<Utc>>,
+    pub key_version: u32,
+    working_key: Option<[u8; 16]>,
+}
+
+impl LinkSession {
+    pub fn new() -> Self {
+        Self {
+            state: LinkState::SignedOff,
+            signed_on_at: None,
+            last_echo_at: None,
+            key_version: 0,
+            working_key: None,
+        }
+    }
+
+    pub fn is_signed_on(&self) -> bool {
+        self.state == LinkState::SignedOn
+    }
+
+    pub fn working_key(&self) -> Option<&[u8; 16]> {
+        self.working_key.as_ref()
+    }
+
+    // Replaces the link's working key and returns the new one
+    fn rotate_key(&mut self) -> Result<[u8; 16], String> {
+        let mut key = [0u8; 16];
+        SystemRandom::new().fill(&mut key).map_err(|_| "Key generation failed".to_string())?;
+        self.working_key = Some(key);
+        self.key_version += 1;
+        Ok(key)
+    }
+}
+
+impl Default for LinkSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Applies an 0800 network management request (field 70) to the link and builds the 0810.
+pub fn handle_network_management(request: &Iso8583Message, session: &mut LinkSession) -> Option<Iso8583Message> {
+    let Some(code) = request.get_field(70) else {
+        return reply(request, RESPONSE_FORMAT_ERROR);
+    };
+
+    let response_code = match code.as_str() {
+        NETWORK_SIGN_ON => {
+            session.state = LinkState::SignedOn;
+            session.signed_on_at = Some(Utc::now());
+            RESPONSE_APPROVED
+        }
+        NETWORK_SIGN_OFF => {
+            session.state = LinkState::SignedOff;
+            RESPONSE_APPROVED
+        }
+        NETWORK_ECHO_TEST => {
+            session.last_echo_at = Some(Utc::now());
+            RESPONSE_APPROVED
+        }
+        NETWORK_KEY_CHANGE if !session.is_signed_on() => RESPONSE_ISSUER_UNAVAILABLE,
+        NETWORK_KEY_CHANGE => {
+            // In real system, the new key would be sent wrapped under the zone master key
+            let key = match session.rotate_key() {
+                Ok(key) => key,
+                Err(e) => {
+                    warn!("{}", e);
+                    return reply(request, RESPONSE_SYSTEM_MALFUNCTION);
+                }
+            };
+            let mut response = reply(request, RESPONSE_APPROVED)?;
+            response.set_field(48, hex_encode(&key)).ok()?;
+            response.set_field(53, format!("{:016}", session.key_version)).ok()?;
+            response.set_field(70, code.clone()).ok()?;
+            return Some(response);
+        }
+        _ => RESPONSE_FUNCTION_NOT_SUPPORTED,
+    };
+
+    let mut response = reply(request, response_code)?;
+    response.set_field(70, code.clone()).ok()?;
+    Some(response)
+}
+
+fn reverse_original(processor: &mut PaymentProcessor, message: &Iso8583Message) -> Result<(), EuropayError> {
+    let field = message.get_field(90).ok_or_else(|| EuropayError::InvalidRequest("Missing field 90".to_string()))?;
+    let original = OriginalDataElements::parse(field).map_err(EuropayError::InvalidRequest)?;
+    let tx_id = processor.transaction_for_network_reference(&original.reference()).ok_or(EuropayError::TransactionNotFound)?;
+    let currency = processor.get_transaction(tx_id).ok_or(EuropayError::TransactionNotFound)?.amount.currency();
+    match replacement_amount(message, currency).map_err(EuropayError::InvalidRequest)? {
+        Some(actual) if !actual.is_zero() => processor.partially_reverse_transaction(tx_id, actual),
+        _ => processor.reverse_transaction(tx_id),
+    }
+}
+
+fn transaction_response(request: &TransactionRequest, result: Result<(uuid::Uuid, Option<String>), EuropayError>) -> TransactionResponse {
+    let (transaction_id, response_code, approval_code) = match result {
+        Ok((tx_id, approval_code)) => (tx_id, RESPONSE_APPROVED, approval_code),
+        Err(e) => (request.transaction_id, e.response_code(), None),
+    };
+    TransactionResponse {
+        transaction_id,
+        message_class: request.message_class,
+        approved: response_code == RESPONSE_APPROVED,
+        response_code: response_code.to_string(),
+        approval_code,
+        stan: request.stan.clone(),
+        retrieval_reference: request.retrieval_reference.clone(),
+        timestamp: Utc::now().timestamp() as u64,
+    }
+}
+
+/// A response to `request` carrying only the echoed fields and a field 39 response code.
+pub fn reply(request: &Iso8583Message, response_code: &str) -> Option<Iso8583Message> {
+    let mut response = Iso8583Message::new(response_mti_for(&request.mti)?);
+    echo_fields(request, &mut response);
+    response.set_field(39, response_code.to_string()).ok()?;
+    Some(response)
+}
+
+fn echo_fields(request: &Iso8583Message, response: &mut Iso8583Message) {
+    for field in ECHOED_FIELDS {
+        if let (Some(value), None) = (request.get_field(field), response.get_field(field)) {
+            let _ = response.set_field(field, value.clone());
+        }
+    }
+}
+
+fn frame_bytes(message: Vec<u8>) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(message.len() + 2);
+    frame.extend_from_slice(&(message.len() as u16).to_be_bytes());
+    frame.extend(message);
+    frame
+}
+
+async fn read_frame<R: AsyncReadExt + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>, String> {
+    let mut header = [0u8; 2];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.to_string()),
+    }
+    let len = u16::from_be_bytes(header) as usize;
+    let mut frame = vec![0u8; len];
+    reader.read_exact(&mut frame).await.map_err(|e| e.to_string())?;
+    Ok(Some(frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::Currency;
+    use crate::core::money::Money;
+    use crate::models::transactions::fixtures;
+    use crate::services::messaging::MTI_AUTH_RESPONSE;
+
+    fn server() -> (Arc<Iso8583Server>, String) {
+        let mut processor = fixtures::processor();
+        let cardholder = fixtures::add_cardholder(&mut processor, Currency::EUR, "100.00");
+        let server = Iso8583Server::new(Arc::new(Mutex::new(processor)), Iso8583Codec::default());
+        (Arc::new(server), cardholder.card_acceptor_id)
+    }
+
+    fn auth_request(stan: &str, amount: &str, acceptor: &str) -> Iso8583Message {
+        let mut message = Iso8583Message::new(MTI_AUTH_REQUEST.to_string());
+        for (field, value) in [(2, "4000000000000002"), (3, "000000"), (4, amount), (7, "0101120000"), (11, stan),
+                               (37, "000000000001"), (41, "TERM0001"), (42, acceptor), (49, "978")] {
+            message.set_field(field, value.to_string()).unwrap();
+        }
+        message
+    }
+
+    fn network_request(stan: &str, code: &str) -> Iso8583Message {
+        let mut message = Iso8583Message::new(MTI_NETWORK_REQUEST.to_string());
+        message.set_field(11, stan.to_string()).unwrap();
+        message.set_field(70, code.to_string()).unwrap();
+        message
+    }
+
+    fn signed_on() -> Mutex<LinkSession> {
+        let mut session = LinkSession::new();
+        session.state = LinkState::SignedOn;
+        Mutex::new(session)
+    }
+
+    #[tokio::test]
+    async fn test_authorization_approve_and_decline() {
+        let (server, acceptor) = server();
+        let session = signed_on();
+        let approved = server.handle_message(&auth_request("000001", "000000002000", &acceptor), &session).await.unwrap();
+        assert_eq!(approved.mti, MTI_AUTH_RESPONSE);
+        assert_eq!(approved.get_field(39).map(String::as_str), Some("00"));
+        assert_eq!(approved.get_field(38).map(String::len), Some(6));
+
+        let declined = server.handle_message(&auth_request("000002", "000000020000", &acceptor), &session).await.unwrap();
+        assert_eq!(declined.get_field(39).map(String::as_str), Some("51"));
+        assert_eq!(declined.get_field(11).map(String::as_str), Some("000002"));
+    }
+
+    #[tokio::test]
+    async fn test_link_must_sign_on_before_transactions() {
+        let (server, acceptor) = server();
+        let session = Mutex::new(LinkSession::new());
+        let rejected = server.handle_message(&auth_request("000001", "000000002000", &acceptor), &session).await.unwrap();
+        assert_eq!(rejected.get_field(39).map(String::as_str), Some("91"));
+
+        let echo = server.handle_message(&network_request("000002", NETWORK_ECHO_TEST), &session).await.unwrap();
+        assert_eq!(echo.mti, "0810");
+        assert_eq!(echo.get_field(39).map(String::as_str), Some("00"));
+        assert!(!session.lock().await.is_signed_on());
+
+        let key_change = server.handle_message(&network_request("000003", NETWORK_KEY_CHANGE), &session).await.unwrap();
+        assert_eq!(key_change.get_field(39).map(String::as_str), Some("91"));
+
+        server.handle_message(&network_request("000004", NETWORK_SIGN_ON), &session).await.unwrap();
+        let approved = server.handle_message(&auth_request("000005", "000000002000", &acceptor), &session).await.unwrap();
+        assert_eq!(approved.get_field(39).map(String::as_str), Some("00"));
+
+        server.handle_message(&network_request("000006", NETWORK_SIGN_OFF), &session).await.unwrap();
+        let rejected = server.handle_message(&auth_request("000007", "000000002000", &acceptor), &session).await.unwrap();
+        assert_eq!(rejected.get_field(39).map(String::as_str), Some("91"));
+    }
+
+    #[test]
+    fn test_key_change_rotates_working_key() {
+        let mut session = LinkSession::new();
+        handle_network_management(&network_request("000001", NETWORK_SIGN_ON), &mut session).unwrap();
+
+        let first = handle_network_management(&network_request("000002", NETWORK_KEY_CHANGE), &mut session).unwrap();
+        assert_eq!(first.get_field(39).map(String::as_str), Some("00"));
+        assert_eq!(first.get_field(48).map(String::len), Some(32));
+        assert_eq!(first.get_field(53).map(String::as_str), Some("0000000000000001"));
+        assert_eq!(first.get_field(48).cloned(), session.working_key().map(|key| hex_encode(key)));
+
+        let second = handle_network_management(&network_request("000003", NETWORK_KEY_CHANGE), &mut session).unwrap();
+        assert_ne!(first.get_field(48), second.get_field(48));
+        assert_eq!(session.key_version, 2);
+    }
+
+    fn reversal(mti: &str, stan: &str, original: &Iso8583Message, actual: Option<&str>) -> Iso8583Message {
+        let mut message = Iso8583Message::new(mti.to_string());
+        message.set_field(7, "0101120500".to_string()).unwrap();
+        message.set_field(11, stan.to_string()).unwrap();
+        message.set_field(90, OriginalDataElements::of(original).unwrap().to_field()).unwrap();
+        if let Some(actual) = actual {
+            message.set_field(95, format!("{}{:0>30}", actual, "")).unwrap();
+        }
+        message
+    }
+
+    async fn transaction_for(server: &Iso8583Server, original: &Iso8583Message) -> crate::models::transactions::Transaction {
+        let processor = server.processor.lock().await;
+        let reference = OriginalDataElements::of(original).unwrap().reference();
+        let tx_id = processor.transaction_for_network_reference(&reference).unwrap();
+        processor.get_transaction(tx_id).unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_partial_and_full_reversal_by_original_data_elements() {
+        let (server, acceptor) = server();
+        let session = signed_on();
+        let original = auth_request("000001", "000000005000", &acceptor);
+        server.handle_message(&original, &session).await.unwrap();
+
+        let partial = reversal(MTI_REVERSAL_REQUEST, "000002", &original, Some("000000003000"));
+        let response = server.handle_message(&partial, &session).await.unwrap();
+        assert_eq!(response.mti, "0410");
+        assert_eq!(response.get_field(39).map(String::as_str), Some("00"));
+        let transaction = transaction_for(&server, &original).await;
+        assert_eq!(transaction.amount, Money::parse("30.00", Currency::EUR).unwrap());
+        assert_eq!(transaction.reversed_amount, Money::parse("20.00", Currency::EUR).unwrap());
+
+        // A repeated advice must not reverse twice
+        for mti in [MTI_REVERSAL_ADVICE, MTI_REVERSAL_ADVICE_REPEAT] {
+            let full = reversal(mti, "000003", &original, None);
+            let response = server.handle_message(&full, &session).await.unwrap();
+            assert_eq!(response.get_field(39).map(String::as_str), Some("00"));
+        }
+        let transaction = transaction_for(&server, &original).await;
+        assert_eq!(transaction.status, crate::models::transactions::TransactionStatus::Reversed);
+        assert_eq!(transaction.reversed_amount, Money::parse("50.00", Currency::EUR).unwrap());
+
+        let unknown = reversal(MTI_REVERSAL_REQUEST, "000004", &auth_request("999999", "000000005000", &acceptor), None);
+        let response = server.handle_message(&unknown, &session).await.unwrap();
+        assert_eq!(response.get_field(39).map(String::as_str), Some("25"));
+    }
+
+    #[tokio::test]
+    async fn test_authorization_advice_is_applied_once() {
+        let (server, acceptor) = server();
+        let session = signed_on();
+        // Stand-in approved more than the balance; the advice is recorded regardless
+        let mut advice = auth_request("000001", "000000015000", &acceptor);
+        advice.mti = MTI_AUTH_ADVICE.to_string();
+        let response = server.handle_message(&advice, &session).await.unwrap();
+        assert_eq!(response.mti, "0130");
+        assert_eq!(response.get_field(39).map(String::as_str), Some("00"));
+        let first = transaction_for(&server, &advice).await;
+
+        advice.mti = MTI_AUTH_ADVICE_REPEAT.to_string();
+        server.handle_message(&advice, &session).await.unwrap();
+        assert_eq!(transaction_for(&server, &advice).await.id, first.id);
+        assert_eq!(server.processor.lock().await.transactions().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_frame_gets_format_error_or_closes_link() {
+        let (server, acceptor) = server();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.run(listener));
+
+        let codec = Iso8583Codec::default();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut data = codec.encode(&auth_request("000020", "000000000500", &acceptor)).unwrap();
+        data.extend_from_slice(b"junk");
+        stream.write_all(&frame_bytes(data)).await.unwrap();
+        let response = codec.decode(&read_frame(&mut stream).await.unwrap().unwrap()).unwrap();
+        assert_eq!(response.mti, MTI_AUTH_RESPONSE);
+        assert_eq!(response.get_field(39).map(String::as_str), Some(RESPONSE_FORMAT_ERROR));
+        assert_eq!(response.get_field(11).map(String::as_str), Some("000020"));
+
+        // Nothing to match a reply to: the connection is closed
+        stream.write_all(&frame_bytes(b"01\x00".to_vec())).await.unwrap();
+        assert_eq!(read_frame(&mut stream).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_requests_over_tcp() {
+        let (server, acceptor) = server();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(server.run(listener));
+
+        let codec = Iso8583Codec::default();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let sign_on = codec.encode(&network_request("000001", NETWORK_SIGN_ON)).unwrap();
+        stream.write_all(&frame_bytes(sign_on)).await.unwrap();
+        for stan in ["000010", "000011", "000012"] {
+            let data = codec.encode(&auth_request(stan, "000000000500", &acceptor)).unwrap();
+            stream.write_all(&frame_bytes(data)).await.unwrap();
+        }
+
+        let frame = read_frame(&mut stream).await.unwrap().unwrap();
+        assert_eq!(codec.decode(&frame).unwrap().mti, "0810");
+
+        let mut stans = HashSet::new();
+        for _ in 0..3 {
+            let frame = read_frame(&mut stream).await.unwrap().unwrap();
+            let response = codec.decode(&frame).unwrap();
+            assert_eq!(response.get_field(39).map(String::as_str), Some("00"));
+            stans.insert(response.get_field(11).cloned().unwrap());
+        }
+        assert_eq!(stans, HashSet::from(["000010".to_string(), "000011".to_string(), "000012".to_string()]));
+    }
+}
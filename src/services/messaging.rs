@@ -1,12 +1,23 @@
 // ISO 8583 messaging module
 
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::core::currency::Currency;
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
+use crate::core::network::{MessageClass, TransactionRequest, TransactionResponse};
+use crate::services::iso8583::Iso8583Codec;
+
+pub const MAX_FIELD: u8 = 192;
+pub const SECONDARY_BITMAP_FIELD: u8 = 1;
+pub const TERTIARY_BITMAP_FIELD: u8 = 65;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Iso8583Message {
     pub mti: String, // Message Type Indicator, 4 digits
-    pub bitmap: Vec<u8>, // Primary bitmap, 8 bytes
     pub fields: HashMap<u8, String>, // Data elements, key is field number
 }
 
@@ -14,63 +25,70 @@ impl Iso8583Message {
     pub fn new(mti: String) -> Self {
         Self {
             mti,
-            bitmap: vec![0; 8],
             fields: HashMap::new(),
         }
     }
 
-    pub fn set_field(&mut self, field_num: u8, value: String) {
-        self.fields.insert(field_num, value);
-        // Update bitmap
-        let byte_index = (field_num - 1) / 8;
-        let bit_index = (field_num - 1) % 8;
-        if byte_index < 8 {
-            self.bitmap[byte_index as usize] |= 1 << (7 - bit_index);
+    /// Sets a data element 2–192. The bitmap fields 1 and 65 are derived and cannot be set.
+    pub fn set_field(&mut self, field_num: u8, value: String) -> Result<(), String> {
+        if field_num == 0 || field_num > MAX_FIELD {
+            return Err(format!("Field {} out of range", field_num));
         }
+        if field_num == SECONDARY_BITMAP_FIELD || field_num == TERTIARY_BITMAP_FIELD {
+            return Err(format!("Field {} is a bitmap and is derived from the other fields", field_num));
+        }
+        self.fields.insert(field_num, value);
+        Ok(())
     }
 
     pub fn get_field(&self, field_num: u8) -> Option<&String> {
         self.fields.get(&field_num)
     }
 
-    // Basic serialization (simplified, real ISO 8583 uses binary)
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut data = Vec::new();
-        data.extend_from_slice(self.mti.as_bytes());
-        data.extend_from_slice(&self.bitmap);
-        // For simplicity, serialize fields as JSON-like
-        for (k, v) in &self.fields {
-            data.push(*k);
-            data.extend_from_slice(&(v.len() as u16).to_be_bytes());
-            data.extend_from_slice(v.as_bytes());
-        }
-        data
+    pub fn has_secondary_bitmap(&self) -> bool {
+        self.fields.keys().any(|&n| n > 64)
     }
 
-    // Basic deserialization
-    pub fn deserialize(data: &[u8]) -> Result<Self, String> {
-        if data.len() < 12 { // MTI 4 + bitmap 8
-            return Err("Data too short".to_string());
+    pub fn has_tertiary_bitmap(&self) -> bool {
+        self.fields.keys().any(|&n| n > 128)
+    }
+
+    /// The primary bitmap followed by the secondary and tertiary bitmaps when fields need them,
+    /// with bit 1 (and bit 65) flagging the presence of the next bitmap.
+    pub fn bitmap(&self) -> Vec<u8> {
+        let len = if self.has_tertiary_bitmap() {
+            24
+        } else if self.has_secondary_bitmap() {
+            16
+        } else {
+            8
+        };
+        let mut bitmap = vec![0u8; len];
+
+        let mut set_bit = |n: u8| {
+            let index = (n - 1) as usize;
+            bitmap[index / 8] |= 0x80 >> (index % 8);
+        };
+        if len > 8 {
+            set_bit(SECONDARY_BITMAP_FIELD);
+        }
+        if len > 16 {
+            set_bit(TERTIARY_BITMAP_FIELD);
         }
-        let mti = String::from_utf8(data[0..4].to_vec()).map_err(|_| "Invalid MTI")?;
-        let bitmap = data[4..12].to_vec();
-        let mut fields = HashMap::new();
-        let mut pos = 12;
-        while pos < data.len() {
-            if pos + 3 > data.len() {
-                break;
-            }
-            let field_num = data[pos];
-            let len = u16::from_be_bytes([data[pos + 1], data[pos + 2]]) as usize;
-            pos += 3;
-            if pos + len > data.len() {
-                return Err("Invalid field length".to_string());
-            }
-            let value = String::from_utf8(data[pos..pos + len].to_vec()).map_err(|_| "Invalid field data")?;
-            fields.insert(field_num, value);
-            pos += len;
+        for &n in self.fields.keys() {
+            set_bit(n);
         }
-        Ok(Self { mti, bitmap, fields })
+        bitmap
+    }
+
+    /// Encodes to the ISO 8583:1987 wire format with ASCII numerics and a binary bitmap.
+    /// Use [`Iso8583Codec`] directly for BCD hosts or custom field dictionaries.
+    pub fn serialize(&self) -> Result<Vec<u8>, String> {
+        Iso8583Codec::default().encode(self)
+    }
+
+    pub fn deserialize(data: &[u8]) -> Result<Self, String> {
+        Iso8583Codec::default().decode(data)
     }
 }
 
@@ -78,4 +96,329 @@ impl Iso8583Message {
 pub const MTI_AUTH_REQUEST: &str = "0100";
 pub const MTI_AUTH_RESPONSE: &str = "0110";
 pub const MTI_FINANCIAL_REQUEST: &str = "0200";
-pub const MTI_FINANCIAL_RESPONSE: &str = "0210";
\ No newline at end of file
+pub const MTI_FINANCIAL_RESPONSE: &str = "0210";
+
+pub const MTI_AUTH_ADVICE: &str = "0120";
+pub const MTI_AUTH_ADVICE_REPEAT: &str = "0121";
+pub const MTI_REVERSAL_REQUEST: &str = "0400";
+pub const MTI_REVERSAL_REPEAT: &str = "0401";
+pub const MTI_REVERSAL_ADVICE: &str = "0420";
+pub const MTI_REVERSAL_ADVICE_REPEAT: &str = "0421";
+pub const MTI_NETWORK_REQUEST: &str = "0800";
+
+// Field 70 network management information codes
+pub const NETWORK_SIGN_ON: &str = "001";
+pub const NETWORK_SIGN_OFF: &str = "002";
+pub const NETWORK_KEY_CHANGE: &str = "161";
+pub const NETWORK_ECHO_TEST: &str = "301";
+
+// Field 39 response codes
+pub const RESPONSE_APPROVED: &str = "00";
+pub const RESPONSE_INVALID_MERCHANT: &str = "03";
+pub const RESPONSE_DO_NOT_HONOR: &str = "05";
+pub const RESPONSE_INVALID_TRANSACTION: &str = "12";
+pub const RESPONSE_INVALID_AMOUNT: &str = "13";
+pub const RESPONSE_INVALID_CARD: &str = "14";
+pub const RESPONSE_NO_ORIGINAL: &str = "25";
+pub const RESPONSE_FORMAT_ERROR: &str = "30";
+pub const RESPONSE_FUNCTION_NOT_SUPPORTED: &str = "40";
+pub const RESPONSE_INSUFFICIENT_FUNDS: &str = "51";
+pub const RESPONSE_EXPIRED_CARD: &str = "54";
+pub const RESPONSE_SUSPECTED_FRAUD: &str = "59";
+pub const RESPONSE_RESTRICTED_CARD: &str = "62";
+pub const RESPONSE_ISSUER_UNAVAILABLE: &str = "91";
+pub const RESPONSE_DUPLICATE_TRANSMISSION: &str = "94";
+pub const RESPONSE_SYSTEM_MALFUNCTION: &str = "96";
+
+/// The response MTI for a request MTI, e.g. 0100 -> 0110. Responses have no response.
+pub fn response_mti_for(mti: &str) -> Option<String> {
+    let bytes = mti.as_bytes();
+    if bytes.len() != 4 || !bytes.iter().all(u8::is_ascii_digit) || !(bytes[2] - b'0').is_multiple_of(2) {
+        return None;
+    }
+    let mut response = mti.to_string();
+    response.replace_range(2..3, &((bytes[2] - b'0') + 1).to_string());
+    Some(response)
+}
+
+/// Field 90: identifies the original request that a reversal refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalDataElements {
+    pub mti: String,
+    pub stan: String,
+    pub transmission_time: String, // MMDDhhmmss, field 7 of the original
+    pub acquirer_id: String,
+    pub forwarding_id: String,
+}
+
+impl OriginalDataElements {
+    /// The data elements identifying `message` itself, for use in a later reversal.
+    pub fn of(message: &Iso8583Message) -> Result<Self, String> {
+        Ok(Self {
+            mti: message.mti.clone(),
+            stan: required(message, 11)?.to_string(),
+            transmission_time: required(message, 7)?.to_string(),
+            acquirer_id: message.get_field(32).map_or("0".to_string(), |v| v.trim_end().to_string()),
+            forwarding_id: message.get_field(33).map_or("0".to_string(), |v| v.trim_end().to_string()),
+        })
+    }
+
+    pub fn parse(value: &str) -> Result<Self, String> {
+        if value.len() != 42 || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Invalid original data elements: {}", value));
+        }
+        let institution = |s: &str| match s.trim_start_matches('0') {
+            "" => "0".to_string(),
+            id => id.to_string(),
+        };
+        Ok(Self {
+            mti: value[0..4].to_string(),
+            stan: value[4..10].to_string(),
+            transmission_time: value[10..20].to_string(),
+            acquirer_id: institution(&value[20..31]),
+            forwarding_id: institution(&value[31..42]),
+        })
+    }
+
+    pub fn to_field(&self) -> String {
+        format!("{}{}{}{:0>11}{:0>11}", self.mti, self.stan, self.transmission_time, self.acquirer_id, self.forwarding_id)
+    }
+
+    /// Identifies the original transaction independently of its MTI, which the reversal
+    /// may report as a repeat or advice.
+    pub fn reference(&self) -> String {
+        format!("{}:{}:{}", self.acquirer_id.trim_start_matches('0'), self.stan, self.transmission_time)
+    }
+}
+
+/// Field 95 actual transaction amount, if the reversal is partial.
+pub fn replacement_amount(message: &Iso8583Message, currency: Currency) -> Result<Option<Money>, String> {
+    let Some(value) = message.get_field(95) else {
+        return Ok(None);
+    };
+    let actual = value.get(..12).ok_or("Invalid replacement amounts")?;
+    let minor_units = actual.parse::<i64>().map_err(|_| "Invalid replacement amounts")?;
+    Ok(Some(Money::from_minor(minor_units, currency)))
+}
+
+/// Resolves the card and merchant referenced by an ISO 8583 message to internal ids.
+pub trait IdentifierResolver {
+    fn card_id_for_pan(&self, pan: &str) -> Option<Uuid>;
+    fn merchant_id_for_acceptor(&self, card_acceptor_id: &str) -> Option<Uuid>;
+}
+
+fn response_mti(class: MessageClass) -> &'static str {
+    match class {
+        MessageClass::Authorization => MTI_AUTH_RESPONSE,
+        MessageClass::Financial => MTI_FINANCIAL_RESPONSE,
+    }
+}
+
+fn required(message: &Iso8583Message, field_num: u8) -> Result<&str, String> {
+    message.get_field(field_num)
+        .map(|v| v.trim_end())
+        .ok_or_else(|| format!("Missing field {} in {}", field_num, message.mti))
+}
+
+// Field 7 is MMDDhhmmss in UTC without a year; assume the most recent such moment
+fn transmission_time(value: &str, now: DateTime<Utc>) -> Result<u64, String> {
+    let parse = |year: i32| NaiveDateTime::parse_from_str(&format!("{}{}", year, value), "%Y%m%d%H%M%S");
+    let mut time = parse(now.year()).map_err(|_| format!("Invalid transmission time: {}", value))?.and_utc();
+    if time > now + chrono::Duration::days(1) {
+        time = parse(now.year() - 1).map_err(|_| format!("Invalid transmission time: {}", value))?.and_utc();
+    }
+    Ok(time.timestamp().max(0) as u64)
+}
+
+fn format_transmission_time(timestamp: u64) -> Result<String, String> {
+    let time = DateTime::<Utc>::from_timestamp(timestamp as i64, 0).ok_or("Invalid timestamp")?;
+    Ok(time.format("%m%d%H%M%S").to_string())
+}
+
+/// Maps a 0100 authorization (or 0120 advice) or 0200 financial request to a [`TransactionRequest`].
+pub fn request_from_iso(message: &Iso8583Message, resolver: &impl IdentifierResolver) -> Result<TransactionRequest, EuropayError> {
+    let message_class = match message.mti.as_str() {
+        MTI_AUTH_REQUEST | MTI_AUTH_ADVICE | MTI_AUTH_ADVICE_REPEAT => MessageClass::Authorization,
+        MTI_FINANCIAL_REQUEST => MessageClass::Financial,
+        other => return Err(EuropayError::InvalidRequest(format!("Not a transaction request: {}", other))),
+    };
+    let required = |field_num| required(message, field_num).map_err(EuropayError::InvalidRequest);
+
+    let pan = required(2)?;
+    let card_acceptor_id = required(42)?;
+    let card_id = resolver.card_id_for_pan(pan).ok_or(EuropayError::CardNotFound)?;
+    let merchant_id = resolver.merchant_id_for_acceptor(card_acceptor_id).ok_or(EuropayError::MerchantNotFound)?;
+
+    let numeric = required(49)?.parse::<u16>().map_err(|_| EuropayError::InvalidRequest("Invalid currency code".to_string()))?;
+    let currency = Currency::from_numeric(numeric)
+        .ok_or_else(|| EuropayError::InvalidRequest(format!("Unknown currency code {}", numeric)))?;
+    let minor_units = required(4)?.parse::<i64>().map_err(|_| EuropayError::InvalidRequest("Invalid amount".to_string()))?;
+
+    let timestamp = match message.get_field(7) {
+        Some(value) => transmission_time(value, Utc::now()).map_err(EuropayError::InvalidRequest)?,
+        None => Utc::now().timestamp() as u64,
+    };
+
+    Ok(TransactionRequest {
+        transaction_id: Uuid::new_v4(),
+        message_class,
+        card_id,
+        merchant_id,
+        amount: Money::from_minor(minor_units, currency),
+        timestamp,
+        pan: pan.to_string(),
+        processing_code: required(3)?.to_string(),
+        stan: required(11)?.to_string(),
+        retrieval_reference: required(37)?.to_string(),
+        terminal_id: required(41)?.to_string(),
+        card_acceptor_id: card_acceptor_id.to_string(),
+    })
+}
+
+pub fn response_to_iso(response: &TransactionResponse) -> Result<Iso8583Message, String> {
+    let mut message = Iso8583Message::new(response_mti(response.message_class).to_string());
+    message.set_field(7, format_transmission_time(response.timestamp)?)?;
+    message.set_field(11, response.stan.clone())?;
+    message.set_field(37, response.retrieval_reference.clone())?;
+    if let Some(approval_code) = &response.approval_code {
+        message.set_field(38, approval_code.clone())?;
+    }
+    message.set_field(39, response.response_code.clone())?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The acquirer side of the mapping. The node only answers requests, so it is needed
+    // just to check that messages survive a round trip.
+    fn request_mti(class: MessageClass) -> &'static str {
+        match class {
+            MessageClass::Authorization => MTI_AUTH_REQUEST,
+            MessageClass::Financial => MTI_FINANCIAL_REQUEST,
+        }
+    }
+
+    fn request_to_iso(request: &TransactionRequest) -> Result<Iso8583Message, String> {
+        if request.amount.is_negative() {
+            return Err("Amount must not be negative".to_string());
+        }
+
+        let mut message = Iso8583Message::new(request_mti(request.message_class).to_string());
+        message.set_field(2, request.pan.clone())?;
+        message.set_field(3, request.processing_code.clone())?;
+        message.set_field(4, format!("{:012}", request.amount.minor_units()))?;
+        message.set_field(7, format_transmission_time(request.timestamp)?)?;
+        message.set_field(11, request.stan.clone())?;
+        message.set_field(37, request.retrieval_reference.clone())?;
+        message.set_field(41, request.terminal_id.clone())?;
+        message.set_field(42, request.card_acceptor_id.clone())?;
+        message.set_field(49, format!("{:03}", request.amount.currency().numeric_code()))?;
+        Ok(message)
+    }
+
+    /// Maps a 0110 or 0210 to a [`TransactionResponse`] for the request it answers.
+    fn response_from_iso(message: &Iso8583Message, original: &TransactionRequest) -> Result<TransactionResponse, String> {
+        if message.mti != response_mti(original.message_class) {
+            return Err(format!("{} does not answer a {}", message.mti, request_mti(original.message_class)));
+        }
+        let stan = required(message, 11)?;
+        if stan != original.stan {
+            return Err(format!("STAN {} does not match request STAN {}", stan, original.stan));
+        }
+
+        let response_code = required(message, 39)?.to_string();
+        Ok(TransactionResponse {
+            transaction_id: original.transaction_id,
+            message_class: original.message_class,
+            approved: response_code == RESPONSE_APPROVED,
+            response_code,
+            approval_code: message.get_field(38).map(|v| v.trim_end().to_string()),
+            stan: stan.to_string(),
+            retrieval_reference: message.get_field(37).map_or(original.retrieval_reference.clone(), |v| v.trim_end().to_string()),
+            timestamp: Utc::now().timestamp() as u64,
+        })
+    }
+
+    struct Directory {
+        card_id: Uuid,
+        merchant_id: Uuid,
+    }
+
+    impl IdentifierResolver for Directory {
+        fn card_id_for_pan(&self, pan: &str) -> Option<Uuid> {
+            (pan == "4000000000000002").then_some(self.card_id)
+        }
+
+        fn merchant_id_for_acceptor(&self, card_acceptor_id: &str) -> Option<Uuid> {
+            (card_acceptor_id == "MERCHANT0000001").then_some(self.merchant_id)
+        }
+    }
+
+    fn request(class: MessageClass, directory: &Directory) -> TransactionRequest {
+        TransactionRequest {
+            transaction_id: Uuid::new_v4(),
+            message_class: class,
+            card_id: directory.card_id,
+            merchant_id: directory.merchant_id,
+            amount: Money::parse("42.50", Currency::EUR).unwrap(),
+            timestamp: (Utc::now().timestamp() - 60) as u64,
+            pan: "4000000000000002".to_string(),
+            processing_code: "000000".to_string(),
+            stan: "000123".to_string(),
+            retrieval_reference: "529012000123".to_string(),
+            terminal_id: "TERM0001".to_string(),
+            card_acceptor_id: "MERCHANT0000001".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_request_round_trip_over_the_wire() {
+        let directory = Directory { card_id: Uuid::new_v4(), merchant_id: Uuid::new_v4() };
+        let codec = Iso8583Codec::default();
+        for class in [MessageClass::Authorization, MessageClass::Financial] {
+            let original = request(class, &directory);
+            let message = request_to_iso(&original).unwrap();
+            assert_eq!(message.mti, request_mti(class));
+            assert_eq!(message.get_field(49).map(String::as_str), Some("978"));
+
+            let decoded = codec.decode(&codec.encode(&message).unwrap()).unwrap();
+            let mapped = request_from_iso(&decoded, &directory).unwrap();
+            assert_eq!(mapped, TransactionRequest { transaction_id: mapped.transaction_id, ..original });
+        }
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let directory = Directory { card_id: Uuid::new_v4(), merchant_id: Uuid::new_v4() };
+        let original = request(MessageClass::Authorization, &directory);
+        let response = TransactionResponse {
+            transaction_id: original.transaction_id,
+            message_class: MessageClass::Authorization,
+            approved: false,
+            response_code: "51".to_string(),
+            approval_code: None,
+            stan: original.stan.clone(),
+            retrieval_reference: original.retrieval_reference.clone(),
+            timestamp: Utc::now().timestamp() as u64,
+        };
+
+        let message = response_to_iso(&response).unwrap();
+        assert_eq!(message.mti, MTI_AUTH_RESPONSE);
+        assert_eq!(message.get_field(39).map(String::as_str), Some("51"));
+        let codec = Iso8583Codec::default();
+        let decoded = codec.decode(&codec.encode(&message).unwrap()).unwrap();
+        let mapped = response_from_iso(&decoded, &original).unwrap();
+        assert_eq!(mapped, TransactionResponse { timestamp: mapped.timestamp, ..response });
+    }
+
+    #[test]
+    fn test_unknown_card_is_rejected() {
+        let directory = Directory { card_id: Uuid::new_v4(), merchant_id: Uuid::new_v4() };
+        let mut original = request(MessageClass::Authorization, &directory);
+        original.pan = "5100000000000008".to_string();
+        let message = request_to_iso(&original).unwrap();
+        assert_eq!(request_from_iso(&message, &directory), Err(EuropayError::CardNotFound));
+    }
+}
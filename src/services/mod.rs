@@ -1,6 +1,11 @@
 // Services module
 
+pub mod disputes;
+pub mod idempotency;
+pub mod iso8583;
+pub mod iso_server;
 pub mod messaging;
 pub mod security;
 pub mod network;
+pub mod reversals;
 pub mod settlement;
\ No newline at end of file
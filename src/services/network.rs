@@ -1,24 +1,106 @@
 // Network service for inter-node communication
 
+use chrono::Utc;
 use reqwest::Client;
-use serde_json;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::core::network::{NetworkMessage, NetworkNode, NetworkProtocol};
+use crate::core::network::{NetworkMessage, NetworkNode, NetworkProtocol, TransactionRequest, TransactionResponse};
+use crate::services::reversals::ReversalQueue;
 
 pub struct HttpNetworkService {
     client: Client,
     nodes: Arc<Mutex<HashMap<Uuid, NetworkNode>>>,
+    reversals: Arc<Mutex<ReversalQueue>>,
+    authorization_timeout: Duration,
 }
 
 impl HttpNetworkService {
-    pub fn new() -> Self {
+    pub fn new(authorization_timeout: Duration, reversal_retry: Duration) -> Self {
+        let retry = chrono::Duration::from_std(reversal_retry).unwrap_or(chrono::Duration::seconds(30));
         Self {
             client: Client::new(),
             nodes: Arc::new(Mutex::new(HashMap::new())),
+            reversals: Arc::new(Mutex::new(ReversalQueue::new(retry))),
+            authorization_timeout,
+        }
+    }
+
+    pub fn reversals(&self) -> Arc<Mutex<ReversalQueue>> {
+        self.reversals.clone()
+    }
+
+    /// Sends an authorization to the issuer. If no response arrives within the authorization
+    /// timeout the issuer may still have approved it, so a reversal is queued.
+    pub async fn authorize(&self, issuer: &NetworkNode, request: TransactionRequest) -> Result<TransactionResponse, String> {
+        let transaction_id = request.transaction_id;
+        let deadline = Utc::now() + chrono::Duration::from_std(self.authorization_timeout).map_err(|e| e.to_string())?;
+        self.reversals.lock().await.track(request.clone(), issuer.clone(), deadline);
+
+        let message = NetworkMessage::TransactionRequest(request);
+        let result = match tokio::time::timeout(self.authorization_timeout, self.send_message(issuer, message)).await {
+            Ok(Ok(NetworkMessage::TransactionResponse(response))) if response.transaction_id == transaction_id => Ok(response),
+            Ok(Ok(_)) => Err("Unexpected response to authorization".to_string()),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err("Authorization timed out".to_string()),
+        };
+
+        let mut reversals = self.reversals.lock().await;
+        match result {
+            Ok(response) => {
+                reversals.complete(transaction_id);
+                Ok(response)
+            }
+            Err(e) => {
+                reversals.fail(transaction_id, Utc::now());
+                Err(e)
+            }
+        }
+    }
+
+    /// Sends every due reversal once and returns how many the issuers acknowledged.
+    pub async fn process_reversals(&self) -> usize {
+        let due = {
+            let mut reversals = self.reversals.lock().await;
+            reversals.expire(Utc::now());
+            reversals.due(Utc::now())
+        };
+
+        let mut acknowledged = 0;
+        for reversal in due {
+            let transaction_id = reversal.request.transaction_id;
+            let message = NetworkMessage::ReversalRequest(reversal.request);
+            let result = match tokio::time::timeout(self.authorization_timeout, self.send_message(&reversal.issuer, message)).await {
+                Ok(Ok(NetworkMessage::ReversalResponse(response))) if response.acknowledged => Ok(()),
+                Ok(Ok(_)) => Err("Reversal not acknowledged".to_string()),
+                Ok(Err(e)) => Err(e),
+                Err(_) => Err("Reversal timed out".to_string()),
+            };
+
+            let mut reversals = self.reversals.lock().await;
+            match result {
+                Ok(()) => {
+                    reversals.acknowledge(transaction_id);
+                    acknowledged += 1;
+                }
+                Err(e) => {
+                    warn!("Reversal of {} failed: {}", transaction_id, e);
+                    reversals.retry_later(transaction_id, Utc::now(), e);
+                }
+            }
+        }
+        acknowledged
+    }
+
+    pub async fn run_reversal_worker(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            self.process_reversals().await;
         }
     }
 
@@ -77,4 +159,54 @@ impl NetworkProtocol for HttpNetworkService {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::Currency;
+    use crate::core::money::Money;
+    use crate::core::network::{MessageClass, NodeRole};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_timed_out_authorization_queues_reversal() {
+        // An issuer that accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = NetworkNode::new(format!("http://{}", listener.local_addr().unwrap()), NodeRole::Issuer);
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        let service = HttpNetworkService::new(Duration::from_millis(50), Duration::from_secs(1));
+        let request = TransactionRequest {
+            transaction_id: Uuid::new_v4(),
+            message_class: MessageClass::Authorization,
+            card_id: Uuid::new_v4(),
+            merchant_id: Uuid::new_v4(),
+            amount: Money::parse("25.00", Currency::EUR).unwrap(),
+            timestamp: Utc::now().timestamp() as u64,
+            pan: "4000000000000002".to_string(),
+            processing_code: "000000".to_string(),
+            stan: "000001".to_string(),
+            retrieval_reference: "000000000001".to_string(),
+            terminal_id: "TERM0001".to_string(),
+            card_acceptor_id: "MERCHANT0000001".to_string(),
+        };
+
+        assert_eq!(service.authorize(&issuer, request.clone()).await, Err("Authorization timed out".to_string()));
+        let reversals = service.reversals();
+        assert_eq!(reversals.lock().await.in_flight().count(), 0);
+        assert_eq!(reversals.lock().await.pending().next().map(|r| r.request.transaction_id), Some(request.transaction_id));
+
+        // The issuer stays silent, so the reversal is kept for a later retry
+        assert_eq!(service.process_reversals().await, 0);
+        let queue = reversals.lock().await;
+        let pending = queue.pending().next().unwrap();
+        assert_eq!(pending.attempts, 1);
+        assert_eq!(pending.last_error.as_deref(), Some("Reversal timed out"));
+    }
+}
@@ -0,0 +1,165 @@
+// Reversal queue for authorizations left without a response
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::core::network::{NetworkNode, ReversalRequest, TransactionRequest};
+
+// Retry delays double after each failed attempt up to this multiple of the base interval
+const MAX_BACKOFF_FACTOR: i32 = 32;
+
+#[derive(Debug, Clone)]
+pub struct InFlightAuthorization {
+    pub request: TransactionRequest,
+    pub issuer: NetworkNode,
+    pub deadline: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingReversal {
+    pub request: ReversalRequest,
+    pub issuer: NetworkNode,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+/// Tracks authorizations sent to issuers and queues a reversal for every one that is not
+/// answered by its deadline, retrying each reversal until the issuer acknowledges it.
+pub struct ReversalQueue {
+    in_flight: HashMap<Uuid, InFlightAuthorization>,
+    pending: HashMap<Uuid, PendingReversal>,
+    retry_interval: Duration,
+}
+
+impl ReversalQueue {
+    pub fn new(retry_interval: Duration) -> Self {
+        Self {
+            in_flight: HashMap::new(),
+            pending: HashMap::new(),
+            retry_interval,
+        }
+    }
+
+    pub fn track(&mut self, request: TransactionRequest, issuer: NetworkNode, deadline: DateTime<Utc>) {
+        self.in_flight.insert(request.transaction_id, InFlightAuthorization { request, issuer, deadline });
+    }
+
+    /// The issuer answered in time; no reversal is needed.
+    pub fn complete(&mut self, transaction_id: Uuid) -> bool {
+        self.in_flight.remove(&transaction_id).is_some()
+    }
+
+    /// The outcome is unknown (send failure or timeout); reverse immediately.
+    pub fn fail(&mut self, transaction_id: Uuid, now: DateTime<Utc>) {
+        if let Some(authorization) = self.in_flight.remove(&transaction_id) {
+            self.enqueue(authorization, now);
+        }
+    }
+
+    /// Queues reversals for in-flight authorizations past their deadline.
+    pub fn expire(&mut self, now: DateTime<Utc>) -> usize {
+        let expired: Vec<Uuid> = self.in_flight.values()
+            .filter(|authorization| authorization.deadline <= now)
+            .map(|authorization| authorization.request.transaction_id)
+            .collect();
+        for transaction_id in &expired {
+            self.fail(*transaction_id, now);
+        }
+        expired.len()
+    }
+
+    fn enqueue(&mut self, authorization: InFlightAuthorization, now: DateTime<Utc>) {
+        let request = ReversalRequest::for_request(&authorization.request, now.timestamp() as u64);
+        self.pending.insert(request.transaction_id, PendingReversal {
+            request,
+            issuer: authorization.issuer,
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+        });
+    }
+
+    /// Reversals whose next attempt is due.
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<PendingReversal> {
+        self.pending.values()
+            .filter(|reversal| reversal.next_attempt_at <= now)
+            .cloned()
+            .collect()
+    }
+
+    pub fn acknowledge(&mut self, transaction_id: Uuid) -> bool {
+        self.pending.remove(&transaction_id).is_some()
+    }
+
+    pub fn retry_later(&mut self, transaction_id: Uuid, now: DateTime<Utc>, error: String) {
+        if let Some(reversal) = self.pending.get_mut(&transaction_id) {
+            reversal.attempts += 1;
+            let factor = 2i32.saturating_pow(reversal.attempts - 1).min(MAX_BACKOFF_FACTOR);
+            reversal.next_attempt_at = now + self.retry_interval * factor;
+            reversal.last_error = Some(error);
+        }
+    }
+
+    pub fn in_flight(&self) -> impl Iterator<Item = &InFlightAuthorization> {
+        self.in_flight.values()
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = &PendingReversal> {
+        self.pending.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::currency::Currency;
+    use crate::core::money::Money;
+    use crate::core::network::{MessageClass, NodeRole};
+
+    fn request() -> TransactionRequest {
+        TransactionRequest {
+            transaction_id: Uuid::new_v4(),
+            message_class: MessageClass::Authorization,
+            card_id: Uuid::new_v4(),
+            merchant_id: Uuid::new_v4(),
+            amount: Money::parse("25.00", Currency::EUR).unwrap(),
+            timestamp: 1_700_000_000,
+            pan: "4000000000000002".to_string(),
+            processing_code: "000000".to_string(),
+            stan: "000123".to_string(),
+            retrieval_reference: "000000000123".to_string(),
+            terminal_id: "TERM0001".to_string(),
+            card_acceptor_id: "MERCHANT0000001".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_unanswered_authorization_is_reversed_with_backoff() {
+        let now = Utc::now();
+        let issuer = NetworkNode::new("http://issuer.example".to_string(), NodeRole::Issuer);
+        let mut queue = ReversalQueue::new(Duration::seconds(10));
+        let (answered, unanswered) = (request(), request());
+        queue.track(answered.clone(), issuer.clone(), now + Duration::seconds(30));
+        queue.track(unanswered.clone(), issuer, now + Duration::seconds(30));
+
+        assert!(queue.complete(answered.transaction_id));
+        assert_eq!(queue.expire(now + Duration::seconds(29)), 0);
+        assert_eq!(queue.expire(now + Duration::seconds(30)), 1);
+
+        let due = queue.due(now + Duration::seconds(30));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].request.transaction_id, unanswered.transaction_id);
+        assert_eq!(due[0].request.original_stan, "000123");
+
+        let failed_at = now + Duration::seconds(30);
+        queue.retry_later(unanswered.transaction_id, failed_at, "Connection refused".to_string());
+        queue.retry_later(unanswered.transaction_id, failed_at, "Connection refused".to_string());
+        assert!(queue.due(failed_at + Duration::seconds(19)).is_empty());
+        assert_eq!(queue.due(failed_at + Duration::seconds(20)).len(), 1);
+
+        assert!(queue.acknowledge(unanswered.transaction_id));
+        assert_eq!(queue.pending().count(), 0);
+    }
+}
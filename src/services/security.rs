@@ -1,28 +1,39 @@
 // Security module
 
 use ring::rand::SecureRandom;
-use ring::{aead, rand};
+use ring::{aead, hmac, rand};
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use crate::core::money::Money;
+use crate::services::iso8583::hex_encode;
+
 pub struct SecurityManager {
     rng: rand::SystemRandom,
     key: aead::LessSafeKey,
+    pan_key: hmac::Key, // keys the PAN hash, derived from the data key
     tokens: HashMap<String, String>, // token -> PAN
 }
 
 impl SecurityManager {
+    /// A manager with a fresh random key; what it encrypts is unreadable after a restart.
     pub fn new() -> Self {
-        let rng = rand::SystemRandom::new();
         let mut key_bytes = [0u8; 32];
-        rng.fill(&mut key_bytes).unwrap();
-        let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &key_bytes).unwrap());
+        rand::SystemRandom::new().fill(&mut key_bytes).unwrap();
+        Self::with_key(&key_bytes).unwrap()
+    }
 
-        Self {
-            rng,
-            key,
+    /// A manager for data at rest, keyed by a 256-bit key that outlives the process.
+    pub fn with_key(key_bytes: &[u8]) -> Result<Self, String> {
+        let key = aead::UnboundKey::new(&aead::AES_256_GCM, key_bytes).map_err(|_| "Data key must be 32 bytes".to_string())?;
+        let pan_key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key_bytes), b"europay pan hash");
+
+        Ok(Self {
+            rng: rand::SystemRandom::new(),
+            key: aead::LessSafeKey::new(key),
+            pan_key: hmac::Key::new(hmac::HMAC_SHA256, pan_key.as_ref()),
             tokens: HashMap::new(),
-        }
+        })
     }
 
     pub fn tokenize_pan(&mut self, pan: &str) -> String {
@@ -35,25 +46,60 @@ impl SecurityManager {
         self.tokens.get(token)
     }
 
+    /// Seals `data` under a random nonce, which is prepended to the ciphertext.
     pub fn encrypt_data(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let mut nonce_bytes = [0u8; aead::NONCE_LEN];
+        self.rng.fill(&mut nonce_bytes).map_err(|_| "Nonce generation failed".to_string())?;
         let mut in_out = data.to_vec();
-        let nonce = aead::Nonce::assume_unique_for_key([0u8; 12]); // In real system, use unique nonce
-        self.key.seal_in_place_append_tag(nonce, aead::Aad::empty(), &mut in_out)
+        self.key.seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce_bytes), aead::Aad::empty(), &mut in_out)
             .map_err(|_| "Encryption failed".to_string())?;
-        Ok(in_out)
+        Ok([nonce_bytes.as_slice(), &in_out].concat())
     }
 
     pub fn decrypt_data(&self, encrypted: &[u8]) -> Result<Vec<u8>, String> {
-        let mut in_out = encrypted.to_vec();
-        let nonce = aead::Nonce::assume_unique_for_key([0u8; 12]);
-        self.key.open_in_place(nonce, aead::Aad::empty(), &mut in_out)
+        if encrypted.len() < aead::NONCE_LEN {
+            return Err("Decryption failed".to_string());
+        }
+        let (nonce, ciphertext) = encrypted.split_at(aead::NONCE_LEN);
+        let nonce = aead::Nonce::try_assume_unique_for_key(nonce).map_err(|_| "Decryption failed".to_string())?;
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self.key.open_in_place(nonce, aead::Aad::empty(), &mut in_out)
             .map_err(|_| "Decryption failed".to_string())?;
-        in_out.truncate(in_out.len() - aead::AES_256_GCM.tag_len());
-        Ok(in_out)
+        Ok(plaintext.to_vec())
+    }
+
+    /// A keyed hash of the PAN, so equal PANs can be found without storing them in clear.
+    pub fn pan_hash(&self, pan: &str) -> String {
+        hex_encode(hmac::sign(&self.pan_key, pan.as_bytes()).as_ref())
     }
 
-    pub fn check_fraud(&self, amount: f64, card_pan: &str) -> bool {
-        // Simple fraud detection: flag if amount > 1000
-        amount > 1000.0
+    pub fn check_fraud(&self, amount: &Money, _card_pan: &str) -> bool {
+        // Simple fraud detection: flag if amount > 1000 in the transaction currency
+        Money::from_major(1000, amount.currency()).is_ok_and(|limit| *amount > limit)
+    }
+}
+
+impl Default for SecurityManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_encrypted_under_a_persistent_key_reads_back() {
+        let key = [7u8; 32];
+        let first = SecurityManager::with_key(&key).unwrap();
+        let sealed = first.encrypt_data(b"4000000000000002").unwrap();
+        assert_ne!(sealed, first.encrypt_data(b"4000000000000002").unwrap());
+
+        let second = SecurityManager::with_key(&key).unwrap();
+        assert_eq!(second.decrypt_data(&sealed).unwrap(), b"4000000000000002");
+        assert_eq!(first.pan_hash("4000000000000002"), second.pan_hash("4000000000000002"));
+        assert!(SecurityManager::new().decrypt_data(&sealed).is_err());
+        assert!(SecurityManager::with_key(&[0u8; 16]).is_err());
     }
 }
\ No newline at end of file
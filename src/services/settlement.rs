@@ -1,26 +1,45 @@
 // Settlement service for fund transfers
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
-use crate::models::transactions::Transaction;
+use crate::models::ledger::{LedgerAccountType, Posting};
+use crate::models::transactions::{PaymentProcessor, TransactionStatus, TransactionType};
 use crate::core::currency::Currency;
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
+use crate::repositories::memory::{InMemoryAdjustmentRepository, InMemoryBatchRepository};
+use crate::repositories::{AdjustmentRepository, BatchRepository};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SettlementBatch {
     pub id: Uuid,
     pub issuer_id: Uuid,
     pub acquirer_id: Uuid,
     pub transactions: Vec<Uuid>,
-    pub total_amount: f64,
-    pub currency: Currency,
+    pub adjustments: Vec<SettlementAdjustment>,
+    pub total_amount: Money,
     pub status: SettlementStatus,
     pub created_at: DateTime<Utc>,
     pub settled_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// A fund movement outside the purchase flow, e.g. from a dispute. A positive amount is
+/// owed by the issuer to the acquirer, a negative one by the acquirer to the issuer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettlementAdjustment {
+    pub id: Uuid,
+    pub issuer_id: Uuid,
+    pub acquirer_id: Uuid,
+    pub amount: Money,
+    pub reference: Uuid, // e.g. the dispute id
+    pub description: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum SettlementStatus {
     Pending,
     Processing,
@@ -28,75 +47,246 @@ pub enum SettlementStatus {
     Failed,
 }
 
+/// Settles between issuers and acquirers. Batches and pending adjustments are written
+/// through to their repositories.
 pub struct SettlementService {
     batches: HashMap<Uuid, SettlementBatch>,
+    pending_adjustments: Vec<SettlementAdjustment>,
+    repository: Box<dyn BatchRepository>,
+    adjustments: Box<dyn AdjustmentRepository>,
 }
 
 impl SettlementService {
     pub fn new() -> Self {
         Self {
             batches: HashMap::new(),
+            pending_adjustments: Vec::new(),
+            repository: Box::new(InMemoryBatchRepository::default()),
+            adjustments: Box::new(InMemoryAdjustmentRepository::default()),
         }
     }
 
-    pub fn create_batch(&mut self, issuer_id: Uuid, acquirer_id: Uuid, transactions: Vec<&Transaction>) -> Uuid {
+    /// Restores the batches and the adjustments not yet batched from their repositories.
+    pub fn load(repository: Box<dyn BatchRepository>, adjustments: Box<dyn AdjustmentRepository>) -> Result<Self, EuropayError> {
+        let batches = repository.all()?.into_iter().map(|batch| (batch.id, batch)).collect();
+        let mut pending_adjustments = adjustments.all()?;
+        pending_adjustments.sort_by_key(|adjustment| adjustment.created_at);
+        Ok(Self {
+            batches,
+            pending_adjustments,
+            repository,
+            adjustments,
+        })
+    }
+
+    /// Queues an adjustment for the next batch between its issuer and acquirer.
+    pub fn add_adjustment(&mut self, adjustment: SettlementAdjustment) -> Result<(), EuropayError> {
+        self.adjustments.save(&adjustment)?;
+        self.pending_adjustments.push(adjustment);
+        Ok(())
+    }
+
+    pub fn pending_adjustments(&self) -> &[SettlementAdjustment] {
+        &self.pending_adjustments
+    }
+
+    /// Batches captured purchases and refunds between an issuer and one of its acquirers,
+    /// netting in the pending adjustments between them.
+    pub fn create_batch(&mut self, issuer_id: Uuid, acquirer_id: Uuid, transaction_ids: &[Uuid], processor: &PaymentProcessor) -> Result<Uuid, EuropayError> {
+        let mut transactions = Vec::new();
+        let mut seen = HashSet::new();
+        for id in transaction_ids {
+            let transaction = processor.get_transaction(*id).ok_or(EuropayError::TransactionNotFound)?;
+            let merchant = processor.get_merchant(transaction.merchant_id).ok_or(EuropayError::MerchantNotFound)?;
+            if transaction.status != TransactionStatus::Captured || transaction.transaction_type == TransactionType::Chargeback {
+                return Err(EuropayError::InvalidState("Only captured purchases and refunds can be settled".to_string()));
+            }
+            if merchant.acquirer_id != acquirer_id {
+                return Err(EuropayError::InvalidRequest(format!("Transaction {} belongs to another acquirer", id)));
+            }
+            if !seen.insert(*id) || self.batches.values().any(|b| b.status != SettlementStatus::Failed && b.transactions.contains(id)) {
+                return Err(EuropayError::InvalidState(format!("Transaction {} is already batched", id)));
+            }
+            transactions.push(transaction);
+        }
+
         let batch_id = Uuid::new_v4();
-        let total_amount: f64 = transactions.iter().map(|t| t.amount).sum();
-        let currency = transactions.first().map(|t| t.currency).unwrap_or(Currency::EUR);
+        let currency = transactions.first().map(|t| t.amount.currency())
+            .or_else(|| self.pending_adjustments.iter()
+                .find(|a| a.issuer_id == issuer_id && a.acquirer_id == acquirer_id)
+                .map(|a| a.amount.currency()))
+            .unwrap_or(Currency::EUR);
+        // Purchases are owed by the issuer to the acquirer, refunds the other way
+        let mut total_amount = Money::zero(currency);
+        for transaction in &transactions {
+            total_amount = match transaction.transaction_type {
+                TransactionType::Refund => total_amount.checked_sub(&transaction.amount),
+                _ => total_amount.checked_add(&transaction.amount),
+            }?;
+        }
+
+        // Pending adjustments between the same parties and in the batch currency are netted in
+        let included = |a: &SettlementAdjustment| a.issuer_id == issuer_id && a.acquirer_id == acquirer_id && a.amount.currency() == currency;
+        for adjustment in self.pending_adjustments.iter().filter(|a| included(a)) {
+            total_amount = total_amount.checked_add(&adjustment.amount)?;
+        }
+        let (adjustments, remaining): (Vec<_>, Vec<_>) = self.pending_adjustments.iter().cloned().partition(included);
 
         let batch = SettlementBatch {
             id: batch_id,
             issuer_id,
             acquirer_id,
             transactions: transactions.iter().map(|t| t.id).collect(),
+            adjustments,
             total_amount,
-            currency,
             status: SettlementStatus::Pending,
             created_at: Utc::now(),
             settled_at: None,
         };
 
+        self.repository.save(&batch)?;
+        for adjustment in &batch.adjustments {
+            self.adjustments.remove(adjustment.id)?;
+        }
+        self.pending_adjustments = remaining;
         self.batches.insert(batch_id, batch);
-        batch_id
+        Ok(batch_id)
     }
 
-    pub fn process_settlement(&mut self, batch_id: Uuid) -> Result<(), String> {
-        let batch = self.batches.get_mut(&batch_id).ok_or("Batch not found")?;
+    /// Settles the batch by moving its net total between the issuer's and the acquirer's
+    /// settlement accounts in the ledger, and marks its transactions settled.
+    pub fn process_settlement(&mut self, batch_id: Uuid, processor: &mut PaymentProcessor) -> Result<(), EuropayError> {
+        let batch = self.batches.get_mut(&batch_id).ok_or(EuropayError::BatchNotFound)?;
 
         if batch.status != SettlementStatus::Pending {
-            return Err("Batch not in pending status".to_string());
+            return Err(EuropayError::InvalidState("Batch not in pending status".to_string()));
+        }
+
+        // A transaction reversed since it was batched no longer has funds to settle
+        if batch.transactions.iter().any(|id| processor.get_transaction(*id).is_none_or(|t| t.status != TransactionStatus::Captured)) {
+            batch.status = SettlementStatus::Failed;
+            self.repository.save(batch)?;
+            return Err(EuropayError::InvalidState("Batch holds a transaction that is no longer captured".to_string()));
         }
 
         batch.status = SettlementStatus::Processing;
 
-        // In real system, this would involve:
-        // 1. Verify funds availability
-        // 2. Transfer funds between issuer and acquirer accounts
-        // 3. Update transaction statuses
-        // 4. Send confirmations
+        // A negative total flows from the acquirer back to the issuer
+        let ledger = processor.ledger_mut();
+        let currency = batch.total_amount.currency();
+        let issuer = ledger.account_id(LedgerAccountType::IssuerSettlement, None, currency);
+        let acquirer = ledger.account_id(LedgerAccountType::AcquirerSettlement, Some(batch.acquirer_id), currency);
+        let postings = if batch.total_amount.is_negative() {
+            let amount = Money::zero(currency).checked_sub(&batch.total_amount)?;
+            vec![Posting::debit(acquirer, amount), Posting::credit(issuer, amount)]
+        } else {
+            vec![Posting::debit(issuer, batch.total_amount), Posting::credit(acquirer, batch.total_amount)]
+        };
+        if !batch.total_amount.is_zero() && let Err(e) = ledger.post("Settlement", Some(batch_id), postings) {
+            batch.status = SettlementStatus::Failed;
+            self.repository.save(batch)?;
+            return Err(e);
+        }
+        // Every transaction was checked to be captured above, so this only fails if a
+        // transaction cannot be saved; the batch is then recorded as failed like above
+        for id in &batch.transactions {
+            if let Err(e) = processor.settle_transaction(*id) {
+                batch.status = SettlementStatus::Failed;
+                self.repository.save(batch)?;
+                return Err(e);
+            }
+        }
 
-        // For now, simulate successful settlement
         batch.status = SettlementStatus::Completed;
         batch.settled_at = Some(Utc::now());
-
-        Ok(())
+        self.repository.save(batch)
     }
 
     pub fn get_batch(&self, batch_id: &Uuid) -> Option<&SettlementBatch> {
         self.batches.get(batch_id)
     }
 
+    pub fn batches(&self) -> impl Iterator<Item = &SettlementBatch> {
+        self.batches.values()
+    }
+
     pub fn get_pending_batches(&self) -> Vec<&SettlementBatch> {
         self.batches.values()
             .filter(|b| b.status == SettlementStatus::Pending)
             .collect()
     }
 
-    pub fn calculate_net_settlement(&self, issuer_id: Uuid, acquirer_id: Uuid) -> f64 {
+    pub fn calculate_net_settlement(&self, issuer_id: Uuid, acquirer_id: Uuid, currency: Currency) -> Result<Money, EuropayError> {
         // Calculate net amount to be settled between issuer and acquirer
         self.batches.values()
             .filter(|b| b.issuer_id == issuer_id && b.acquirer_id == acquirer_id && b.status == SettlementStatus::Completed)
-            .map(|b| b.total_amount)
-            .sum()
+            .filter(|b| b.total_amount.currency() == currency)
+            .try_fold(Money::zero(currency), |total, b| total.checked_add(&b.total_amount))
+            .map_err(EuropayError::from)
+    }
+}
+
+impl Default for SettlementService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::transactions::fixtures;
+
+    #[test]
+    fn test_batch_settles_captured_purchases_net_of_refunds() {
+        let mut processor = fixtures::processor();
+        let fixtures::Cardholder { card_id, merchant_id, acquirer_id, .. } = fixtures::add_cardholder(&mut processor, Currency::EUR, "200.00");
+        let eur = |s: &str| Money::parse(s, Currency::EUR).unwrap();
+
+        let purchase = processor.authorize_transaction(card_id, merchant_id, eur("80.00")).unwrap();
+        processor.capture_transaction(purchase).unwrap();
+        let refund = processor.refund_transaction(purchase, eur("30.00")).unwrap();
+        let uncaptured = processor.authorize_transaction(card_id, merchant_id, eur("10.00")).unwrap();
+
+        let mut settlement = SettlementService::new();
+        let issuer_id = Uuid::new_v4();
+        assert!(settlement.create_batch(issuer_id, acquirer_id, &[purchase, uncaptured], &processor).is_err());
+        assert!(settlement.create_batch(issuer_id, Uuid::new_v4(), &[purchase], &processor).is_err());
+        let batch_id = settlement.create_batch(issuer_id, acquirer_id, &[purchase, refund], &processor).unwrap();
+        assert_eq!(settlement.get_batch(&batch_id).unwrap().total_amount, eur("50.00"));
+        assert!(settlement.create_batch(issuer_id, acquirer_id, &[purchase], &processor).is_err());
+
+        settlement.process_settlement(batch_id, &mut processor).unwrap();
+        assert_eq!(processor.get_transaction(purchase).unwrap().status, TransactionStatus::Settled);
+        assert_eq!(processor.get_transaction(refund).unwrap().status, TransactionStatus::Settled);
+        let ledger = processor.ledger();
+        // Only the deposit is left on the issuer's settlement account
+        assert_eq!(ledger.balance_of(LedgerAccountType::IssuerSettlement, None, Currency::EUR), eur("-200.00"));
+        assert!(ledger.balance_of(LedgerAccountType::AcquirerSettlement, Some(acquirer_id), Currency::EUR).is_zero());
+        ledger.verify().unwrap();
+
+        // A transaction reversed after it was batched fails the batch
+        let second = processor.authorize_transaction(card_id, merchant_id, eur("20.00")).unwrap();
+        processor.capture_transaction(second).unwrap();
+        let batch_id = settlement.create_batch(issuer_id, acquirer_id, &[second], &processor).unwrap();
+        processor.reverse_transaction(second).unwrap();
+        assert!(settlement.process_settlement(batch_id, &mut processor).is_err());
+        assert_eq!(settlement.get_batch(&batch_id).unwrap().status, SettlementStatus::Failed);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_batch_that_fails_to_settle_is_recorded_as_failed() {
+        let mut processor = fixtures::processor();
+        let fixtures::Cardholder { card_id, merchant_id, acquirer_id, .. } = fixtures::add_cardholder(&mut processor, Currency::EUR, "200.00");
+        let purchase = processor.authorize_transaction(card_id, merchant_id, Money::parse("40.00", Currency::EUR).unwrap()).unwrap();
+        processor.capture_transaction(purchase).unwrap();
+
+        let mut settlement = SettlementService::new();
+        let batch_id = settlement.create_batch(Uuid::new_v4(), acquirer_id, &[purchase], &processor).unwrap();
+        // Listed twice, the purchase passes the captured check but cannot be settled a second time
+        settlement.batches.get_mut(&batch_id).unwrap().transactions.push(purchase);
+
+        assert!(settlement.process_settlement(batch_id, &mut processor).is_err());
+        assert_eq!(settlement.get_batch(&batch_id).unwrap().status, SettlementStatus::Failed);
+        assert_eq!(settlement.repository.get(batch_id).unwrap().unwrap().status, SettlementStatus::Failed);
+    }
+}
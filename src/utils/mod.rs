@@ -1,15 +1,193 @@
 // Utils module
 
+use chrono::{DateTime, Datelike, Utc};
+use serde::Serialize;
 use uuid::Uuid;
 
+use crate::core::error::EuropayError;
+use crate::core::money::Money;
+
 pub fn generate_id() -> String {
     Uuid::new_v4().to_string()
 }
 
-pub fn validate_amount(amount: f64) -> Result<f64, String> {
-    if amount <= 0.0 {
-        Err("Amount must be positive".to_string())
+pub fn validate_amount(amount: Money) -> Result<Money, EuropayError> {
+    if !amount.is_positive() {
+        Err(EuropayError::InvalidAmount("Amount must be positive".to_string()))
     } else {
         Ok(amount)
     }
+}
+
+/// Trims a holder or merchant name and checks it is printable and at most `max_len` characters.
+pub fn validate_name(name: &str, max_len: usize) -> Result<String, EuropayError> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > max_len {
+        return Err(EuropayError::InvalidRequest(format!("Name must be between 1 and {} characters", max_len)));
+    }
+    if name.chars().any(char::is_control) {
+        return Err(EuropayError::InvalidRequest("Name contains control characters".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// The name embossed on a card and carried in track 1: up to 26 letters, spaces and `-.'/`.
+pub fn validate_cardholder_name(name: &str) -> Result<String, EuropayError> {
+    let name = validate_name(name, 26)?;
+    if !name.chars().all(|c| c.is_ascii_alphabetic() || " -.'/".contains(c)) {
+        return Err(EuropayError::InvalidRequest("Cardholder name may only contain letters, spaces and -.'/".to_string()));
+    }
+    Ok(name.to_ascii_uppercase())
+}
+
+/// 13 to 19 digits with a valid Luhn check digit.
+pub fn validate_pan(pan: &str) -> Result<(), EuropayError> {
+    if !(13..=19).contains(&pan.len()) || !pan.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(EuropayError::InvalidRequest("Card number must be 13 to 19 digits".to_string()));
+    }
+    // Double every second digit from the right, subtracting 9 from two-digit results
+    let sum: u32 = pan.bytes().rev().enumerate().map(|(i, b)| {
+        let digit = (b - b'0') as u32;
+        match (i % 2, digit * 2) {
+            (0, _) => digit,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        }
+    }).sum();
+    if !sum.is_multiple_of(10) {
+        return Err(EuropayError::InvalidRequest("Card number fails the Luhn check".to_string()));
+    }
+    Ok(())
+}
+
+pub fn validate_cvv(cvv: &str) -> Result<(), EuropayError> {
+    if !(3..=4).contains(&cvv.len()) || !cvv.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(EuropayError::InvalidRequest("CVV must be 3 or 4 digits".to_string()));
+    }
+    Ok(())
+}
+
+/// A card's expiry month must be valid, after the current month (cards lapse at the start of
+/// their expiry month) and no more than 20 years out.
+pub fn validate_expiry(month: u8, year: u16, now: DateTime<Utc>) -> Result<(), EuropayError> {
+    if !(1..=12).contains(&month) {
+        return Err(EuropayError::InvalidRequest("Expiry month must be 1 to 12".to_string()));
+    }
+    let current = (now.year(), now.month());
+    let expiry = (year as i32, month as u32);
+    if expiry <= current {
+        return Err(EuropayError::InvalidRequest("Expiry date is in the past".to_string()));
+    }
+    if expiry.0 > current.0 + 20 {
+        return Err(EuropayError::InvalidRequest("Expiry date is more than 20 years away".to_string()));
+    }
+    Ok(())
+}
+
+/// A merchant category code (ISO 18245) is four digits.
+pub fn validate_mcc(mcc: &str) -> Result<(), EuropayError> {
+    if mcc.len() != 4 || !mcc.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(EuropayError::InvalidRequest("Merchant category must be a four-digit code".to_string()));
+    }
+    Ok(())
+}
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+impl<T> Page<T> {
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next_cursor: self.next_cursor,
+        }
+    }
+}
+
+/// Pages `items` oldest first by `(created_at, id)`, starting after `cursor` (the previous
+/// page's `next_cursor`). The cursor encodes that position rather than an offset, so
+/// records added while paging do not shift later pages.
+pub fn paginate<T>(mut items: Vec<T>, key: impl Fn(&T) -> (DateTime<Utc>, Uuid), cursor: Option<&str>, limit: Option<usize>) -> Result<Page<T>, EuropayError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if limit == 0 || limit > MAX_PAGE_SIZE {
+        return Err(EuropayError::InvalidRequest(format!("limit must be between 1 and {}", MAX_PAGE_SIZE)));
+    }
+    items.sort_by_key(|item| key(item));
+    if let Some(cursor) = cursor {
+        let after = decode_cursor(cursor)?;
+        items.retain(|item| key(item) > after);
+    }
+    let next_cursor = if items.len() > limit {
+        items.truncate(limit);
+        items.last().map(|item| encode_cursor(key(item)))
+    } else {
+        None
+    };
+    Ok(Page { items, next_cursor })
+}
+
+fn encode_cursor((created_at, id): (DateTime<Utc>, Uuid)) -> String {
+    // Full precision, or the last item of a page would sort after its own cursor
+    let nanos = created_at.timestamp_nanos_opt().unwrap_or(i64::MAX);
+    format!("{}_{}", nanos, id.simple())
+}
+
+fn decode_cursor(cursor: &str) -> Result<(DateTime<Utc>, Uuid), EuropayError> {
+    let invalid = || EuropayError::InvalidRequest("Invalid cursor".to_string());
+    let (nanos, id) = cursor.split_once('_').ok_or_else(invalid)?;
+    let created_at = DateTime::from_timestamp_nanos(nanos.parse().map_err(|_| invalid())?);
+    Ok((created_at, Uuid::parse_str(id).map_err(|_| invalid())?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_pages_through_items_in_order() {
+        let start = DateTime::from_timestamp_nanos(1_700_000_000_000_000_123);
+        let items: Vec<(DateTime<Utc>, Uuid)> = (0..5).map(|i| (start + chrono::Duration::nanoseconds(i), Uuid::new_v4())).collect();
+        let mut reversed = items.clone();
+        reversed.reverse();
+
+        let first = paginate(reversed.clone(), |item| *item, None, Some(2)).unwrap();
+        assert_eq!(first.items, items[..2]);
+
+        let second = paginate(reversed.clone(), |item| *item, first.next_cursor.as_deref(), Some(2)).unwrap();
+        assert_eq!(second.items, items[2..4]);
+
+        let last = paginate(reversed, |item| *item, second.next_cursor.as_deref(), Some(2)).unwrap();
+        assert_eq!(last.items, items[4..]);
+        assert!(last.next_cursor.is_none());
+
+        assert!(matches!(paginate(items.clone(), |item| *item, Some("not-a-cursor"), None), Err(EuropayError::InvalidRequest(_))));
+        assert!(matches!(paginate(items, |item| *item, None, Some(0)), Err(EuropayError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn test_card_and_merchant_validation() {
+        assert!(validate_pan("4000000000000002").is_ok());
+        assert!(validate_pan("4000000000000003").is_err());
+        assert!(validate_pan("4000 0000 0000 0002").is_err());
+
+        let now = DateTime::parse_from_rfc3339("2026-10-18T12:00:00Z").unwrap().with_timezone(&Utc);
+        assert!(validate_expiry(11, 2026, now).is_ok());
+        assert!(validate_expiry(10, 2026, now).is_err());
+        assert!(validate_expiry(13, 2030, now).is_err());
+        assert!(validate_expiry(1, 2050, now).is_err());
+
+        assert_eq!(validate_cardholder_name("  Zoë Smith ").err().map(|e| e.code()), Some("invalid_request"));
+        assert_eq!(validate_cardholder_name(" o'brien/pat ").unwrap(), "O'BRIEN/PAT");
+        assert_eq!(validate_name("  Zoë Smith ", 140).unwrap(), "Zoë Smith");
+        assert!(validate_name("   ", 140).is_err());
+
+        assert!(validate_mcc("5814").is_ok());
+        assert!(validate_mcc("581").is_err());
+    }
 }
\ No newline at end of file